@@ -0,0 +1,283 @@
+//! Bundle Protocol socket addresses, for the `AF_BP` family of the BP kernel module.
+
+use socket2::SockAddr;
+use std::{
+    fmt,
+    io::{self, Error, ErrorKind},
+    mem::{self, ManuallyDrop},
+    num::{IntErrorKind, ParseIntError},
+    ptr,
+};
+
+use libc::c_int;
+
+use crate::{
+    endpoint::{Endpoint, EndpointProto},
+    error::SocketEngineError,
+};
+
+/// Default address family of the BP kernel module, see `BpConfig`.
+pub const AF_BP: c_int = 28;
+
+/// Address family and protocol number BP sockets are created with, which
+/// depend on how the BP kernel module registered itself. Set per engine with
+/// `EngineConfig::bp`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BpConfig {
+    pub family: c_int,
+    pub protocol: i32,
+}
+
+impl Default for BpConfig {
+    fn default() -> Self {
+        Self {
+            family: AF_BP,
+            protocol: 0,
+        }
+    }
+}
+
+const BP_SCHEME_IPN: u32 = 1;
+const BP_SCHEME_DTN: u32 = 2;
+
+/// Longest scheme-specific part of a `dtn:` EID, e.g. `//node/service`.
+pub const DTN_EID_MAX_LEN: usize = 112;
+
+#[repr(C)]
+pub struct SockAddrBp {
+    bp_family: libc::sa_family_t,
+    bp_scheme: u32,
+    bp_addr: BpAddr,
+}
+
+impl SockAddrBp {
+    /// Family and scheme, common to every BP address.
+    const HEADER_LEN: usize = mem::offset_of!(SockAddrBp, bp_addr);
+
+    /// Two-component `ipn:` addresses keep the original 16-byte layout, without
+    /// the allocator that follows the node and service numbers.
+    const IPN_LEGACY_LEN: usize = Self::HEADER_LEN + mem::offset_of!(IpnAddr, allocator_id);
+    const IPN_LEN: usize = Self::HEADER_LEN + mem::size_of::<IpnAddr>();
+    const DTN_LEN: usize = Self::HEADER_LEN + mem::size_of::<DtnAddr>();
+
+    fn encoded_len(&self) -> usize {
+        match self.bp_scheme {
+            BP_SCHEME_IPN if unsafe { self.bp_addr.ipn.allocator_id } == 0 => Self::IPN_LEGACY_LEN,
+            BP_SCHEME_IPN => Self::IPN_LEN,
+            _ => Self::DTN_LEN,
+        }
+    }
+}
+
+impl std::fmt::Display for SockAddrBp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bp_scheme {
+            BP_SCHEME_IPN => {
+                let ipn_addr = unsafe { &*self.bp_addr.ipn };
+                write!(f, "ipn:")?;
+                if ipn_addr.allocator_id != 0 {
+                    write!(f, "{}.", ipn_addr.allocator_id)?;
+                }
+                write!(f, "{}.{}", ipn_addr.node_id, ipn_addr.service_id)
+            }
+            BP_SCHEME_DTN => {
+                let dtn_addr = unsafe { &*self.bp_addr.dtn };
+                let len = (dtn_addr.eid_len as usize).min(DTN_EID_MAX_LEN);
+                write!(f, "dtn:{}", String::from_utf8_lossy(&dtn_addr.eid[..len]))
+            }
+            scheme => {
+                write!(f, "scheme {} unknown", scheme)
+            }
+        }
+    }
+}
+#[repr(C)]
+pub union BpAddr {
+    ipn: ManuallyDrop<IpnAddr>,
+    dtn: ManuallyDrop<DtnAddr>,
+}
+
+// The allocator comes last so that the legacy layout is a prefix of this one
+#[repr(C)]
+struct IpnAddr {
+    node_id: u32,
+    service_id: u32,
+    allocator_id: u32,
+}
+
+// Scheme-specific part of the EID, not NUL-terminated
+#[repr(C)]
+struct DtnAddr {
+    eid_len: u32,
+    eid: [u8; DTN_EID_MAX_LEN],
+}
+
+fn bp_sockaddr(sockaddr_bp: SockAddrBp) -> SockAddr {
+    let mut sockaddr_storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    unsafe {
+        ptr::copy_nonoverlapping(
+            &sockaddr_bp as *const SockAddrBp as *const u8,
+            &mut sockaddr_storage as *mut _ as *mut u8,
+            mem::size_of::<SockAddrBp>(),
+        );
+    }
+
+    let addr_len = sockaddr_bp.encoded_len() as libc::socklen_t;
+    unsafe { SockAddr::new(sockaddr_storage, addr_len) }
+}
+
+/// Decodes a BP socket address, such as the source of a received bundle, of
+/// the family of `config`.
+pub fn bp_sockaddr_to_endpoint(addr: &SockAddr, config: &BpConfig) -> io::Result<Endpoint> {
+    let invalid = |reason: String| Error::new(ErrorKind::InvalidData, reason);
+    let family = config.family;
+    if addr.family() as libc::c_int != family {
+        return Err(invalid(format!(
+            "Address family {} is not the BP family {}",
+            addr.family(),
+            family
+        )));
+    }
+    if (addr.len() as usize) < SockAddrBp::HEADER_LEN {
+        return Err(invalid(format!(
+            "BP address is {} bytes long, too short for a BP address",
+            addr.len()
+        )));
+    }
+    // Family checked, the storage behind a `SockAddr` is large enough for any
+    // `SockAddrBp` and the length is checked against the scheme before use
+    let mut bp_addr = unsafe { ptr::read(addr.as_ptr() as *const SockAddrBp) };
+    let expected: &[usize] = match bp_addr.bp_scheme {
+        BP_SCHEME_IPN => &[SockAddrBp::IPN_LEGACY_LEN, SockAddrBp::IPN_LEN],
+        BP_SCHEME_DTN => &[SockAddrBp::DTN_LEN],
+        scheme => return Err(invalid(format!("Unknown BP scheme {}", scheme))),
+    };
+    if !expected.contains(&(addr.len() as usize)) {
+        return Err(invalid(format!(
+            "BP address is {} bytes long, expected {:?}",
+            addr.len(),
+            expected
+        )));
+    }
+    if addr.len() as usize == SockAddrBp::IPN_LEGACY_LEN {
+        // Past the end of a legacy address, not set by the sender
+        unsafe { (*bp_addr.bp_addr.ipn).allocator_id = 0 };
+    }
+    Ok(Endpoint {
+        proto: EndpointProto::Bp,
+        endpoint: bp_addr.to_string(),
+    })
+}
+
+// Components are numbers from 0 to `u32::MAX`, the range of the kernel's `ipn` address
+fn parse_ipn_number(
+    component: &str,
+    value: &str,
+    endpoint_string: &str,
+) -> Result<u32, SocketEngineError> {
+    value.parse().map_err(|e: ParseIntError| {
+        let reason = match e.kind() {
+            IntErrorKind::Empty => "it is empty".to_string(),
+            IntErrorKind::PosOverflow => format!("it is larger than {}", u32::MAX),
+            _ => "it is not a number".to_string(),
+        };
+        SocketEngineError::AddrParse(format!(
+            "Invalid {} number `{}` in {}: {}",
+            component, value, endpoint_string, reason
+        ))
+    })
+}
+
+/// Encodes an `ipn:` or `dtn:` EID as a BP socket address of the family of `config`.
+pub fn create_bp_sockaddr_with_string(
+    endpoint_string: &str,
+    config: &BpConfig,
+) -> Result<SockAddr, SocketEngineError> {
+    if endpoint_string.is_empty() {
+        return Err(SocketEngineError::AddrParse(
+            "Endpoint string cannot be empty".to_string(),
+        ));
+    }
+
+    // ---- Handle "ipn:" scheme, as in ipn:node.service or ipn:allocator.node.service ----
+    if let Some(endpoint_body) = endpoint_string.strip_prefix("ipn:") {
+        let parts: Vec<&str> = endpoint_body.split('.').collect();
+        // The legacy two-component form has the default allocator 0
+        let (allocator, parts) = match parts.as_slice() {
+            [_, _] => ("0", &parts[..]),
+            [allocator, rest @ ..] if rest.len() == 2 => (*allocator, rest),
+            _ => {
+                return Err(SocketEngineError::AddrParse(format!(
+                    "Invalid IPN endpoint format: {}",
+                    endpoint_string
+                )))
+            }
+        };
+
+        let node_id = parse_ipn_number("node", parts[0], endpoint_string)?;
+        let service_id = parse_ipn_number("service", parts[1], endpoint_string)?;
+        let allocator_id = parse_ipn_number("allocator", allocator, endpoint_string)?;
+        if node_id == 0 {
+            // ipn:0.0 is the null endpoint, which cannot be bound nor sent to
+            return Err(SocketEngineError::AddrParse(format!(
+                "Invalid node number `0` in {}: node 0 is reserved",
+                endpoint_string
+            )));
+        }
+
+        Ok(bp_sockaddr(SockAddrBp {
+            bp_family: config.family as libc::sa_family_t,
+            bp_scheme: BP_SCHEME_IPN,
+            bp_addr: BpAddr {
+                ipn: ManuallyDrop::new(IpnAddr {
+                    node_id,
+                    service_id,
+                    allocator_id,
+                }),
+            },
+        }))
+    }
+    // ---- Handle "dtn:" scheme, as in dtn://node/service ----
+    else if let Some(endpoint_body) = endpoint_string.strip_prefix("dtn:") {
+        let node_and_demux = endpoint_body.strip_prefix("//").ok_or_else(|| {
+            SocketEngineError::AddrParse(format!(
+                "Invalid DTN endpoint format: {}",
+                endpoint_string
+            ))
+        })?;
+        if node_and_demux
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .is_empty()
+        {
+            return Err(SocketEngineError::AddrParse(format!(
+                "Missing node name in DTN endpoint: {}",
+                endpoint_string
+            )));
+        }
+        if endpoint_body.len() > DTN_EID_MAX_LEN {
+            return Err(SocketEngineError::AddrParse(format!(
+                "DTN endpoint {} is longer than {} bytes",
+                endpoint_string, DTN_EID_MAX_LEN
+            )));
+        }
+
+        let mut eid = [0; DTN_EID_MAX_LEN];
+        eid[..endpoint_body.len()].copy_from_slice(endpoint_body.as_bytes());
+        Ok(bp_sockaddr(SockAddrBp {
+            bp_family: config.family as libc::sa_family_t,
+            bp_scheme: BP_SCHEME_DTN,
+            bp_addr: BpAddr {
+                dtn: ManuallyDrop::new(DtnAddr {
+                    eid_len: endpoint_body.len() as u32,
+                    eid,
+                }),
+            },
+        }))
+    } else {
+        Err(SocketEngineError::UnsupportedScheme(
+            endpoint_string.to_string(),
+        ))
+    }
+}
@@ -0,0 +1,204 @@
+//! Buffer sizes, timeouts, intervals and send queue of an engine, see
+//! `Engine::with_config`.
+
+use std::time::Duration;
+
+#[cfg(feature = "bp")]
+use crate::bp::BpConfig;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use crate::{
+    engine::{
+        DEFAULT_CONNECT_TIMEOUT, DEFAULT_HEARTBEAT_MAX_MISSED, DEFAULT_HEARTBEAT_TIMEOUT,
+        DEFAULT_SEND_QUEUE_CAPACITY, DEFAULT_SEND_WORKERS,
+    },
+    socket::{
+        DEFAULT_POLL_INTERVAL, DEFAULT_TCP_BACKLOG, DEFAULT_TCP_BUFFER_SIZE,
+        DEFAULT_UDP_BUFFER_SIZE,
+    },
+};
+
+/// Settings an engine is built with. Each one defaults to the constant named
+/// in its setter:
+///
+/// ```
+/// use std::time::Duration;
+/// use socket_engine::prelude::*;
+///
+/// let engine = Engine::with_config(
+///     EngineConfig::default()
+///         .tcp_buffer_size(64 * 1024)
+///         .connect_timeout(Duration::from_secs(2)),
+/// );
+/// # drop(engine);
+/// ```
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+    pub(crate) tcp_buffer_size: usize,
+    pub(crate) udp_buffer_size: usize,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) poll_interval: Duration,
+    pub(crate) tcp_backlog: i32,
+    pub(crate) send_queue_capacity: usize,
+    pub(crate) send_workers: usize,
+    pub(crate) heartbeat_interval: Option<Duration>,
+    pub(crate) heartbeat_timeout: Duration,
+    pub(crate) heartbeat_max_missed: u32,
+    #[cfg(feature = "bp")]
+    pub(crate) bp: BpConfig,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: TlsConfig,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            tcp_buffer_size: DEFAULT_TCP_BUFFER_SIZE,
+            udp_buffer_size: DEFAULT_UDP_BUFFER_SIZE,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            tcp_backlog: DEFAULT_TCP_BACKLOG,
+            send_queue_capacity: DEFAULT_SEND_QUEUE_CAPACITY,
+            send_workers: DEFAULT_SEND_WORKERS,
+            heartbeat_interval: None,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            heartbeat_max_missed: DEFAULT_HEARTBEAT_MAX_MISSED,
+            #[cfg(feature = "bp")]
+            bp: BpConfig::default(),
+            #[cfg(feature = "tls")]
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Sets how many bytes a TCP listener, or a reader of replies, reads from a
+    /// connection at a time (default: `DEFAULT_TCP_BUFFER_SIZE`). Larger buffers
+    /// take fewer reads for big messages, at the cost of `size` bytes per open
+    /// connection.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is 0.
+    pub fn tcp_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "The TCP buffer size must not be 0");
+        self.tcp_buffer_size = size;
+        self
+    }
+
+    /// Sets the receive buffer of UDP and BP listeners (default:
+    /// `DEFAULT_UDP_BUFFER_SIZE`, the largest UDP payload). Longer datagrams
+    /// are truncated to it.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is 0.
+    pub fn udp_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "The UDP buffer size must not be 0");
+        self.udp_buffer_size = size;
+        self
+    }
+
+    /// How long a TCP send waits for each connect attempt before failing with
+    /// `ConnectionFailed` and the `Timeout` reason (default:
+    /// `DEFAULT_CONNECT_TIMEOUT`). `SendOptions::connect_timeout` overrides it
+    /// for one send.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets how often an idle listener wakes up to check whether it should stop
+    /// (default: `DEFAULT_POLL_INTERVAL`). It bounds the time a listener takes
+    /// to notice `stop_listener` or its duration limit, while shorter intervals
+    /// cost more wake-ups. Received data is handled as soon as it arrives.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets how many connections not yet accepted a TCP listener queues
+    /// (default: `DEFAULT_TCP_BACKLOG`), capped by the system's `somaxconn`.
+    pub fn tcp_backlog(mut self, backlog: i32) -> Self {
+        self.tcp_backlog = backlog;
+        self
+    }
+
+    /// Sets how many sends wait for a send worker at most (default:
+    /// `DEFAULT_SEND_QUEUE_CAPACITY`). Beyond it `Engine::send` fails with
+    /// `SocketEngineError::QueueFull` so callers can slow down, while
+    /// `send_blocking` waits for room.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn send_queue_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "The send queue capacity must not be 0");
+        self.send_queue_capacity = capacity;
+        self
+    }
+
+    /// Sets how many sends run at a time (default: `DEFAULT_SEND_WORKERS`),
+    /// from their `Sending` event until their outcome and, for TCP, until the
+    /// connection is pooled or closed. Each worker is a task on the engine's
+    /// runtime, started by the first send.
+    ///
+    /// # Panics
+    ///
+    /// If `workers` is 0.
+    pub fn send_workers(mut self, workers: usize) -> Self {
+        assert!(workers > 0, "The engine needs at least one send worker");
+        self.send_workers = workers;
+        self
+    }
+
+    /// Probes outgoing TCP connections kept open between sends (see
+    /// `Engine::with_close_after_send`) once no send used them for `interval`,
+    /// so that NATs and firewalls keep them open and dead peers are noticed.
+    /// Probes are small messages that engine listeners answer without
+    /// delivering them, so the peer must be an engine. Default: no heartbeat.
+    ///
+    /// Without length-prefix framing, a probe may reach the peer glued to the
+    /// next payload and only a leading probe is recognized; enable framing on
+    /// both sides for heavy traffic.
+    pub fn heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// How long a heartbeat probe waits for its answer before it counts as
+    /// missed and the next one is sent (default: `DEFAULT_HEARTBEAT_TIMEOUT`).
+    pub fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Drops a connection, emitting `Closed`, once `max_missed` heartbeat
+    /// probes in a row went unanswered (default: `DEFAULT_HEARTBEAT_MAX_MISSED`).
+    ///
+    /// # Panics
+    ///
+    /// If `max_missed` is 0.
+    pub fn heartbeat_max_missed(mut self, max_missed: u32) -> Self {
+        assert!(max_missed > 0, "At least one heartbeat must be missed");
+        self.heartbeat_max_missed = max_missed;
+        self
+    }
+
+    /// Sets the address family and protocol BP sockets are created with
+    /// (default: `AF_BP` and protocol 0), for a BP kernel module registered
+    /// differently. Engines of one process may use different ones.
+    #[cfg(feature = "bp")]
+    pub fn bp(mut self, config: BpConfig) -> Self {
+        self.bp = config;
+        self
+    }
+
+    /// Encrypts the TCP transport with the certificates of `config` (default:
+    /// plaintext). `Engine::reload_tls` replaces them on a running engine.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: TlsConfig) -> Self {
+        self.tls = config;
+        self
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    endpoint::{Endpoint, EndpointProto},
+    event::{notify_all_observers, DataEvent, EngineObserver, SocketEngineEvent},
+    framing::{encode_frame, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN},
+    socket::{resolve_socket_addrs, retry_on_eintr},
+};
+
+/// Prefix marking a payload as an echo probe. Listeners with an echo responder
+/// send such payloads back to their source instead of delivering them.
+pub const ECHO_MAGIC: &[u8] = b"\xE0SE-ECHO";
+/// How long a probe waits for its echo before being counted as lost.
+pub const ECHO_REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub fn is_echo_probe(data: &[u8]) -> bool {
+    data.starts_with(ECHO_MAGIC)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PingReport {
+    pub sent: u32,
+    pub received: u32,
+    pub min: Option<Duration>,
+    pub avg: Option<Duration>,
+    pub max: Option<Duration>,
+    pub p95: Option<Duration>,
+}
+
+impl PingReport {
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        1.0 - self.received as f64 / self.sent as f64
+    }
+
+    fn from_rtts(sent: u32, mut rtts: Vec<Duration>) -> Self {
+        rtts.sort();
+        let received = rtts.len() as u32;
+        let avg = (received > 0).then(|| rtts.iter().sum::<Duration>() / received);
+        let p95 = rtts
+            .get(((rtts.len() * 95).div_ceil(100)).saturating_sub(1))
+            .copied();
+        Self {
+            sent,
+            received,
+            min: rtts.first().copied(),
+            avg,
+            max: rtts.last().copied(),
+            p95,
+        }
+    }
+}
+
+fn probe(seq: u32, size: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(size.max(ECHO_MAGIC.len() + 4));
+    data.extend_from_slice(ECHO_MAGIC);
+    data.extend_from_slice(&seq.to_be_bytes());
+    data.resize(size.max(data.len()), 0);
+    data
+}
+
+// One connected probe channel per ping run
+enum Prober {
+    Datagram(UdpSocket),
+    Stream {
+        stream: TcpStream,
+        addr: SocketAddr,
+        framed: bool,
+    },
+}
+
+fn connect_stream(addr: &SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(addr, ECHO_REPLY_TIMEOUT)?;
+    stream.set_read_timeout(Some(ECHO_REPLY_TIMEOUT))?;
+    Ok(stream)
+}
+
+// Reads the next echo of a probe of `len` bytes from a TCP stream
+fn read_echo(stream: &mut TcpStream, framed: bool, len: usize) -> io::Result<Vec<u8>> {
+    let len = if framed {
+        let mut header = [0; FRAME_HEADER_LEN];
+        stream.read_exact(&mut header)?;
+        let declared = u32::from_be_bytes(header) as usize;
+        if declared > DEFAULT_MAX_FRAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Oversized frame in reply to an echo probe",
+            ));
+        }
+        declared
+    } else {
+        len
+    };
+    let mut echo = vec![0; len];
+    stream.read_exact(&mut echo)?;
+    Ok(echo)
+}
+
+impl Prober {
+    fn connect(target: &Endpoint, framed: bool) -> io::Result<Self> {
+        let addr = Some(target)
+            .filter(|target| !target.proto.is_bp())
+            .and_then(|target| resolve_socket_addrs(&target.endpoint).ok())
+            .map(|addrs| addrs[0])
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Cannot ping {}: not an IP endpoint", target),
+                )
+            })?;
+        match target.proto {
+            EndpointProto::Udp => {
+                let bind_addr = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(bind_addr)?;
+                socket.connect(addr)?;
+                socket.set_read_timeout(Some(ECHO_REPLY_TIMEOUT))?;
+                Ok(Prober::Datagram(socket))
+            }
+            EndpointProto::Tcp => Ok(Prober::Stream {
+                stream: connect_stream(&addr)?,
+                addr,
+                framed,
+            }),
+            #[cfg(feature = "bp")]
+            EndpointProto::Bp => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Ping is not supported over BP",
+            )),
+        }
+    }
+
+    // Sends a probe and waits for its echo, `None` if it was lost
+    fn round_trip(&mut self, data: &[u8]) -> io::Result<Option<Duration>> {
+        let start = Instant::now();
+        let reply = match self {
+            Prober::Datagram(socket) => {
+                retry_on_eintr(|| socket.send(data))?;
+                let mut buffer = vec![0; data.len()];
+                loop {
+                    match retry_on_eintr(|| socket.recv(&mut buffer)) {
+                        // Late echoes of earlier probes are skipped
+                        Ok(size) if buffer[..size] == *data => break Ok(()),
+                        Ok(_) => continue,
+                        Err(e) => break Err(e),
+                    }
+                }
+            }
+            Prober::Stream { stream, framed, .. } => {
+                if *framed {
+                    let frame = encode_frame(data, DEFAULT_MAX_FRAME_SIZE)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                    stream.write_all(&frame)?;
+                } else {
+                    stream.write_all(data)?;
+                }
+                let deadline = start + ECHO_REPLY_TIMEOUT;
+                loop {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break Err(io::ErrorKind::TimedOut.into());
+                    }
+                    stream.set_read_timeout(Some(remaining))?;
+                    match read_echo(stream, *framed, data.len()) {
+                        Ok(echo) if echo == data => break Ok(()),
+                        // Late echoes of earlier probes come first on the stream
+                        Ok(echo) if is_echo_probe(&echo) => continue,
+                        Ok(_) => {
+                            break Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "Unexpected reply to an echo probe",
+                            ))
+                        }
+                        Err(e) => break Err(e),
+                    }
+                }
+            }
+        };
+        match reply {
+            Ok(()) => Ok(Some(start.elapsed())),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::InvalidData
+                ) =>
+            {
+                // A stream may be left in the middle of an echo, which would be
+                // misread as the reply to the next probe
+                if let Prober::Stream { stream, addr, .. } = self {
+                    *stream = connect_stream(addr)?;
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub(crate) fn run_ping(
+    observers: &Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
+    target: Endpoint,
+    size: usize,
+    count: u32,
+    interval: Duration,
+    framed: bool,
+) -> io::Result<PingReport> {
+    let mut prober = Prober::connect(&target, framed)?;
+    let mut rtts = Vec::with_capacity(count as usize);
+    for seq in 0..count {
+        if seq > 0 {
+            std::thread::sleep(interval);
+        }
+        let rtt = prober.round_trip(&probe(seq, size))?;
+        notify_all_observers(
+            observers,
+            &SocketEngineEvent::Data(DataEvent::EchoReply {
+                to: target.clone(),
+                seq,
+                rtt,
+            }),
+        );
+        rtts.extend(rtt);
+    }
+    Ok(PingReport::from_rtts(count, rtts))
+}
@@ -1,173 +1,199 @@
-use socket2::SockAddr;
 use std::{
     fmt,
-    io::{self, Error, ErrorKind},
-    mem::{self, ManuallyDrop},
-    ptr,
+    net::{Ipv6Addr, SocketAddr},
+    str::FromStr,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EndpointProto {
     Udp,
     Tcp,
+    /// Bundle Protocol, through the `AF_BP` kernel module. Requires the `bp` feature.
+    #[cfg(feature = "bp")]
     Bp,
 }
+
 impl EndpointProto {
-    pub fn to_string(&self) -> String {
-        match self {
-            EndpointProto::Udp => format!("udp").to_string(),
-            EndpointProto::Tcp => format!("tcp").to_string(),
-            EndpointProto::Bp => format!("bp").to_string(),
-        }
+    /// Always false without the `bp` feature.
+    pub fn is_bp(&self) -> bool {
+        #[cfg(feature = "bp")]
+        return *self == EndpointProto::Bp;
+        #[cfg(not(feature = "bp"))]
+        false
     }
 }
 
 impl fmt::Display for EndpointProto {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        match self {
+            EndpointProto::Udp => write!(f, "udp"),
+            EndpointProto::Tcp => write!(f, "tcp"),
+            #[cfg(feature = "bp")]
+            EndpointProto::Bp => write!(f, "bp"),
+        }
     }
 }
 
-use crate::socket::AF_BP;
+use crate::error::SocketEngineError;
+
+#[cfg(feature = "bp")]
+use crate::bp::{create_bp_sockaddr_with_string, BpConfig};
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Endpoint {
     pub proto: EndpointProto,
     pub endpoint: String,
 }
 
 impl Endpoint {
-    pub fn from_str(input: &str) -> Result<Self, String> {
-        // Split into scheme and addr parts
-        let mut parts = input.splitn(2, ' ');
-        let scheme = parts.next().ok_or("Missing scheme")?;
-        let addr = parts.next().ok_or("Missing address")?;
-
-        match scheme.to_lowercase().as_str() {
-            "bp" => Ok(Endpoint {
-                proto: EndpointProto::Bp,
-                endpoint: addr.to_string(),
-            }),
-            "tcp" => Ok(Endpoint {
-                proto: EndpointProto::Tcp,
-                endpoint: addr.to_string(),
-            }),
-            "udp" => Ok(Endpoint {
-                proto: EndpointProto::Udp,
-                endpoint: addr.to_string(),
-            }),
-            _ => Err(format!("Unsupported scheme: {}", scheme)),
+    /// Checks the address as a socket would: `host:port` for UDP and TCP, a
+    /// well-formed `ipn:` or `dtn:` EID for BP. The engine calls it before
+    /// creating sockets, so that invalid endpoints fail at the call.
+    pub fn validate(&self) -> Result<(), SocketEngineError> {
+        match self.proto {
+            #[cfg(feature = "bp")]
+            EndpointProto::Bp => {
+                create_bp_sockaddr_with_string(&self.endpoint, &BpConfig::default()).map(drop)
+            }
+            EndpointProto::Tcp | EndpointProto::Udp => {
+                validate_host_port(&self.endpoint).map_err(|reason| {
+                    SocketEngineError::Endpoint(EndpointParseError::InvalidAddress {
+                        address: self.endpoint.clone(),
+                        reason: reason.to_string(),
+                    })
+                })
+            }
         }
     }
-    pub fn to_string(&self) -> String {
-        format!("{} {}", self.proto.to_string(), self.endpoint)
-    }
-}
 
-impl fmt::Display for Endpoint {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+    #[allow(clippy::should_implement_trait)]
+    #[deprecated(note = "use `str::parse`, `Endpoint` implements `FromStr`")]
+    pub fn from_str(input: &str) -> Result<Self, String> {
+        input.parse().map_err(|e: EndpointParseError| e.to_string())
     }
 }
 
-const BP_SCHEME_IPN: u32 = 1;
-// const BP_SCHEME_DTN: u32 = 2;
-
-#[repr(C)]
-pub struct SockAddrBp {
-    bp_family: libc::sa_family_t,
-    bp_scheme: u32,
-    bp_addr: BpAddr,
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EndpointParseError {
+    /// No space or `://` separating the scheme from the address.
+    MissingAddress,
+    UnsupportedScheme(String),
+    /// A `bp` endpoint in a build without the `bp` feature.
+    BpNotCompiled,
+    /// Whitespace in a `scheme://` address, as in `udp://udp 1.2.3.4`.
+    MalformedAddress(String),
+    /// A UDP or TCP address that is not `host:port`.
+    InvalidAddress {
+        address: String,
+        reason: String,
+    },
 }
 
-impl std::fmt::Display for SockAddrBp {
+impl fmt::Display for EndpointParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let sch = if self.bp_scheme == BP_SCHEME_IPN {
-            "ipn"
-        } else {
-            "??"
-        };
-        match self.bp_scheme {
-            BP_SCHEME_IPN => {
-                let ipn_addr = unsafe { &*self.bp_addr.ipn };
-                write!(f, "{}:{}.{}", sch, ipn_addr.node_id, ipn_addr.service_id)
+        match self {
+            EndpointParseError::MissingAddress => write!(f, "Missing address"),
+            EndpointParseError::UnsupportedScheme(scheme) => {
+                write!(f, "Unsupported scheme: {}", scheme)
             }
-            _ => {
-                write!(f, "scheme {} unknown", sch)
+            EndpointParseError::BpNotCompiled => write!(
+                f,
+                "BP support not compiled in, rebuild with the `bp` feature"
+            ),
+            EndpointParseError::InvalidAddress { address, reason } => {
+                write!(f, "Invalid address `{}`: {}", address, reason)
+            }
+            EndpointParseError::MalformedAddress(addr) => {
+                write!(
+                    f,
+                    "Malformed address `{}`: it must not contain spaces",
+                    addr
+                )
             }
         }
     }
 }
-#[repr(C)]
-pub union BpAddr {
-    ipn: ManuallyDrop<IpnAddr>,
-    // Extend with other schemes like DTN if needed
-}
 
-#[repr(C)]
-struct IpnAddr {
-    node_id: u32,
-    service_id: u32,
-}
-
-pub fn create_bp_sockaddr_with_string(endpoint_string: &str) -> io::Result<SockAddr> {
-    if endpoint_string.is_empty() {
-        return Err(Error::new(
-            ErrorKind::InvalidInput,
-            "Endpoint string cannot be empty",
-        ));
-    }
-
-    // ---- Handle "ipn:" scheme ----
-    if let Some(endpoint_body) = endpoint_string.strip_prefix("ipn:") {
-        let parts: Vec<&str> = endpoint_body.split('.').collect();
-        if parts.len() != 2 {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                format!("Invalid IPN endpoint format: {}", endpoint_string),
-            ));
-        }
-
-        let node_id: u32 = parts[0]
-            .parse()
-            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid node ID"))?;
-        let service_id: u32 = parts[1]
-            .parse()
-            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid service ID"))?;
-
-        let sockaddr_bp = SockAddrBp {
-            bp_family: AF_BP as libc::sa_family_t,
-            bp_scheme: BP_SCHEME_IPN,
-            bp_addr: BpAddr {
-                ipn: ManuallyDrop::new(IpnAddr {
-                    node_id,
-                    service_id,
-                }),
-            },
+impl std::error::Error for EndpointParseError {}
+
+/// Parses `<scheme> <address>` or `<scheme>://<address>`, e.g. `udp 127.0.0.1:8888`
+/// or `bp://ipn:1.2`. Both forms give the same endpoint, displayed in the first one.
+impl FromStr for Endpoint {
+    type Err = EndpointParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (scheme, addr) = match input.split_once("://") {
+            Some((scheme, addr)) if !scheme.contains(char::is_whitespace) => {
+                if addr.is_empty() {
+                    return Err(EndpointParseError::MissingAddress);
+                }
+                if addr.contains(char::is_whitespace) {
+                    return Err(EndpointParseError::MalformedAddress(addr.to_string()));
+                }
+                (scheme, addr)
+            }
+            _ => input
+                .split_once(' ')
+                .ok_or(EndpointParseError::MissingAddress)?,
         };
 
-        let mut sockaddr_storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
-        unsafe {
-            ptr::copy_nonoverlapping(
-                &sockaddr_bp as *const SockAddrBp as *const u8,
-                &mut sockaddr_storage as *mut _ as *mut u8,
-                mem::size_of::<SockAddrBp>(),
-            );
+        let proto = match scheme.to_lowercase().as_str() {
+            #[cfg(feature = "bp")]
+            "bp" => EndpointProto::Bp,
+            #[cfg(not(feature = "bp"))]
+            "bp" => return Err(EndpointParseError::BpNotCompiled),
+            "tcp" => EndpointProto::Tcp,
+            "udp" => EndpointProto::Udp,
+            _ => return Err(EndpointParseError::UnsupportedScheme(scheme.to_string())),
+        };
+        // BP EIDs are checked when the socket address is built
+        if !proto.is_bp() {
+            validate_host_port(addr).map_err(|reason| EndpointParseError::InvalidAddress {
+                address: addr.to_string(),
+                reason: reason.to_string(),
+            })?;
         }
+        Ok(Endpoint {
+            proto,
+            endpoint: addr.to_string(),
+        })
+    }
+}
 
-        let addr_len = mem::size_of::<SockAddrBp>() as libc::socklen_t;
-        let address = unsafe { SockAddr::new(sockaddr_storage, addr_len) };
-        Ok(address)
+// Syntax check only, host names are resolved when a socket is created
+fn validate_host_port(addr: &str) -> Result<(), &'static str> {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return Ok(());
     }
-    // ---- Handle unsupported or unimplemented schemes ----
-    else if endpoint_string.starts_with("dtn:") {
-        Err(Error::new(
-            ErrorKind::Unsupported,
-            "DTN scheme not yet implemented",
-        ))
-    } else {
-        Err(Error::new(
-            ErrorKind::InvalidInput,
-            format!("Unsupported scheme in endpoint: {}", endpoint_string),
-        ))
+    let (host, port) = addr.rsplit_once(':').ok_or("missing `:port`")?;
+    port.parse::<u16>()
+        .map_err(|_| "the port must be a number between 0 and 65535")?;
+    if host.is_empty() {
+        return Err("missing host");
+    }
+    if let Some(ipv6) = host.strip_prefix('[') {
+        return match ipv6.strip_suffix(']').map(str::parse::<Ipv6Addr>) {
+            Some(Ok(_)) => Ok(()),
+            _ => Err("invalid IPv6 address"),
+        };
+    }
+    if host.contains(':') {
+        return Err("IPv6 addresses must be enclosed in brackets, e.g. `[::1]:8080`");
+    }
+    if !host
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+    {
+        return Err("the host is neither an IP address nor a host name");
+    }
+    Ok(())
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.proto, self.endpoint)
     }
 }
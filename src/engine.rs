@@ -1,266 +1,1772 @@
+#[cfg(feature = "tls")]
+use crate::tls::{SharedTls, TlsConfig};
 use crate::{
+    config::EngineConfig,
+    echo::{run_ping, PingReport},
     endpoint::{Endpoint, EndpointProto},
+    error::SocketEngineError,
     event::{
-        notify_all_observers, ConnectionEvent, ConnectionFailureReason, DataEvent, EngineObserver,
-        ErrorEvent, SocketEngineEvent,
+        notify_all_observers, notify_received, AsyncEngineObserver, ConnectionEvent, ConnectionId,
+        DataEvent, EngineObserver, ErrorEvent, EventMask, MessageId, MisuseKind, ObserverId,
+        Observers, SharedObserver, SocketEngineEvent,
     },
-    socket::{endpoint_to_sockaddr, GenericSocket},
+    framing::{encode_frame, Framing, DEFAULT_MAX_FRAME_SIZE},
+    pairing::{run_pairing, PairingError, PAIR_ALIAS},
+    peer_state::{PeerState, PeerStateObserver, PeerStateThresholds, PeerStateTracker},
+    poll::{EventEnvelope, PollQueue, PollQueueObserver},
+    pool::{self, ConnectionPool, Heartbeat, PoolSettings},
+    rate_limit::{RateLimit, RateLimits, TokenBucket},
+    request::{ResponseMatcher, ResponseObserver},
+    retry::RetryPolicy,
+    runtime::{thread_budget, LISTENER_RUNTIME, TOKIO_RUNTIME},
+    sender::{SendQueue, SendSettings, SendTask},
+    socket::{
+        endpoint_to_sockaddrs, AdoptedSocket, GenericSocket, ListenerHandle, ListenerLimits,
+        ListenerOptions, ListenerStatus, MulticastGroup, QueuedReply, ReplyQueues,
+    },
+    stats::{EndpointStats, StatsObserver, TrafficStats},
 };
 
-use once_cell::sync::Lazy;
 use std::{
-    collections::HashMap,
-    io::Write,
-    sync::{Arc, Mutex},
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, oneshot, watch, Notify},
 };
-use tokio::runtime::Runtime;
 
-pub static TOKIO_RUNTIME: Lazy<Runtime> =
-    Lazy::new(|| Runtime::new().expect("Failed to create Tokio runtime"));
+/// Deadline for each TCP connect attempt of a send, see `EngineConfig::connect_timeout`.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends waiting for a send worker beyond which `Engine::send` fails with
+/// `QueueFull`, see `EngineConfig::send_queue_capacity`.
+pub const DEFAULT_SEND_QUEUE_CAPACITY: usize = 1024;
+
+/// Sends an engine runs at a time, see `EngineConfig::send_workers`.
+pub const DEFAULT_SEND_WORKERS: usize = 64;
+
+/// How long a heartbeat probe waits for its answer, see
+/// `EngineConfig::heartbeat_timeout`.
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Unanswered heartbeat probes in a row after which a pooled connection is
+/// dropped, see `EngineConfig::heartbeat_max_missed`.
+pub const DEFAULT_HEARTBEAT_MAX_MISSED: u32 = 3;
+
+/// Connections a TCP listener handles at a time, see `Engine::with_max_connections`.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+
+/// How long an outgoing TCP connection stays open without sends, see
+/// `Engine::with_idle_timeout`.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+// How long dropping the engine waits for its listeners beyond their poll interval
+const LISTENER_EXIT_GRACE: Duration = Duration::from_secs(1);
+
+/// Outgoing TCP connections kept open between sends, see
+/// `Engine::with_max_pooled_connections`.
+pub const DEFAULT_MAX_POOLED_CONNECTIONS: usize = 64;
+
+static NEXT_SEND_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+fn next_send_token() -> MessageId {
+    MessageId::from(format!(
+        "send-{}",
+        NEXT_SEND_TOKEN.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Per-send settings handed to `Engine::send`. The default sends from no
+/// particular source, under a token generated by the engine.
+#[derive(Clone, Debug, Default)]
+pub struct SendOptions {
+    source: Option<Endpoint>,
+    token: Option<MessageId>,
+    connect_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl SendOptions {
+    /// Endpoint the data leaves from: one of the engine's UDP listeners, or a BP
+    /// EID (see `Engine::with_bp_identity`). Ignored for TCP.
+    pub fn source(mut self, source: Endpoint) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Token identifying this send in `Sending`, `Sent` and error events.
+    pub fn token(mut self, token: impl Into<MessageId>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Overrides `EngineConfig::connect_timeout` for this send.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Overrides `Engine::with_retry_policy` for this send, e.g. to keep retrying
+    /// a message for a peer that is often unreachable for a while.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+}
+
+/// Final result of one send, as reported by `SendHandle::outcome`.
+#[derive(Clone, Debug)]
+pub enum SendOutcome {
+    /// The payload was handed to the socket, `bytes` excludes framing overhead.
+    Sent {
+        bytes: usize,
+    },
+    Failed {
+        error: SocketEngineError,
+    },
+}
+
+/// A send whose outcome is not known yet, see `Engine::pending_sends`.
+#[derive(Clone, Debug)]
+pub struct SendState {
+    pub token: MessageId,
+    pub to: Endpoint,
+    /// Payload size, framing excluded.
+    pub bytes: usize,
+    pub started_at: Instant,
+    /// Current attempt, from 1: the one under way or, while waiting to retry, the
+    /// next one. See `RetryPolicy`.
+    pub attempts: u32,
+}
+
+/// Returned by `Engine::send`, to wait for that send alone or cancel it. Observers
+/// still get every event of the send.
+#[derive(Clone)]
+pub struct SendHandle {
+    token: MessageId,
+    to: Endpoint,
+    outcome: watch::Receiver<Option<SendOutcome>>,
+    reporter: Arc<watch::Sender<Option<SendOutcome>>>,
+    cancel: Arc<Notify>,
+    observers: Observers,
+    runtime: Handle,
+}
+
+impl fmt::Debug for SendHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendHandle")
+            .field("token", &self.token)
+            .field("to", &self.to)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SendHandle {
+    pub fn token(&self) -> &MessageId {
+        &self.token
+    }
+
+    pub fn to(&self) -> &Endpoint {
+        &self.to
+    }
+
+    /// Cancels the send unless its outcome is already known, the outcome then
+    /// being `Failed` with `SocketEngineError::Cancelled` and a `Cancelled` event
+    /// being emitted instead of `SendFailed`. The send task stops at its next
+    /// wait: a connect or write under way is not interrupted, but no retry
+    /// follows and no further event is emitted. Returns false, doing nothing,
+    /// when the send is already over.
+    ///
+    /// `Cancelled` is emitted from the engine's runtime, so observers may cancel
+    /// sends while being notified.
+    pub fn abort(&self) -> bool {
+        let cancelled = report_outcome(
+            &self.reporter,
+            SendOutcome::Failed {
+                error: SocketEngineError::Cancelled,
+            },
+        );
+        if !cancelled {
+            return false;
+        }
+        self.cancel.notify_one();
+        let observers = self.observers.clone();
+        let event = SocketEngineEvent::Data(DataEvent::Cancelled {
+            token: self.token.clone(),
+            to: self.to.clone(),
+        });
+        self.runtime.spawn(async move {
+            notify_all_observers(&observers, &event);
+        });
+        true
+    }
 
+    /// Resolves once the data is sent or the send has failed.
+    pub async fn outcome(&self) -> SendOutcome {
+        let mut outcome = self.outcome.clone();
+        let outcome = match outcome.wait_for(Option::is_some).await {
+            Ok(outcome) => outcome.clone(),
+            Err(_) => None,
+        };
+        outcome.unwrap_or_else(|| SendOutcome::Failed {
+            error: SocketEngineError::send(std::io::Error::other(
+                "Send task ended without reporting its outcome",
+            )),
+        })
+    }
+}
+
+// Only the first outcome reported for a send counts, returns whether it was this one
+pub(crate) fn report_outcome(
+    outcome: &watch::Sender<Option<SendOutcome>>,
+    value: SendOutcome,
+) -> bool {
+    outcome.send_if_modified(|outcome| {
+        if outcome.is_some() {
+            return false;
+        }
+        *outcome = Some(value);
+        true
+    })
+}
+
+/// Sends and receives over UDP, TCP and BP on behalf of its observers.
+///
+/// Every method takes `&self`, so one engine can be shared between threads
+/// behind an `Arc`.
 pub struct Engine {
-    observers: Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
-    sockets: HashMap<Endpoint, GenericSocket>,
+    observers: RwLock<Observers>,
+    // Observers that receive `PeerStateChanged`, all of them but the peer state one
+    peer_state_subscribers: Arc<Mutex<Observers>>,
+    peer_states: Option<Arc<Mutex<PeerStateTracker>>>,
+    stats: Arc<TrafficStats>,
+    // Runtime the engine's tasks and listeners run on, `TOKIO_RUNTIME` when unset
+    runtime: Option<Handle>,
+    sockets: Arc<Mutex<HashMap<Endpoint, GenericSocket>>>,
+    // Outgoing TCP connections kept open when `close_after_send` is disabled
+    connections: ConnectionPool,
+    idle_timeout: Duration,
+    max_pooled_connections: usize,
+    close_after_send: bool,
+    reconnect: bool,
+    read_replies: bool,
+    // Writers of the TCP connections accepted by the listeners
+    replies: ReplyQueues,
+    retry_policy: RetryPolicy,
+    ipv6_only: Option<bool>,
+    ttl: Option<u32>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    reuse_address: Option<bool>,
+    reuse_port: bool,
+    max_connections: usize,
+    config: EngineConfig,
+    // Certificates of the TCP transport, see `reload_tls`
+    #[cfg(feature = "tls")]
+    tls: SharedTls,
+    tcp_nodelay: bool,
+    send_queue: SendQueue,
+    rate_limits: RateLimits,
+    max_frame_size: Option<usize>,
+    local_shortcut: bool,
+    poll_queue: Option<Arc<PollQueue>>,
+    bp_identity: Option<Endpoint>,
+    echo_responders: Mutex<HashMap<Endpoint, Arc<AtomicBool>>>,
+    // Listeners started by this engine
+    listeners: Mutex<HashMap<Endpoint, StartedListener>>,
+    strict: bool,
+    misuse: MisuseTracker,
+    // Sends in flight, by token
+    send_registry: SendRegistry,
+    registrations: Mutex<HashMap<ObserverId, Registration>>,
+    next_observer_id: AtomicU64,
+    peers: Mutex<HashMap<String, Endpoint>>,
+}
+
+#[derive(Default)]
+struct MisuseTracker {
+    warned: Mutex<HashSet<MisuseKind>>,
+}
+
+struct Registration {
+    observer: SharedObserver,
+    detachable: SharedObserver,
+    slot: Arc<Mutex<Option<SharedObserver>>>,
+}
+
+// Forwards the events of `mask` to an observer until `Engine::remove_observer`
+// empties the slot. Other events never lock the observer.
+struct DetachableObserver {
+    slot: Arc<Mutex<Option<SharedObserver>>>,
+    mask: EventMask,
+}
+
+impl EngineObserver for DetachableObserver {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if !self.mask.matches(&event) {
+            return;
+        }
+        let observer = self.slot.lock().unwrap().clone();
+        if let Some(observer) = observer {
+            observer.lock().unwrap().on_engine_event(event);
+        }
+    }
+}
+
+// Queues events for an async observer, whose task hands them over one at a time
+struct AsyncObserverAdapter(mpsc::UnboundedSender<SocketEngineEvent>);
+
+impl EngineObserver for AsyncObserverAdapter {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        // Fails only once the task is gone with the runtime
+        let _ = self.0.send(event);
+    }
+}
+
+// Sends whose task is running, by token; a broadcast has several
+type SendRegistry = Arc<Mutex<HashMap<MessageId, Vec<TrackedSend>>>>;
+
+struct TrackedSend {
+    handle: SendHandle,
+    bytes: usize,
+    started_at: Instant,
+    // Updated by the send task before each retry
+    attempts: Arc<AtomicU32>,
+}
+
+impl TrackedSend {
+    // `None` once the outcome is reported, though the task may not be over yet
+    fn state(&self) -> Option<SendState> {
+        if self.handle.outcome.borrow().is_some() {
+            return None;
+        }
+        Some(SendState {
+            token: self.handle.token.clone(),
+            to: self.handle.to.clone(),
+            bytes: self.bytes,
+            started_at: self.started_at,
+            attempts: self.attempts.load(Ordering::Relaxed),
+        })
+    }
+}
+
+// Token of in-flight sends, released when the last send task using it ends
+struct PendingToken {
+    sends: SendRegistry,
+    token: MessageId,
+}
+
+impl Drop for PendingToken {
+    fn drop(&mut self) {
+        self.sends.lock().unwrap().remove(&self.token);
+    }
+}
+
+// Response observer of a pending `Engine::request`
+struct RequestObserver<'a> {
+    engine: &'a Engine,
+    id: ObserverId,
+}
+
+impl Drop for RequestObserver<'_> {
+    fn drop(&mut self) {
+        self.engine.remove_observer(self.id);
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A listener started by the engine, as `Drop` needs it
+struct StartedListener {
+    stop: Arc<AtomicBool>,
+    status: watch::Receiver<ListenerStatus>,
+}
+
+/// Dropping the engine stops its listeners, closes the TCP connections it keeps
+/// open (with a `Closed` event each) and the sockets adopted for sends.
+///
+/// Each listener notices within its poll interval (see `EngineConfig::poll_interval`),
+/// then closes its socket, which frees the port, before its `ListenerHandle`
+/// turns `Stopped`. Drop waits for that, so that the ports can be bound again
+/// right away, unless it runs on a Tokio runtime, whose thread it must not
+/// block: listeners are then only signalled. The wait is woken by the status
+/// changes and bounded by the poll interval plus one second, after which a
+/// listener held up, e.g. by an observer, is left to stop on its own. TCP
+/// connections already accepted stay open until their peer closes them.
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let listeners: Vec<_> = self.listeners.lock().unwrap().drain().collect();
+        for (_, listener) in &listeners {
+            listener.stop.store(true, Ordering::Relaxed);
+        }
+
+        pool::close_all(&self.connections, &self.observers());
+        self.sockets.lock().unwrap().clear();
+
+        if listeners.is_empty() || Handle::try_current().is_ok() {
+            return;
+        }
+        let stopped = async {
+            for (_, mut listener) in listeners {
+                // An error means the listener task is gone, so is its socket
+                let _ = listener
+                    .status
+                    .wait_for(|status| {
+                        !matches!(status, ListenerStatus::Starting | ListenerStatus::Running)
+                    })
+                    .await;
+            }
+        };
+        // Waited on a timer of its own, the engine's runtime may have its workers
+        // held up by the same observers as the listeners
+        let bound = self.config.poll_interval + LISTENER_EXIT_GRACE;
+        if let Ok(waiter) = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+        {
+            let _ = waiter.block_on(async { tokio::time::timeout(bound, stopped).await });
+        }
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// An engine with the settings of `config`.
+    pub fn with_config(config: EngineConfig) -> Self {
+        let stats = Arc::new(TrafficStats::default());
         Self {
-            observers: Vec::new(),
-            sockets: HashMap::new(),
+            observers: RwLock::new(vec![Arc::new(Mutex::new(StatsObserver(stats.clone())))]),
+            peer_state_subscribers: Arc::new(Mutex::new(Vec::new())),
+            peer_states: None,
+            stats,
+            runtime: None,
+            sockets: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_pooled_connections: DEFAULT_MAX_POOLED_CONNECTIONS,
+            close_after_send: true,
+            reconnect: false,
+            read_replies: false,
+            replies: ReplyQueues::default(),
+            retry_policy: RetryPolicy::NONE,
+            ipv6_only: None,
+            ttl: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            reuse_address: None,
+            reuse_port: false,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            send_queue: SendQueue::new(config.send_queue_capacity, config.send_workers),
+            #[cfg(feature = "tls")]
+            tls: Arc::new(RwLock::new(config.tls.clone())),
+            config,
+            tcp_nodelay: false,
+            rate_limits: RateLimits::default(),
+            max_frame_size: None,
+            local_shortcut: false,
+            poll_queue: None,
+            bp_identity: None,
+            echo_responders: Mutex::new(HashMap::new()),
+            listeners: Mutex::new(HashMap::new()),
+            strict: false,
+            misuse: MisuseTracker::default(),
+            send_registry: Arc::new(Mutex::new(HashMap::new())),
+            registrations: Mutex::new(HashMap::new()),
+            next_observer_id: AtomicU64::new(0),
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a TCP send shuts the connection down once the payload is written (default: true).
+    /// With `false`, the connection stays open and is reused by later sends to the same target,
+    /// until the peer closes it or a write fails; the next send then connects again.
+    pub fn with_close_after_send(mut self, close_after_send: bool) -> Self {
+        self.close_after_send = close_after_send;
+        self
+    }
+
+    /// When writing to a kept-alive TCP connection fails because the peer dropped
+    /// it (`BrokenPipe` or `ConnectionReset`), connects again once and resends the
+    /// payload before reporting a failure, under the same token. Observers see
+    /// `Closed` then `Established`. Default: false, the send fails.
+    pub fn with_reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Delivers what peers send back over outgoing TCP connections as `Received`
+    /// events, `listener` being the local end of the connection (default: false,
+    /// such data is left unread). `Closed` is then emitted when the peer closes
+    /// the connection. With `with_close_after_send(true)`, only the write half is
+    /// shut down after the send, and the connection is closed once the peer does
+    /// or after sending nothing for `with_idle_timeout`.
+    pub fn with_read_replies(mut self, read_replies: bool) -> Self {
+        self.read_replies = read_replies;
+        self
+    }
+
+    /// Closes an outgoing TCP connection once no send used it for `timeout`
+    /// (default: `DEFAULT_IDLE_TIMEOUT`), emitting `Closed`.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Caps the outgoing TCP connections kept open between sends (default:
+    /// `DEFAULT_MAX_POOLED_CONNECTIONS`). Beyond it, connections to further
+    /// targets are closed after their send.
+    pub fn with_max_pooled_connections(mut self, max: usize) -> Self {
+        self.max_pooled_connections = max;
+        self
+    }
+
+    /// Retries TCP connects and UDP/BP sends that fail with a transient error,
+    /// keeping the send token. Each retry is announced by a `Retrying` event and
+    /// failure events are only emitted once the last attempt failed; other errors
+    /// fail the send at once. Default: no retry.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Sets `IPV6_V6ONLY` on IPv6 UDP and TCP listeners. With `false`, a listener on
+    /// `[::]` also accepts IPv4 peers, seen as v4-mapped addresses (`[::ffff:a.b.c.d]`).
+    /// Unset, the system default applies (`net.ipv6.bindv6only` on Linux).
+    pub fn with_ipv6_only(mut self, ipv6_only: bool) -> Self {
+        self.ipv6_only = Some(ipv6_only);
+        self
+    }
+
+    /// Sets the TTL (IPv4) or hop limit (IPv6) of the packets the engine sends
+    /// over UDP and TCP, from listeners and from the sockets opened for sends.
+    /// Unset, the system default applies (64 on Linux).
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the kernel receive buffer (`SO_RCVBUF`) of listener sockets and of
+    /// the sockets opened for sends. Raise it when a busy UDP listener drops
+    /// datagrams, e.g. to 8–32 MiB for 10GbE ingest. The system may cap it
+    /// without error: on Linux, raise `net.core.rmem_max` as well.
+    pub fn with_recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the kernel send buffer (`SO_SNDBUF`) of the same sockets as
+    /// `with_recv_buffer_size`, capped by `net.core.wmem_max` on Linux.
+    pub fn with_send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets `SO_REUSEADDR` on listener sockets. By default it is set for TCP, BP
+    /// and multicast UDP listeners, and cleared for other UDP listeners so that
+    /// two of them cannot bind the same port.
+    pub fn with_reuse_address(mut self, enabled: bool) -> Self {
+        self.reuse_address = Some(enabled);
+        self
+    }
+
+    /// Sets `SO_REUSEPORT` on listener sockets (default: off), so that listeners
+    /// of several processes can bind the same port and share its traffic.
+    /// Only available on Unix systems, elsewhere listeners fail to start with it.
+    pub fn with_reuse_port(mut self, enabled: bool) -> Self {
+        self.reuse_port = enabled;
+        self
+    }
+
+    /// Caps the connections each TCP listener handles at a time (default:
+    /// `DEFAULT_MAX_CONNECTIONS`). Connections accepted beyond it are closed
+    /// right away and reported as a `SocketError` with
+    /// `SocketEngineError::TooManyConnections`.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on outgoing and accepted TCP connections, so small
+    /// messages leave at once instead of being held back by Nagle's algorithm
+    /// for up to 40 ms. Recommended for chat-like traffic; off by default.
+    pub fn with_tcp_nodelay(mut self, enabled: bool) -> Self {
+        self.tcp_nodelay = enabled;
+        self
+    }
+
+    /// Prefixes every TCP payload with its 4-byte big-endian length and reassembles
+    /// frames on receive, so one `Received` event matches one sent message.
+    /// Malformed streams are reported as `ReceiveFailed` and the connection is closed.
+    /// Sending a payload larger than `DEFAULT_MAX_FRAME_SIZE` fails with a
+    /// `SendFailed` event before anything is written.
+    pub fn with_length_prefix_framing(mut self, enabled: bool) -> Self {
+        self.max_frame_size = enabled.then_some(DEFAULT_MAX_FRAME_SIZE);
+        self
+    }
+
+    /// Delivers sends aimed at one of this engine's own listeners without touching
+    /// the network. Observers still see `Sending`, `Sent` and `Received`, all flagged
+    /// with `local: true`.
+    pub fn with_local_shortcut(mut self, local_shortcut: bool) -> Self {
+        self.local_shortcut = local_shortcut;
+        self
+    }
+
+    /// Default BP endpoint that BP sends leave from when no source is given.
+    pub fn with_bp_identity(mut self, identity: Endpoint) -> Self {
+        self.bp_identity = Some(identity);
+        self
+    }
+
+    /// Queues every event (up to `capacity`, dropping the oldest) so it can be
+    /// fetched with `poll_event`. Observers keep receiving events as well.
+    pub fn with_poll_queue(mut self, capacity: usize) -> Self {
+        let queue = Arc::new(PollQueue::new(capacity));
+        self.register_observer(Arc::new(Mutex::new(PollQueueObserver(queue.clone()))));
+        self.poll_queue = Some(queue);
+        self
+    }
+
+    /// Runs sends and listeners on the runtime behind `handle` instead of the shared
+    /// `TOKIO_RUNTIME`, which is then never started by this engine.
+    ///
+    /// The runtime must be multi-threaded, with I/O and time enabled: listeners
+    /// and sends wait on its reactor, and a current-thread runtime only runs
+    /// them while its owner is blocked on it. `send_blocking` must not be called
+    /// from one of its tasks.
+    pub fn with_runtime(mut self, handle: Handle) -> Self {
+        self.runtime = Some(handle);
+        self
+    }
+
+    fn runtime(&self) -> &Handle {
+        self.runtime
+            .as_ref()
+            .unwrap_or_else(|| TOKIO_RUNTIME.handle())
+    }
+
+    // Where listeners run, see `ThreadBudget::listeners_share_workers`
+    fn listener_runtime(&self) -> &Handle {
+        match &self.runtime {
+            Some(handle) => handle,
+            None if thread_budget().listeners_share_workers => TOKIO_RUNTIME.handle(),
+            None => LISTENER_RUNTIME.handle(),
+        }
+    }
+
+    /// Tracks the state of every peer the engine sends to or pings, from send
+    /// outcomes, connection events and echo replies. Each state change is reported
+    /// with a `PeerStateChanged` event.
+    pub fn with_peer_states(mut self, thresholds: PeerStateThresholds) -> Self {
+        let tracker = Arc::new(Mutex::new(PeerStateTracker::new(thresholds)));
+        self.observers
+            .get_mut()
+            .unwrap()
+            .push(Arc::new(Mutex::new(PeerStateObserver {
+                tracker: tracker.clone(),
+                subscribers: self.peer_state_subscribers.clone(),
+            })));
+        self.peer_states = Some(tracker);
+        self
+    }
+
+    /// Current state of every peer seen so far. Always empty without `with_peer_states`.
+    pub fn peer_states(&self) -> HashMap<Endpoint, PeerState> {
+        self.peer_states
+            .as_ref()
+            .map_or_else(HashMap::new, |tracker| tracker.lock().unwrap().states())
+    }
+
+    /// Traffic exchanged with `endpoint`: sends to it, data received from it and
+    /// failures reported for it. Receive failures are counted on the listener.
+    /// Only the `stats::MAX_TRACKED_ENDPOINTS` endpoints with the most recent traffic
+    /// are kept, the counters of an endpoint forgotten before start over.
+    pub fn stats(&self, endpoint: &Endpoint) -> EndpointStats {
+        self.stats.get(endpoint)
+    }
+
+    /// Traffic of the whole engine, along with its `socket_count`.
+    pub fn total_stats(&self) -> EndpointStats {
+        EndpointStats {
+            socket_count: self.socket_count() as u64,
+            ..self.stats.total()
+        }
+    }
+
+    /// Next queued event, waiting at most `timeout`. Always `None` without a poll queue.
+    pub fn poll_event(&self, timeout: Duration) -> Option<EventEnvelope> {
+        self.poll_queue.as_ref()?.poll(timeout)
+    }
+
+    /// Number of events the poll queue dropped on overflow.
+    pub fn poll_dropped(&self) -> usize {
+        self.poll_queue.as_ref().map_or(0, |queue| queue.dropped())
+    }
+
+    // Snapshot of the observers, handed to tasks that outlive the call
+    fn observers(&self) -> Observers {
+        self.observers.read().unwrap().clone()
+    }
+
+    fn echo_flag(&self, endpoint: &Endpoint) -> Arc<AtomicBool> {
+        self.echo_responders
+            .lock()
+            .unwrap()
+            .entry(endpoint.clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Makes the listener on `endpoint` (running or started later) send echo probes
+    /// back to their source. Probes are not delivered as `Received` events.
+    pub fn enable_echo_responder(&self, endpoint: Endpoint) {
+        self.echo_flag(&endpoint).store(true, Ordering::Relaxed);
+    }
+
+    /// Caps the rate of the sends to `endpoint`, replacing any previous limit.
+    /// Sends over the limit wait before leaving, in the order they were made.
+    ///
+    /// Bytes are counted as handed to the socket, frame headers included. The
+    /// limit is exact for TCP but best-effort for UDP, whose datagrams may still
+    /// be dropped by the network or a slow receiver. Local deliveries are not limited.
+    pub fn set_rate_limit(&self, endpoint: Endpoint, limit: RateLimit) {
+        self.rate_limits
+            .lock()
+            .unwrap()
+            .insert(endpoint, TokenBucket::new(limit));
+    }
+
+    /// Lifts the limit set with `set_rate_limit`, sends already waiting still wait.
+    pub fn remove_rate_limit(&self, endpoint: &Endpoint) {
+        self.rate_limits.lock().unwrap().remove(endpoint);
+    }
+
+    /// Replaces the TLS config set with `EngineConfig::tls`, e.g. to renew
+    /// certificates without restarting the listeners. Connections accepted and
+    /// sends started afterwards use the new config, open connections keep theirs.
+    #[cfg(feature = "tls")]
+    pub fn reload_tls(&self, config: TlsConfig) {
+        *self.tls.write().unwrap() = config;
+    }
+
+    /// Sends `count` echo probes of `size` bytes to a UDP or TCP listener with an
+    /// echo responder, one every `interval`. Each probe is reported with an
+    /// `EchoReply` event; a probe without reply after `ECHO_REPLY_TIMEOUT` is lost.
+    /// Fails with `SocketEngineError::Send` when the probes cannot be sent.
+    pub async fn ping(
+        &self,
+        target: Endpoint,
+        size: usize,
+        count: u32,
+        interval: Duration,
+    ) -> Result<PingReport, SocketEngineError> {
+        let observers = self.observers();
+        let framed = self.max_frame_size.is_some();
+        self.runtime()
+            .spawn_blocking(move || run_ping(&observers, target, size, count, interval, framed))
+            .await
+            .map_err(std::io::Error::other)
+            .and_then(|report| report)
+            .map_err(SocketEngineError::send)
+    }
+
+    /// Endpoint registered under `alias`, such as the peer found by `pair_with_code`.
+    pub fn peer(&self, alias: &str) -> Option<Endpoint> {
+        self.peers.lock().unwrap().get(alias).cloned()
+    }
+
+    /// Finds the other engine using the same `code` on the local network and
+    /// registers it under the `PAIR_ALIAS` alias.
+    ///
+    /// Both sides announce one of their listeners on a multicast group derived from
+    /// the code, so a listener must be started first. Blocks until exactly one other
+    /// participant is found, failing on timeout or when a third one shows up.
+    pub fn pair_with_code(&self, code: &str, timeout: Duration) -> Result<Endpoint, PairingError> {
+        let advertised = self
+            .sockets
+            .lock()
+            .unwrap()
+            .keys()
+            .min_by_key(|endpoint| endpoint.to_string())
+            .cloned()
+            .ok_or(PairingError::NoListener)?;
+        let endpoint = run_pairing(&self.observers(), code, &advertised, timeout)?;
+        self.peers
+            .lock()
+            .unwrap()
+            .insert(PAIR_ALIAS.to_string(), endpoint.clone());
+        Ok(endpoint)
+    }
+
+    /// Number of socket descriptors the engine currently holds open: one per
+    /// bound listener or adopted socket, and per TCP connection kept alive between
+    /// sends along with the duplicate its watcher waits on. Also in `total_stats`.
+    pub fn socket_count(&self) -> usize {
+        self.sockets.lock().unwrap().len() + pool::descriptor_count(&self.connections)
+    }
+
+    /// Endpoints the engine currently has a bound socket on: running listeners and
+    /// sockets registered with `adopt_send_socket`, sorted by their display form.
+    pub fn active_listeners(&self) -> Vec<Endpoint> {
+        let mut endpoints: Vec<Endpoint> = self.sockets.lock().unwrap().keys().cloned().collect();
+        endpoints.sort_by_cached_key(Endpoint::to_string);
+        endpoints
+    }
+
+    /// Whether a listener is running on `endpoint`, as opposed to a bound socket
+    /// only used to send from.
+    pub fn is_listening(&self, endpoint: &Endpoint) -> bool {
+        // Entries outlive listeners that stopped by themselves, the socket does not
+        let started = self.listeners.lock().unwrap().contains_key(endpoint);
+        started && self.sockets.lock().unwrap().contains_key(endpoint)
+    }
+
+    fn deliver_locally(
+        &self,
+        source_endpoint: Option<Endpoint>,
+        target_endpoint: Endpoint,
+        data: Vec<u8>,
+        token: MessageId,
+        outcome: Arc<watch::Sender<Option<SendOutcome>>>,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let observers = self.observers();
+        let runtime = self.runtime().clone();
+        async move {
+            let bytes = data.len();
+            report_outcome(&outcome, SendOutcome::Sent { bytes });
+            notify_all_observers(
+                &observers,
+                &SocketEngineEvent::Data(DataEvent::Sending {
+                    token: token.clone(),
+                    to: target_endpoint.clone(),
+                    bytes,
+                    local: true,
+                }),
+            );
+            notify_all_observers(
+                &observers,
+                &SocketEngineEvent::Data(DataEvent::Sent {
+                    token,
+                    to: target_endpoint.clone(),
+                    bytes_sent: bytes,
+                    wire_bytes: 0,
+                    from: source_endpoint.clone(),
+                    local: true,
+                }),
+            );
+            notify_received(
+                &observers,
+                &SocketEngineEvent::Data(DataEvent::Received {
+                    wire_bytes: 0,
+                    data,
+                    from: source_endpoint.unwrap_or(target_endpoint.clone()),
+                    listener: target_endpoint,
+                    connection: None,
+                    local: true,
+                }),
+                &runtime,
+            );
         }
     }
-    pub fn add_observer(&mut self, obs: Arc<Mutex<dyn EngineObserver + Send + Sync>>) {
-        self.observers.push(obs);
+
+    /// Registers `obs` for all events from now on. When the observer is already
+    /// registered and strict mode refuses it, the existing registration is returned.
+    pub fn add_observer(&self, obs: Arc<Mutex<dyn EngineObserver + Send + Sync>>) -> ObserverId {
+        self.add_observer_filtered(obs, EventMask::ALL)
+    }
+
+    /// Like `add_observer`, but only delivers the events of the categories in
+    /// `mask`, e.g. `EventMask::ERROR` for an error log. The observer's lock is
+    /// not taken for the other events.
+    pub fn add_observer_filtered(
+        &self,
+        obs: Arc<Mutex<dyn EngineObserver + Send + Sync>>,
+        mask: EventMask,
+    ) -> ObserverId {
+        let existing = self
+            .registrations
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, registration)| Arc::ptr_eq(&registration.observer, &obs))
+            .map(|(id, _)| *id);
+        if let Some(id) = existing {
+            if self.report_misuse(
+                MisuseKind::DuplicateObserver,
+                "Observer is already registered".to_string(),
+            ) {
+                return id;
+            }
+        }
+
+        let id = ObserverId(self.next_observer_id.fetch_add(1, Ordering::Relaxed));
+        let slot = Arc::new(Mutex::new(Some(obs.clone())));
+        let detachable: SharedObserver = Arc::new(Mutex::new(DetachableObserver {
+            slot: slot.clone(),
+            mask,
+        }));
+        self.registrations.lock().unwrap().insert(
+            id,
+            Registration {
+                observer: obs,
+                detachable: detachable.clone(),
+                slot,
+            },
+        );
+        self.register_observer(detachable);
+        id
+    }
+
+    /// Registers an observer whose handling of events is async, e.g. to write them
+    /// to a database, without blocking the thread that emits them.
+    ///
+    /// A task on the engine's runtime awaits `on_engine_event` for one event at a
+    /// time, in the order they were emitted; events wait in an unbounded queue
+    /// meanwhile, so a slow observer only delays its own events. There is no
+    /// ordering between it and other observers: a sync observer may see later
+    /// events before it does. `remove_observer` stops the queue, events already
+    /// in it are still delivered.
+    pub fn add_async_observer<O: AsyncEngineObserver>(&self, observer: Arc<O>) -> ObserverId {
+        let (events, mut queue) = mpsc::unbounded_channel();
+        self.runtime().spawn(async move {
+            while let Some(event) = queue.recv().await {
+                observer.on_engine_event(event).await;
+            }
+        });
+        self.add_observer(Arc::new(Mutex::new(AsyncObserverAdapter(events))))
+    }
+
+    /// Stops delivering events to an observer and releases it, including in the
+    /// listeners and sends already running. May be called from the observer itself.
+    /// Returns false when `id` is not registered, e.g. already removed.
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        let Some(registration) = self.registrations.lock().unwrap().remove(&id) else {
+            return false;
+        };
+        // Running tasks keep their copy of the observer list, detaching is what
+        // makes them skip this observer
+        registration.slot.lock().unwrap().take();
+        self.observers
+            .write()
+            .unwrap()
+            .retain(|o| !Arc::ptr_eq(o, &registration.detachable));
+        self.peer_state_subscribers
+            .lock()
+            .unwrap()
+            .retain(|o| !Arc::ptr_eq(o, &registration.detachable));
+        true
+    }
+
+    fn register_observer(&self, obs: Arc<Mutex<dyn EngineObserver + Send + Sync>>) {
+        self.peer_state_subscribers
+            .lock()
+            .unwrap()
+            .push(obs.clone());
+        self.observers.write().unwrap().push(obs);
+    }
+
+    /// Turns silently tolerated misuse into refused operations reported as
+    /// `ErrorEvent::Misuse`. The checks are:
+    /// - `DuplicateObserver`: `add_observer` with an observer already registered
+    ///   and not removed since;
+    /// - `UnknownSource`: a UDP or TCP send whose source is not one of this engine's
+    ///   listeners, the source would otherwise be ignored;
+    /// - `TokenReused`: a send reusing the token of a send still in flight;
+    /// - `UnsupportedProtocol`: a listener on a protocol the system does not
+    ///   provide, e.g. BP without the `AF_BP` kernel module or with a `BpConfig`
+    ///   family it does not know. Its handle fails either way, with
+    ///   `SocketEngineError::Misuse` in strict mode. Without the `bp` feature, `bp`
+    ///   endpoints already fail to parse with `EndpointParseError::BpNotCompiled`.
+    ///
+    /// In the default lenient mode the operation goes ahead, `misuse_warnings` is
+    /// incremented and the first misuse of each kind emits `ErrorEvent::MisuseWarning`.
+    ///
+    /// Both events are emitted from the engine's runtime, so observers may call
+    /// the engine while being notified.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Number of misuses tolerated in lenient mode, also in `total_stats`.
+    pub fn misuse_warnings(&self) -> usize {
+        self.stats.total().misuse_warnings as usize
+    }
+
+    // Returns true when the misused operation must be refused
+    fn report_misuse(&self, kind: MisuseKind, detail: String) -> bool {
+        let event = if self.strict {
+            Some(ErrorEvent::Misuse { kind, detail })
+        } else {
+            self.stats.record_misuse();
+            let first = self.misuse.warned.lock().unwrap().insert(kind);
+            first.then_some(ErrorEvent::MisuseWarning { kind, detail })
+        };
+        if let Some(event) = event {
+            // Reported from the runtime, the caller may be an observer currently
+            // being notified
+            let observers = self.observers();
+            self.runtime().spawn(async move {
+                notify_all_observers(&observers, &SocketEngineEvent::Error(event));
+            });
+        }
+        self.strict
     }
 
     fn create_socket_and_store(
-        &mut self,
+        &self,
         endpoint: Endpoint,
-    ) -> Result<GenericSocket, Box<dyn std::error::Error + Send + Sync>> {
-        let socket = match GenericSocket::new(endpoint.clone()) {
-            Ok(sock) => sock,
-            Err(e) => {
-                return Err(e);
+    ) -> Result<GenericSocket, SocketEngineError> {
+        let socket = GenericSocket::new(endpoint.clone(), &self.config)
+            .map_err(|e| self.check_protocol_support(&endpoint, e))?;
+        self.store_socket(socket)
+    }
+
+    fn store_socket(&self, socket: GenericSocket) -> Result<GenericSocket, SocketEngineError> {
+        // Checked and inserted under one lock, so concurrent starts on the same
+        // endpoint cannot replace each other's socket
+        match self.sockets.lock().unwrap().entry(socket.endpoint.clone()) {
+            Entry::Occupied(entry) => {
+                return Err(SocketEngineError::AlreadyInUse(entry.key().clone()));
             }
+            Entry::Vacant(entry) => {
+                entry.insert(socket.try_clone().map_err(SocketEngineError::socket)?);
+            }
+        }
+        Ok(socket)
+    }
+
+    // The socket of a listener could not be created as its protocol is missing
+    fn check_protocol_support(
+        &self,
+        endpoint: &Endpoint,
+        error: SocketEngineError,
+    ) -> SocketEngineError {
+        let unsupported = match &error {
+            SocketEngineError::Socket(e) => matches!(
+                e.raw_os_error(),
+                Some(libc::EAFNOSUPPORT | libc::EPROTONOSUPPORT)
+            ),
+            _ => false,
         };
+        if unsupported
+            && self.report_misuse(
+                MisuseKind::UnsupportedProtocol,
+                format!("Cannot listen on {}: {}", endpoint, error),
+            )
+        {
+            return SocketEngineError::Misuse(MisuseKind::UnsupportedProtocol);
+        }
+        error
+    }
 
-        match socket.try_clone() {
-            Ok(sock) => self.sockets.insert(endpoint.clone(), sock),
-            Err(e) => {
-                return Err(Box::new(e));
+    pub fn start_listener_async(&self, endpoint: Endpoint) -> ListenerHandle {
+        self.start_listener_with_limits(endpoint, ListenerLimits::default())
+    }
+
+    /// Starts a listener that stops by itself once `limits` are reached, emitting
+    /// `ListenerStopped` and releasing its socket.
+    pub fn start_listener_with_limits(
+        &self,
+        endpoint: Endpoint,
+        limits: ListenerLimits,
+    ) -> ListenerHandle {
+        let res = endpoint
+            .validate()
+            .and_then(|()| self.create_socket_and_store(endpoint.clone()));
+        self.spawn_listener(endpoint, res, limits, Vec::new(), None)
+    }
+
+    /// Starts a listener splitting accepted TCP connections into messages with
+    /// `framing`, e.g. `Framing::NewlineDelimited { max_message_size: 4096 }`,
+    /// instead of the engine-wide `with_length_prefix_framing` setting. A message
+    /// growing past its maximum size is reported as `ReceiveFailed` and the
+    /// connection is dropped. Other protocols ignore `framing`.
+    pub fn start_listener_with_framing(
+        &self,
+        endpoint: Endpoint,
+        framing: Framing,
+    ) -> ListenerHandle {
+        let res = endpoint
+            .validate()
+            .and_then(|()| self.create_socket_and_store(endpoint.clone()));
+        self.spawn_listener(
+            endpoint,
+            res,
+            ListenerLimits::default(),
+            Vec::new(),
+            Some(framing),
+        )
+    }
+
+    /// Starts a UDP listener that joins `groups` once bound and leaves them when
+    /// it stops. The endpoint is usually the wildcard address with the groups'
+    /// port, e.g. `udp 0.0.0.0:5353`; other receivers may bind the same port.
+    /// `Received` events report the unicast address of the sender.
+    pub fn start_multicast_listener(
+        &self,
+        endpoint: Endpoint,
+        groups: Vec<MulticastGroup>,
+    ) -> ListenerHandle {
+        let res = endpoint.validate().and_then(|()| {
+            if endpoint.proto != EndpointProto::Udp {
+                return Err(SocketEngineError::UnsupportedScheme(
+                    endpoint.proto.to_string(),
+                ));
             }
-        };
-        return Ok(socket);
+            self.create_socket_and_store(endpoint.clone())
+        });
+        self.spawn_listener(endpoint, res, ListenerLimits::default(), groups, None)
+    }
+
+    /// Starts a listener on a socket created outside the engine, without binding
+    /// it again.
+    ///
+    /// The engine owns the socket from then on: it is closed when the listener
+    /// stops, the caller must not keep another handle on it to be sure of that.
+    pub fn adopt_listener(&self, socket: AdoptedSocket) -> ListenerHandle {
+        let AdoptedSocket(socket) = socket;
+        let endpoint = socket.endpoint.clone();
+        let res = self
+            .check_adopted(&socket)
+            .and_then(|()| self.store_socket(socket));
+        self.spawn_listener(endpoint, res, ListenerLimits::default(), Vec::new(), None)
     }
 
-    pub fn start_listener_async(&mut self, endpoint: Endpoint) {
-        let res = self.create_socket_and_store(endpoint.clone());
+    /// Registers a socket created outside the engine as a send source: UDP and BP
+    /// sends from its endpoint go out through it. No listener is started.
+    ///
+    /// The engine owns the socket and keeps it open until the engine is dropped.
+    pub fn adopt_send_socket(&self, socket: AdoptedSocket) -> Result<(), SocketEngineError> {
+        let AdoptedSocket(socket) = socket;
+        self.check_adopted(&socket)?;
+        self.store_socket(socket).map(drop)
+    }
+
+    // BP sockets must be of the family of this engine, which `AdoptedSocket::new`
+    // could not check
+    fn check_adopted(&self, socket: &GenericSocket) -> Result<(), SocketEngineError> {
+        #[cfg(feature = "bp")]
+        if socket.endpoint.proto.is_bp() && socket.sockaddr.family() as i32 != self.config.bp.family
+        {
+            return Err(SocketEngineError::SocketMismatch(format!(
+                "Socket address family does not match {}",
+                socket.endpoint
+            )));
+        }
+        #[cfg(not(feature = "bp"))]
+        let _ = socket;
+        Ok(())
+    }
 
-        TOKIO_RUNTIME.spawn_blocking({
-            let observers = self.observers.clone();
-            let endpoint_clone = endpoint.clone();
-            move || match res {
-                Ok(mut sock) => {
-                    if let Err(e) = sock.start_listener(observers.clone()) {
+    fn spawn_listener(
+        &self,
+        endpoint: Endpoint,
+        res: Result<GenericSocket, SocketEngineError>,
+        limits: ListenerLimits,
+        multicast: Vec<MulticastGroup>,
+        framing: Option<Framing>,
+    ) -> ListenerHandle {
+        let (status, status_rx) = watch::channel(ListenerStatus::Starting);
+        let framing = framing.unwrap_or(match self.max_frame_size {
+            Some(max_frame_size) => Framing::LengthPrefixed { max_frame_size },
+            None => Framing::Raw,
+        });
+        let options = ListenerOptions {
+            framing,
+            ipv6_only: self.ipv6_only,
+            ttl: self.ttl,
+            recv_buffer_size: self.recv_buffer_size,
+            send_buffer_size: self.send_buffer_size,
+            reuse_address: self.reuse_address,
+            reuse_port: self.reuse_port,
+            max_connections: Some(self.max_connections),
+            tcp_buffer_size: self.config.tcp_buffer_size,
+            udp_buffer_size: self.config.udp_buffer_size,
+            poll_interval: self.config.poll_interval,
+            tcp_backlog: self.config.tcp_backlog,
+            tcp_nodelay: self.tcp_nodelay,
+            multicast,
+            limits,
+            echo: self.echo_flag(&endpoint),
+            stop: Arc::new(AtomicBool::new(false)),
+            replies: self.replies.clone(),
+            status: Arc::new(status),
+            runtime: self.runtime().clone(),
+            #[cfg(feature = "bp")]
+            bp: self.config.bp,
+            #[cfg(feature = "tls")]
+            tls: self.tls.clone(),
+        };
+        let handle = ListenerHandle::new(endpoint.clone(), options.stop.clone(), status_rx);
+        // The handle's status is already `Failed` when the socket could not be
+        // created. The event is reported from the runtime, the caller may be an
+        // observer currently being notified
+        let mut sock = match res {
+            Ok(sock) => sock,
+            Err(e) => {
+                options
+                    .status
+                    .send_replace(ListenerStatus::Failed(e.to_string()));
+                let observers = self.observers();
+                self.runtime().spawn(async move {
+                    notify_all_observers(
+                        &observers,
+                        &SocketEngineEvent::Error(ErrorEvent::SocketError { endpoint, error: e }),
+                    );
+                });
+                return handle;
+            }
+        };
+        self.listeners.lock().unwrap().insert(
+            endpoint,
+            StartedListener {
+                stop: options.stop.clone(),
+                status: handle.status_receiver(),
+            },
+        );
+
+        self.listener_runtime().spawn({
+            let observers = self.observers();
+            let sockets = self.sockets.clone();
+            async move {
+                let status = options.status.clone();
+                let endpoint = sock.endpoint.clone();
+                let res = sock.start_listener(observers.clone(), options).await;
+                // Both handles closed, so the port is free once the status changes
+                drop(sock);
+                sockets.lock().unwrap().remove(&endpoint);
+                match res {
+                    Ok((reason, messages)) => {
+                        status.send_replace(ListenerStatus::Stopped);
+                        notify_all_observers(
+                            &observers,
+                            &SocketEngineEvent::Connection(ConnectionEvent::ListenerStopped {
+                                endpoint: endpoint.clone(),
+                                reason,
+                                messages,
+                            }),
+                        )
+                    }
+                    Err(e) => {
+                        status.send_replace(ListenerStatus::Failed(e.to_string()));
                         notify_all_observers(
                             &observers,
                             &SocketEngineEvent::Error(ErrorEvent::SocketError {
-                                endpoint: sock.endpoint.clone(),
-                                reason: e.to_string(),
+                                endpoint: endpoint.clone(),
+                                error: e,
                             }),
-                        );
-                    } else {
-                        if let EndpointProto::Tcp = sock.endpoint.proto {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Connection(ConnectionEvent::ListenerStarted {
-                                    endpoint: sock.endpoint.clone(),
-                                }),
-                            );
-                        }
+                        )
                     }
                 }
-                Err(e) => {
-                    notify_all_observers(
-                        &observers,
-                        &SocketEngineEvent::Error(ErrorEvent::SocketError {
-                            endpoint: endpoint_clone,
-                            reason: e.to_string(),
-                        }),
-                    );
-                }
             }
         });
+        handle
+    }
+
+    /// Stops the listener running on `endpoint` and closes its socket. The listener
+    /// notices within a receive poll interval and emits `ListenerStopped` with the
+    /// `Stopped` reason; TCP connections it already accepted are left open.
+    pub fn stop_listener(&self, endpoint: Endpoint) -> Result<(), SocketEngineError> {
+        let listener = self.listeners.lock().unwrap().remove(&endpoint);
+        match listener.map(|listener| listener.stop) {
+            Some(stop) if self.sockets.lock().unwrap().contains_key(&endpoint) => {
+                stop.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            _ => Err(SocketEngineError::UnknownListener(endpoint)),
+        }
     }
 
+    /// Picks the socket a send goes out from, along with the source endpoint it is bound to.
+    ///
+    /// A BP send always leaves from a bound EID: the given source, or the engine's
+    /// BP identity. Sockets already bound by a listener are reused, otherwise a
+    /// socket is bound for this send only.
     fn try_reuse_socket_for_send(
         &self,
         source_opt: Option<Endpoint>,
         dest: Endpoint,
-    ) -> Result<GenericSocket, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(GenericSocket, Option<Endpoint>), SocketEngineError> {
+        if dest.proto.is_bp() {
+            let source = source_opt
+                .or_else(|| self.bp_identity.clone())
+                .ok_or(SocketEngineError::NoBpIdentity)?;
+            if let Some(existing_sock) = self.sockets.lock().unwrap().get(&source) {
+                let sock = existing_sock
+                    .try_clone()
+                    .map_err(SocketEngineError::socket)?;
+                return Ok((sock, Some(source)));
+            }
+            let sock = GenericSocket::new(source.clone(), &self.config)?;
+            sock.socket
+                .bind(&sock.sockaddr)
+                .map_err(SocketEngineError::bind)?;
+            return Ok((sock, Some(source)));
+        }
+
         if let Some(source) = source_opt {
-            if dest.proto == EndpointProto::Bp || dest.proto == EndpointProto::Udp {
-                if let Some(existing_sock) = self.sockets.get(&source) {
-                    return existing_sock.try_clone().map_err(Into::into);
+            if dest.proto == EndpointProto::Udp {
+                if let Some(existing_sock) = self.sockets.lock().unwrap().get(&source) {
+                    let sock = existing_sock
+                        .try_clone()
+                        .map_err(SocketEngineError::socket)?;
+                    return Ok((sock, Some(source)));
                 }
             }
         }
         // Should be safe as we do not bind
-        GenericSocket::new(dest).map_err(Into::into)
+        let sock = GenericSocket::new(dest, &self.config)?;
+        if let Some(ttl) = self.ttl {
+            sock.set_ttl(ttl).map_err(SocketEngineError::socket)?;
+        }
+        sock.set_buffer_sizes(self.recv_buffer_size, self.send_buffer_size)
+            .map_err(SocketEngineError::socket)?;
+        Ok((sock, None))
     }
 
+    #[deprecated(note = "use `Engine::send` with `SendOptions`")]
     pub fn send_async(
         &self,
         source_endpoint: Option<Endpoint>,
         target_endpoint: Endpoint,
         data: Vec<u8>,
         token: String,
-    ) {
-        let observers = self.observers.clone();
-        let target_endpoint_clone = target_endpoint.clone();
-        let generic_socket_res = self.try_reuse_socket_for_send(source_endpoint, target_endpoint);
+    ) -> Result<SendHandle, SocketEngineError> {
+        let mut options = SendOptions::default().token(token);
+        if let Some(source) = source_endpoint {
+            options = options.source(source);
+        }
+        self.send(target_endpoint, data, options)
+    }
+
+    /// Like `send`, but waits for the outcome and returns the number of payload
+    /// bytes sent. Events are emitted as for `send`.
+    ///
+    /// Blocks the calling thread, so it must not be called from an async task
+    /// running on the engine's runtime.
+    pub fn send_blocking(
+        &self,
+        target_endpoint: Endpoint,
+        data: Vec<u8>,
+        options: SendOptions,
+    ) -> Result<usize, SocketEngineError> {
+        let token = self.reserve_token(options.token.clone())?;
+        let handle = self.start_send(target_endpoint, data, &options, token, true)?;
+        match self.runtime().block_on(handle.outcome()) {
+            SendOutcome::Sent { bytes } => Ok(bytes),
+            SendOutcome::Failed { error } => Err(error),
+        }
+    }
+
+    /// Sends `data` to `target_endpoint` in the background, reporting progress and
+    /// failures through events tagged with the send token.
+    ///
+    /// Failures known before anything is sent, such as an unresolvable address or
+    /// a socket that cannot be created, are also returned here; the returned handle
+    /// resolves with the outcome of the send itself.
+    pub fn send(
+        &self,
+        target_endpoint: Endpoint,
+        data: Vec<u8>,
+        options: SendOptions,
+    ) -> Result<SendHandle, SocketEngineError> {
+        let token = self.reserve_token(options.token.clone())?;
+        self.start_send(target_endpoint, data, &options, token, false)
+    }
+
+    /// Sends the same `data` to each of `targets`, as one `send` per target sharing
+    /// the token of `options`. Observers tell targets apart by the endpoint of each
+    /// event. A target that fails does not stop the others, the returned results
+    /// are in the order of `targets`.
+    pub fn broadcast(
+        &self,
+        targets: &[Endpoint],
+        data: Vec<u8>,
+        options: SendOptions,
+    ) -> Vec<Result<SendHandle, SocketEngineError>> {
+        let token = match self.reserve_token(options.token.clone()) {
+            Ok(token) => token,
+            Err(e) => return targets.iter().map(|_| Err(e.clone())).collect(),
+        };
+        targets
+            .iter()
+            .map(|target| {
+                self.start_send(target.clone(), data.clone(), &options, token.clone(), false)
+            })
+            .collect()
+    }
+
+    /// Sends `data` to `target` and resolves with the first received payload that
+    /// `matcher` accepts as its response, e.g. the acknowledgement of a message.
+    /// Fails with the error of the send if it fails, or with `NoResponse` after
+    /// `timeout`. Observers still get every event, the response included.
+    ///
+    /// A token is generated when `options` has none, so the matcher can compare it
+    /// with what the response carries.
+    pub async fn request(
+        &self,
+        target: Endpoint,
+        data: Vec<u8>,
+        mut options: SendOptions,
+        matcher: impl ResponseMatcher,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, SocketEngineError> {
+        let token = options.token.get_or_insert_with(next_send_token).clone();
+        let (response, response_rx) = oneshot::channel();
+        let observer = ResponseObserver {
+            token,
+            matcher,
+            response: Some(response),
+        };
+        let id = self.add_observer_filtered(
+            Arc::new(Mutex::new(observer)),
+            EventMask::DATA | EventMask::ERROR,
+        );
+        // Detaches the observer even when the request is dropped before it ends
+        let _observer = RequestObserver { engine: self, id };
+        match self.send(target, data, options) {
+            // Timed on the engine's runtime, the caller's may have no timer
+            Ok(_) => match self
+                .runtime()
+                .spawn(async move { tokio::time::timeout(timeout, response_rx).await })
+                .await
+            {
+                Ok(Ok(Ok(result))) => result,
+                _ => Err(SocketEngineError::NoResponse),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes `data` back on a TCP connection accepted by one of the engine's
+    /// listeners, as given by `Received` events, e.g. to answer a peer that has no
+    /// listener of its own. Replies to one connection are written in order by its
+    /// reader, with `Sending`, then `Sent` or `SendFailed` events tagged `token`.
+    /// Fails with a `NotFound` send error once the connection is closed.
+    pub fn reply(
+        &self,
+        connection: ConnectionId,
+        data: Vec<u8>,
+        token: impl Into<MessageId>,
+    ) -> Result<(), SocketEngineError> {
+        let reply = QueuedReply {
+            data,
+            token: token.into(),
+        };
+        self.replies.push(connection, reply).map_err(|_| {
+            SocketEngineError::send(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Connection {:?} is closed", connection),
+            ))
+        })
+    }
+
+    /// Cancels the sends in flight under `token`, as `SendHandle::abort` does,
+    /// e.g. the messages queued for a deleted peer. Returns whether a send was
+    /// cancelled: a token whose sends are over is left alone.
+    pub fn cancel_send(&self, token: &MessageId) -> bool {
+        let handles: Vec<SendHandle> = self
+            .send_registry
+            .lock()
+            .unwrap()
+            .get(token)
+            .map(|sends| sends.iter().map(|send| send.handle.clone()).collect())
+            .unwrap_or_default();
+        // Not under the lock, cancelled tasks release their token
+        let mut cancelled = false;
+        for handle in handles {
+            cancelled |= handle.abort();
+        }
+        cancelled
+    }
+
+    /// The sends started and not over yet, e.g. to show which messages are still
+    /// outstanding after the application reconnects to the engine. A broadcast
+    /// has one entry per target still pending.
+    pub fn pending_sends(&self) -> Vec<SendState> {
+        self.send_registry
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .filter_map(TrackedSend::state)
+            .collect()
+    }
 
-        let sock_addr = endpoint_to_sockaddr(target_endpoint_clone.clone()).unwrap();
+    /// State of the send running under `token`, the first target still pending
+    /// for a broadcast. `None` once it is over.
+    pub fn send_state(&self, token: &MessageId) -> Option<SendState> {
+        self.send_registry
+            .lock()
+            .unwrap()
+            .get(token)?
+            .iter()
+            .find_map(TrackedSend::state)
+    }
 
-        TOKIO_RUNTIME.spawn(async move {
-            let data_uuid_ref = &token;
+    fn reserve_token(
+        &self,
+        token: Option<MessageId>,
+    ) -> Result<Arc<PendingToken>, SocketEngineError> {
+        let token = token.unwrap_or_else(next_send_token);
+        let reused = match self.send_registry.lock().unwrap().entry(token.clone()) {
+            Entry::Occupied(_) => true,
+            Entry::Vacant(entry) => {
+                entry.insert(Vec::new());
+                false
+            }
+        };
+        if reused
+            && self.report_misuse(
+                MisuseKind::TokenReused,
+                format!("Token {} is already used by a pending send", token),
+            )
+        {
+            return Err(SocketEngineError::Misuse(MisuseKind::TokenReused));
+        }
+        Ok(Arc::new(PendingToken {
+            sends: self.send_registry.clone(),
+            token,
+        }))
+    }
 
-            let mut generic_socket = match generic_socket_res {
-                Ok(generic_socket) => generic_socket,
-                Err(e) => {
+    // Queues the send for the engine's send workers: waits for room in the queue
+    // when `wait_for_room` is set, fails with `QueueFull` otherwise
+    fn start_send(
+        &self,
+        target_endpoint: Endpoint,
+        data: Vec<u8>,
+        options: &SendOptions,
+        pending: Arc<PendingToken>,
+        wait_for_room: bool,
+    ) -> Result<SendHandle, SocketEngineError> {
+        let valid = target_endpoint
+            .validate()
+            .and_then(|()| options.source.as_ref().map_or(Ok(()), Endpoint::validate));
+        if let Err(error) = valid {
+            // Reported from the runtime, the caller may be an observer being notified
+            let observers = self.observers();
+            let event = SocketEngineEvent::Error(ErrorEvent::SendFailed {
+                endpoint: target_endpoint,
+                token: pending.token.clone(),
+                error: error.clone(),
+            });
+            self.runtime().spawn(async move {
+                notify_all_observers(&observers, &event);
+            });
+            return Err(error);
+        }
+        let source_endpoint = options.source.clone();
+        let token = pending.token.clone();
+        if let Some(source) = &source_endpoint {
+            if !target_endpoint.proto.is_bp()
+                && !self.sockets.lock().unwrap().contains_key(source)
+                && self.report_misuse(
+                    MisuseKind::UnknownSource,
+                    format!("{} is not a listener of this engine", source),
+                )
+            {
+                return Err(SocketEngineError::Misuse(MisuseKind::UnknownSource));
+            }
+        }
+        let (outcome, outcome_rx) = watch::channel(None);
+        let outcome = Arc::new(outcome);
+        let attempts = Arc::new(AtomicU32::new(1));
+        let handle = {
+            let token = token.clone();
+            let to = target_endpoint.clone();
+            let reporter = outcome.clone();
+            let observers = self.observers();
+            let runtime = self.runtime().clone();
+            let sends = self.send_registry.clone();
+            let bytes = data.len();
+            let attempts = attempts.clone();
+            let started_at = Instant::now();
+            move |cancel| {
+                let handle = SendHandle {
+                    token,
+                    to,
+                    outcome: outcome_rx,
+                    reporter,
+                    cancel,
+                    observers,
+                    runtime,
+                };
+                // Absent when the send is already over
+                if let Some(pending) = sends.lock().unwrap().get_mut(&handle.token) {
+                    pending.push(TrackedSend {
+                        handle: handle.clone(),
+                        bytes,
+                        started_at,
+                        attempts,
+                    });
+                }
+                handle
+            }
+        };
+
+        if self.local_shortcut && self.sockets.lock().unwrap().contains_key(&target_endpoint) {
+            let send = self.deliver_locally(
+                source_endpoint,
+                target_endpoint,
+                data,
+                token.clone(),
+                outcome.clone(),
+            );
+            return self.queue_send(
+                async move {
+                    let _pending = pending;
+                    send.await;
+                },
+                handle,
+                wait_for_room,
+            );
+        }
+
+        let candidates = endpoint_to_sockaddrs(&target_endpoint, &self.config);
+        // Frames are built up front, so that a payload too large for one fails
+        // before anything is written
+        let frame = match self.max_frame_size {
+            Some(max) if target_endpoint.proto == EndpointProto::Tcp => encode_frame(&data, max)
+                .map(Some)
+                .map_err(|error| SocketEngineError::Frame {
+                    peer: target_endpoint.clone(),
+                    error,
+                }),
+            _ => Ok(None),
+        };
+        let resolved = frame.and_then(|frame| {
+            let res = self.try_reuse_socket_for_send(source_endpoint, target_endpoint.clone())?;
+            if candidates.is_empty() {
+                return Err(SocketEngineError::AddrParse(format!(
+                    "Invalid address `{}`",
+                    target_endpoint.endpoint
+                )));
+            }
+            Ok((res, frame))
+        });
+        let ((socket, source), frame) = match resolved {
+            Ok(res) => res,
+            Err(e) => {
+                // Reported from the runtime like any other send failure, the caller
+                // may be an observer currently being notified
+                let observers = self.observers();
+                let error = e.clone();
+                self.runtime().spawn(async move {
+                    let _pending = pending;
                     notify_all_observers(
                         &observers,
-                        &&SocketEngineEvent::Error(ErrorEvent::SendFailed {
-                            endpoint: target_endpoint_clone,
-                            reason: e.to_string(),
+                        &SocketEngineEvent::Error(ErrorEvent::SendFailed {
+                            endpoint: target_endpoint,
+                            error,
                             token,
                         }),
                     );
-                    return;
-                }
-            };
+                });
+                return Err(e);
+            }
+        };
 
-            notify_all_observers(
-                &observers,
-                &SocketEngineEvent::Data(DataEvent::Sending {
-                    token: data_uuid_ref.clone(),
-                    to: target_endpoint_clone.clone(),
-                    bytes: data.len(),
-                }),
-            );
+        #[cfg(feature = "tracing")]
+        let span = crate::tracing_support::send_span(&token, &target_endpoint);
+        let task = SendTask {
+            token,
+            target: target_endpoint,
+            data,
+            frame,
+            source,
+            candidates,
+            observers: self.observers(),
+            outcome,
+            attempts,
+            connections: self.connections.clone(),
+            rate_limits: self.rate_limits.clone(),
+            settings: self.send_settings(options),
+        };
+        let send = async move {
+            let _pending = pending;
+            task.run(socket).await;
+        };
+        #[cfg(feature = "tracing")]
+        let send = tracing::Instrument::instrument(send, span);
+        self.queue_send(send, handle, wait_for_room)
+    }
 
-            match generic_socket.endpoint.proto {
-                EndpointProto::Bp | EndpointProto::Udp => {
-                    if let Err(err) = generic_socket.socket.send_to(&data.as_slice(), &sock_addr) {
-                        notify_all_observers(
-                            &observers,
-                            &SocketEngineEvent::Error(ErrorEvent::SendFailed {
-                                endpoint: target_endpoint_clone.clone(),
-                                token: data_uuid_ref.clone(),
-                                reason: err.to_string(),
-                            }),
-                        );
-                    } else {
-                        notify_all_observers(
-                            &observers,
-                            &SocketEngineEvent::Data(DataEvent::Sent {
-                                token: data_uuid_ref.clone(),
-                                to: target_endpoint_clone.clone(),
-                                bytes_sent: data.len(),
-                            }),
-                        );
-                    }
-                }
-                EndpointProto::Tcp => {
-                    if let Err(err) = generic_socket.socket.connect(&sock_addr) {
-                        if err.kind() == std::io::ErrorKind::ConnectionRefused {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Error(ErrorEvent::ConnectionFailed {
-                                    endpoint: target_endpoint_clone.clone(),
-                                    reason: ConnectionFailureReason::Refused,
-                                    token: data_uuid_ref.clone(),
-                                }),
-                            );
-                        } else if err.kind() == std::io::ErrorKind::TimedOut {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Error(ErrorEvent::ConnectionFailed {
-                                    endpoint: target_endpoint_clone.clone(),
-                                    reason: ConnectionFailureReason::Timeout,
-                                    token: data_uuid_ref.clone(),
-                                }),
-                            );
-                        } else {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Error(ErrorEvent::ConnectionFailed {
-                                    endpoint: target_endpoint_clone.clone(),
-                                    reason: ConnectionFailureReason::Other,
-                                    token: data_uuid_ref.clone(),
-                                }),
-                            );
-                        }
-                    } else {
-                        notify_all_observers(
-                            &observers,
-                            &SocketEngineEvent::Connection(ConnectionEvent::Established {
-                                remote: target_endpoint_clone.clone(), // Remote is the target we're connecting to
-                            }),
-                        );
-
-                        if let Err(err) = generic_socket.socket.write_all(&data.as_slice()) {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Error(ErrorEvent::SendFailed {
-                                    endpoint: target_endpoint_clone.clone(),
-                                    token: data_uuid_ref.clone(),
-                                    reason: err.to_string(),
-                                }),
-                            );
-                        } else {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Data(DataEvent::Sent {
-                                    token: data_uuid_ref.clone(),
-                                    to: target_endpoint_clone.clone(),
-                                    bytes_sent: data.len(),
-                                }),
-                            );
-                        }
-
-                        if let Err(err) = generic_socket.socket.flush() {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Error(ErrorEvent::SendFailed {
-                                    endpoint: target_endpoint_clone.clone(),
-                                    token: data_uuid_ref.clone(),
-                                    reason: err.to_string(),
-                                }),
-                            );
-                        }
-
-                        if let Err(err) = generic_socket.socket.shutdown(std::net::Shutdown::Both) {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Error(ErrorEvent::SendFailed {
-                                    endpoint: target_endpoint_clone.clone(),
-                                    token: data_uuid_ref.clone(),
-                                    reason: format!("Shutdown failed: {}", err),
-                                }),
-                            );
-                        } else {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Connection(ConnectionEvent::Closed {
-                                    remote: Some(generic_socket.endpoint.clone()),
-                                }),
-                            );
-                        }
-                    }
+    // Queues `send`, stopped early when its handle cancels it, and returns the
+    // handle `handle` builds
+    fn queue_send(
+        &self,
+        send: impl Future<Output = ()> + Send + 'static,
+        handle: impl FnOnce(Arc<Notify>) -> SendHandle,
+        wait_for_room: bool,
+    ) -> Result<SendHandle, SocketEngineError> {
+        let cancel = Arc::new(Notify::new());
+        // Held until the handle is registered, so the send can be cancelled from
+        // its first event on
+        let (registered, wait_registered) = oneshot::channel::<()>();
+        let job = {
+            let cancel = cancel.clone();
+            async move {
+                let _ = wait_registered.await;
+                tokio::select! {
+                    biased;
+                    _ = cancel.notified() => {}
+                    _ = send => {}
                 }
             }
-        });
+        };
+        self.send_queue
+            .push(self.runtime(), Box::pin(job), wait_for_room)?;
+        let handle = handle(cancel);
+        let _ = registered.send(());
+        Ok(handle)
+    }
+
+    fn send_settings(&self, options: &SendOptions) -> SendSettings {
+        SendSettings {
+            connect_timeout: options
+                .connect_timeout
+                .unwrap_or(self.config.connect_timeout),
+            retry_policy: options.retry_policy.unwrap_or(self.retry_policy),
+            pool: PoolSettings {
+                idle_timeout: self.idle_timeout,
+                max_pooled: self.max_pooled_connections,
+                heartbeat: self.config.heartbeat_interval.map(|interval| {
+                    Heartbeat::new(
+                        interval,
+                        self.config.heartbeat_timeout,
+                        self.config.heartbeat_max_missed,
+                        self.max_frame_size,
+                    )
+                }),
+                read_replies: self.read_replies,
+            },
+            close_after_send: self.close_after_send,
+            reconnect: self.reconnect,
+            read_replies: self.read_replies,
+            tcp_buffer_size: self.config.tcp_buffer_size,
+            max_frame_size: self.max_frame_size,
+            tcp_nodelay: self.tcp_nodelay,
+            ttl: self.ttl,
+            recv_buffer_size: self.recv_buffer_size,
+            send_buffer_size: self.send_buffer_size,
+            #[cfg(feature = "tls")]
+            tls: crate::tls::client(&self.tls),
+        }
     }
 }
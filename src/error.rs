@@ -0,0 +1,162 @@
+use std::{fmt, io, sync::Arc};
+
+use crate::{
+    endpoint::{Endpoint, EndpointParseError},
+    event::{ConnectionFailureReason, MisuseKind},
+    framing::FrameError,
+};
+
+/// Errors returned by the engine and carried by `ErrorEvent`s.
+///
+/// I/O errors are behind an `Arc` so events stay cheap to clone.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SocketEngineError {
+    Endpoint(EndpointParseError),
+    /// An address that could not be parsed or resolved.
+    AddrParse(String),
+    UnsupportedScheme(String),
+    /// Creating or configuring a socket failed.
+    Socket(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::io_error"))]
+        Arc<io::Error>,
+    ),
+    /// A socket handed to `AdoptedSocket::new` does not match its endpoint.
+    SocketMismatch(String),
+    /// Binding or listening failed, e.g. the address is already in use.
+    Bind(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::io_error"))]
+        Arc<io::Error>,
+    ),
+    /// The endpoint already has a socket in this engine.
+    AlreadyInUse(Endpoint),
+    /// A process-wide setting that can no longer change, e.g. the thread budget
+    /// once the shared runtime started.
+    AlreadyConfigured(String),
+    /// A setting refused as it stands, e.g. a thread budget without workers.
+    InvalidConfig(String),
+    /// No listener of this engine runs on the endpoint.
+    UnknownListener(Endpoint),
+    /// The listener could not start, see `ListenerHandle::wait_ready`.
+    ListenerFailed {
+        endpoint: Endpoint,
+        reason: String,
+    },
+    /// A BP send without source and without `Engine::with_bp_identity`.
+    NoBpIdentity,
+    /// An operation refused in strict mode, see `Engine::with_strict`.
+    Misuse(MisuseKind),
+    Connect(ConnectionFailureReason),
+    /// The engine's send queue is full, see `EngineConfig::send_queue_capacity`.
+    QueueFull,
+    /// A TCP listener closed a connection accepted beyond its limit, see
+    /// `Engine::with_max_connections`.
+    TooManyConnections {
+        remote: Endpoint,
+        max: usize,
+    },
+    /// The send was cancelled with `SendHandle::abort`.
+    Cancelled,
+    /// No response matched an `Engine::request` before its timeout.
+    NoResponse,
+    Send(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::io_error"))]
+        Arc<io::Error>,
+    ),
+    Receive(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::io_error"))]
+        Arc<io::Error>,
+    ),
+    /// A TCP peer sent a malformed length-prefixed stream.
+    Frame {
+        peer: Endpoint,
+        error: FrameError,
+    },
+    Shutdown(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::io_error"))]
+        Arc<io::Error>,
+    ),
+}
+
+impl SocketEngineError {
+    pub(crate) fn socket(e: io::Error) -> Self {
+        SocketEngineError::Socket(Arc::new(e))
+    }
+
+    pub(crate) fn bind(e: io::Error) -> Self {
+        SocketEngineError::Bind(Arc::new(e))
+    }
+
+    pub(crate) fn send(e: io::Error) -> Self {
+        SocketEngineError::Send(Arc::new(e))
+    }
+
+    pub(crate) fn receive(e: io::Error) -> Self {
+        SocketEngineError::Receive(Arc::new(e))
+    }
+}
+
+impl fmt::Display for SocketEngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketEngineError::Endpoint(e) => write!(f, "{}", e),
+            SocketEngineError::AddrParse(reason) => write!(f, "{}", reason),
+            SocketEngineError::UnsupportedScheme(scheme) => {
+                write!(f, "Unsupported scheme in endpoint: {}", scheme)
+            }
+            SocketEngineError::Socket(e) => write!(f, "Socket error: {}", e),
+            SocketEngineError::SocketMismatch(reason) => write!(f, "{}", reason),
+            SocketEngineError::Bind(e) => write!(f, "Bind failed: {}", e),
+            SocketEngineError::AlreadyInUse(endpoint) => {
+                write!(f, "{} is already in use by this engine", endpoint)
+            }
+            SocketEngineError::AlreadyConfigured(reason)
+            | SocketEngineError::InvalidConfig(reason) => write!(f, "{}", reason),
+            SocketEngineError::UnknownListener(endpoint) => {
+                write!(f, "No listener running on {}", endpoint)
+            }
+            SocketEngineError::ListenerFailed { endpoint, reason } => {
+                write!(f, "Could not listen on {}: {}", endpoint, reason)
+            }
+            SocketEngineError::NoBpIdentity => write!(
+                f,
+                "No BP identity configured: pass a source endpoint or set Engine::with_bp_identity"
+            ),
+            SocketEngineError::Misuse(kind) => write!(f, "Refused in strict mode: {:?}", kind),
+            SocketEngineError::Connect(reason) => write!(f, "Connection failed: {:?}", reason),
+            SocketEngineError::QueueFull => write!(f, "Send queue is full"),
+            SocketEngineError::TooManyConnections { remote, max } => write!(
+                f,
+                "Connection from {} refused: {} connections already open",
+                remote, max
+            ),
+            SocketEngineError::Cancelled => write!(f, "Send cancelled"),
+            SocketEngineError::NoResponse => write!(f, "No response before the timeout"),
+            SocketEngineError::Send(e) => write!(f, "Send failed: {}", e),
+            SocketEngineError::Receive(e) => write!(f, "Receive failed: {}", e),
+            SocketEngineError::Frame { peer, error } => write!(f, "{}: {}", peer, error),
+            SocketEngineError::Shutdown(e) => write!(f, "Shutdown failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SocketEngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SocketEngineError::Endpoint(e) => Some(e),
+            SocketEngineError::Socket(e)
+            | SocketEngineError::Bind(e)
+            | SocketEngineError::Send(e)
+            | SocketEngineError::Receive(e)
+            | SocketEngineError::Shutdown(e) => Some(e.as_ref()),
+            SocketEngineError::Frame { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<EndpointParseError> for SocketEngineError {
+    fn from(e: EndpointParseError) -> Self {
+        SocketEngineError::Endpoint(e)
+    }
+}
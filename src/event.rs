@@ -1,73 +1,187 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    fmt,
+    future::Future,
+    ops::BitOr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use crate::endpoint::Endpoint;
+use crate::{
+    endpoint::Endpoint,
+    error::SocketEngineError,
+    peer_state::{PeerState, PeerStateCause},
+};
+
+use tokio::runtime::Handle;
 
-#[cfg(feature = "with_delay")]
-use crate::engine::TOKIO_RUNTIME;
 #[cfg(feature = "with_delay")]
 use std::env;
 #[cfg(feature = "with_delay")]
-use tokio::time::{sleep, Duration};
+use tokio::time::sleep;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SocketEngineEvent {
     Data(DataEvent),
     Connection(ConnectionEvent),
     Error(ErrorEvent),
 }
 
+/// `local` is set when the payload was delivered by the engine to one of its own
+/// listeners without going through a socket (see `Engine::with_local_shortcut`).
+///
+/// `wire_bytes` counts what was handed to or read from the socket, payload plus
+/// engine overhead such as frame headers (0 for local deliveries). Headers added
+/// below the socket (UDP/IP, Ethernet) are not included.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DataEvent {
+    /// `listener` is the endpoint of the listener the data arrived on, and
+    /// `connection` the accepted TCP connection, to answer on with `Engine::reply`.
     Received {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::base64"))]
         data: Vec<u8>,
         from: Endpoint,
+        listener: Endpoint,
+        connection: Option<ConnectionId>,
+        wire_bytes: usize,
+        local: bool,
     },
     Sending {
-        token: String,
+        token: MessageId,
         to: Endpoint,
         bytes: usize,
+        local: bool,
     },
+    /// `from` is the bound endpoint the data left from, if any.
     Sent {
-        token: String,
+        token: MessageId,
         to: Endpoint,
         bytes_sent: usize,
+        wire_bytes: usize,
+        from: Option<Endpoint>,
+        local: bool,
+    },
+    /// The send was cancelled with `SendHandle::abort` or `Engine::cancel_send`
+    /// before its outcome was known. No `SendFailed` follows.
+    Cancelled { token: MessageId, to: Endpoint },
+    /// Attempt number `attempt` (from 1) of a send failed with a transient
+    /// error, the next one starts in `next_in`. See `RetryPolicy`.
+    Retrying {
+        token: MessageId,
+        to: Endpoint,
+        attempt: u32,
+        next_in: Duration,
+    },
+    /// Outcome of one `Engine::ping` probe, `rtt` is `None` when it was lost.
+    EchoReply {
+        to: Endpoint,
+        seq: u32,
+        rtt: Option<Duration>,
+    },
+    /// Echo probes from `from` sent back by the echo responder of `listener`
+    /// instead of being delivered. `bytes` were written back, frame headers
+    /// included. Over a raw TCP connection one event covers one read.
+    Echoed {
+        from: Endpoint,
+        listener: Endpoint,
+        bytes: usize,
     },
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConnectionEvent {
-    ListenerStarted { endpoint: Endpoint },
-    Established { remote: Endpoint },
-    Closed { remote: Option<Endpoint> },
+    ListenerStarted {
+        endpoint: Endpoint,
+    },
+    /// `messages` is the number of messages the listener delivered before stopping.
+    ListenerStopped {
+        endpoint: Endpoint,
+        reason: ListenerStopReason,
+        messages: u64,
+    },
+    /// An outgoing TCP connection was set up by a send.
+    Established {
+        remote: Endpoint,
+    },
+    /// The listener on `local` accepted an incoming TCP connection.
+    Accepted {
+        remote: Endpoint,
+        local: Endpoint,
+    },
+    Closed {
+        remote: Option<Endpoint>,
+    },
+    /// Another participant announced itself during `Engine::pair_with_code`.
+    PeerDiscovered {
+        endpoint: Endpoint,
+    },
+    Paired {
+        alias: String,
+        endpoint: Endpoint,
+    },
+    /// Emitted on transitions only, see `Engine::with_peer_states`.
+    PeerStateChanged {
+        endpoint: Endpoint,
+        old: PeerState,
+        new: PeerState,
+        cause: PeerStateCause,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ListenerStopReason {
+    LimitReached,
+    /// Stopped with `Engine::stop_listener`.
+    Stopped,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ErrorEvent {
     ConnectionFailed {
         endpoint: Endpoint,
         reason: ConnectionFailureReason,
-        token: String,
+        token: MessageId,
     },
     SendFailed {
         endpoint: Endpoint,
-        token: String,
-        reason: String,
+        token: MessageId,
+        error: SocketEngineError,
     },
     ReceiveFailed {
         endpoint: Endpoint,
-        reason: String,
+        error: SocketEngineError,
     },
     SocketError {
         endpoint: Endpoint,
-        reason: String,
+        error: SocketEngineError,
     },
+    /// An API misuse refused in strict mode.
+    Misuse { kind: MisuseKind, detail: String },
+    /// First occurrence of a misuse kind tolerated in lenient mode.
+    MisuseWarning { kind: MisuseKind, detail: String },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MisuseKind {
+    DuplicateObserver,
+    UnknownSource,
+    TokenReused,
+    UnsupportedProtocol,
 }
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConnectionFailureReason {
     Refused,
     Timeout,
     NetworkUnreachable,
+    /// The TLS handshake failed, e.g. the peer's certificate is not trusted.
+    Handshake,
     Other,
 }
 
@@ -82,32 +196,138 @@ impl ConnectionFailureReason {
     }
 }
 
+/// Event categories an observer receives, see `Engine::add_observer_filtered`.
+/// Categories combine with `|`, e.g. `EventMask::DATA | EventMask::ERROR`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EventMask(u8);
+
+impl EventMask {
+    pub const DATA: EventMask = EventMask(1);
+    pub const CONNECTION: EventMask = EventMask(1 << 1);
+    pub const ERROR: EventMask = EventMask(1 << 2);
+    pub const ALL: EventMask = EventMask(0b111);
+
+    pub fn contains(self, other: EventMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether `event` belongs to one of the categories of the mask.
+    pub fn matches(self, event: &SocketEngineEvent) -> bool {
+        self.contains(match event {
+            SocketEngineEvent::Data(_) => Self::DATA,
+            SocketEngineEvent::Connection(_) => Self::CONNECTION,
+            SocketEngineEvent::Error(_) => Self::ERROR,
+        })
+    }
+}
+
+impl Default for EventMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for EventMask {
+    type Output = EventMask;
+
+    fn bitor(self, rhs: EventMask) -> EventMask {
+        EventMask(self.0 | rhs.0)
+    }
+}
+
 pub trait EngineObserver: Send + Sync {
     fn on_engine_event(&mut self, event: SocketEngineEvent);
 }
 
+/// An observer handling events asynchronously, registered with
+/// `Engine::add_async_observer`. Implement it with an `async fn`.
+pub trait AsyncEngineObserver: Send + Sync + 'static {
+    fn on_engine_event(&self, event: SocketEngineEvent) -> impl Future<Output = ()> + Send;
+}
+
+pub(crate) type SharedObserver = Arc<Mutex<dyn EngineObserver + Send + Sync>>;
+pub(crate) type Observers = Vec<SharedObserver>;
+
+/// Identifies a send in its events, see `SendOptions::token`. Any string can be
+/// used; the engine generates `send-<n>` ones when none is given.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct MessageId(String);
+
+impl MessageId {
+    /// A random UUID (version 4), for ids that must stay unique across runs.
+    /// Drawn from the operating system's random source.
+    pub fn new_v4() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for MessageId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&str> for MessageId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+/// A TCP connection accepted by one of the engine's listeners, see `Engine::reply`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionId(pub(crate) u64);
+
+/// Registration handle returned by `Engine::add_observer`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(pub(crate) u64);
+
 pub fn notify_all_observers(
     observers: &Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
     event: &SocketEngineEvent,
 ) {
-    #[cfg(feature = "with_delay")]
-    let delay_ms = env::var("ENGINE_RECEIVE_DELAY_MS")
-        .ok()
-        .and_then(|v| v.parse::<u64>().ok())
-        .unwrap_or(1000);
+    #[cfg(feature = "tracing")]
+    crate::tracing_support::trace_event(event);
     for obs in observers {
-        #[cfg(feature = "with_delay")]
-        {
-            if let SocketEngineEvent::Data(DataEvent::Received { .. }) = event {
-                let obs_clone = obs.clone();
-                let event_clone = event.clone();
-                TOKIO_RUNTIME.spawn(async move {
-                    sleep(Duration::from_millis(delay_ms)).await;
-                    obs_clone.lock().unwrap().on_engine_event(event_clone);
-                });
-                continue;
-            }
-        }
         obs.lock().unwrap().on_engine_event(event.clone());
     }
 }
+
+/// Notifies a `Received` event. With the `with_delay` feature, observers get it
+/// `ENGINE_RECEIVE_DELAY_MS` (1000 by default) later, from a task on `runtime`,
+/// the runtime of the engine that received it.
+pub(crate) fn notify_received(observers: &Observers, event: &SocketEngineEvent, runtime: &Handle) {
+    #[cfg(not(feature = "with_delay"))]
+    {
+        let _ = runtime;
+        notify_all_observers(observers, event);
+    }
+    #[cfg(feature = "with_delay")]
+    {
+        let delay_ms = env::var("ENGINE_RECEIVE_DELAY_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(1000);
+        let observers = observers.clone();
+        let event = event.clone();
+        runtime.spawn(async move {
+            sleep(Duration::from_millis(delay_ms)).await;
+            notify_all_observers(&observers, &event);
+        });
+    }
+}
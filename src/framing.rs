@@ -0,0 +1,331 @@
+use std::{fmt, sync::Arc};
+
+/// Size of the big-endian length prefix written in front of every frame.
+pub const FRAME_HEADER_LEN: usize = 4;
+/// Largest payload a peer may announce before the connection is dropped.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Puts `payload` behind its length, failing for payloads larger than
+/// `max_frame_size` since the peer would drop the connection on receiving them.
+pub fn encode_frame(payload: &[u8], max_frame_size: usize) -> Result<Vec<u8>, FrameError> {
+    // Larger payloads do not fit in the prefix
+    let max = max_frame_size.min(u32::MAX as usize);
+    if payload.len() > max {
+        return Err(FrameError::PayloadExceedsMax {
+            len: payload.len(),
+            max,
+        });
+    }
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Framing failures, with `offset` being the position in the stream where the
+/// offending frame (its length prefix) starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameError {
+    PrefixExceedsMax {
+        declared: usize,
+        max: usize,
+        offset: usize,
+    },
+    StreamEndedMidFrame {
+        expected: usize,
+        received: usize,
+        offset: usize,
+    },
+    /// A delimited message grew past `max` bytes before its delimiter arrived.
+    MessageExceedsMax { max: usize, offset: usize },
+    /// The stream ended after `received` bytes of a message without its delimiter.
+    UnterminatedMessage { received: usize, offset: usize },
+    /// A payload of `len` bytes to send is larger than the `max` a message may be.
+    PayloadExceedsMax { len: usize, max: usize },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::PrefixExceedsMax {
+                declared,
+                max,
+                offset,
+            } => write!(
+                f,
+                "frame at byte offset {} declares {} bytes, exceeding the maximum of {}",
+                offset, declared, max
+            ),
+            FrameError::StreamEndedMidFrame {
+                expected,
+                received,
+                offset,
+            } => write!(
+                f,
+                "stream ended mid-frame at byte offset {}: expected {} bytes, received {}",
+                offset, expected, received
+            ),
+            FrameError::MessageExceedsMax { max, offset } => write!(
+                f,
+                "message at byte offset {} exceeds the maximum of {} bytes",
+                offset, max
+            ),
+            FrameError::UnterminatedMessage { received, offset } => write!(
+                f,
+                "stream ended mid-message at byte offset {}: {} bytes without a delimiter",
+                offset, received
+            ),
+            FrameError::PayloadExceedsMax { len, max } => write!(
+                f,
+                "payload of {} bytes exceeds the maximum of {} bytes",
+                len, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Reassembles length-prefixed frames from arbitrary read boundaries.
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+    // Stream offset of the first byte still held in `buffer`
+    offset: usize,
+}
+
+impl FrameDecoder {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_frame_size,
+            offset: 0,
+        }
+    }
+
+    /// Feeds freshly read bytes and returns every frame they complete.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, FrameError> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut frames = Vec::new();
+        let mut start = 0;
+        while self.buffer.len() - start >= FRAME_HEADER_LEN {
+            let mut header = [0u8; FRAME_HEADER_LEN];
+            header.copy_from_slice(&self.buffer[start..start + FRAME_HEADER_LEN]);
+            let declared = u32::from_be_bytes(header) as usize;
+            if declared > self.max_frame_size {
+                return Err(FrameError::PrefixExceedsMax {
+                    declared,
+                    max: self.max_frame_size,
+                    offset: self.offset + start,
+                });
+            }
+
+            let end = start + FRAME_HEADER_LEN + declared;
+            if self.buffer.len() < end {
+                break;
+            }
+            frames.push(self.buffer[start + FRAME_HEADER_LEN..end].to_vec());
+            start = end;
+        }
+
+        self.buffer.drain(..start);
+        self.offset += start;
+        Ok(frames)
+    }
+
+    /// Checks that the stream did not end in the middle of a frame.
+    pub fn finish(&self) -> Result<(), FrameError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.buffer.len() < FRAME_HEADER_LEN {
+            return Err(FrameError::StreamEndedMidFrame {
+                expected: FRAME_HEADER_LEN,
+                received: self.buffer.len(),
+                offset: self.offset,
+            });
+        }
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        header.copy_from_slice(&self.buffer[..FRAME_HEADER_LEN]);
+        Err(FrameError::StreamEndedMidFrame {
+            expected: u32::from_be_bytes(header) as usize,
+            received: self.buffer.len() - FRAME_HEADER_LEN,
+            offset: self.offset,
+        })
+    }
+}
+
+/// Splits the bytes read from a TCP connection into the messages delivered as
+/// `Received` events, whatever the read boundaries. Selected per listener with
+/// `Framing`.
+pub trait MessageAssembler: Send {
+    /// Feeds freshly read bytes and returns every message they complete. An error
+    /// makes the listener report `ReceiveFailed` and drop the connection.
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, FrameError>;
+
+    /// Checks that the stream did not end in the middle of a message.
+    fn finish(&self) -> Result<(), FrameError> {
+        Ok(())
+    }
+
+    /// Delimits a payload written back to the peer (replies, echo probes). An
+    /// error fails the reply without writing anything.
+    fn encode(&self, payload: Vec<u8>) -> Result<Vec<u8>, FrameError> {
+        Ok(payload)
+    }
+
+    /// Bytes the delimiting adds to each message, counted in `wire_bytes`.
+    fn overhead(&self) -> usize {
+        0
+    }
+}
+
+/// Delivers every read as it is, the historical behaviour.
+#[derive(Debug, Default)]
+pub struct RawPassthrough;
+
+impl MessageAssembler for RawPassthrough {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, FrameError> {
+        Ok(vec![bytes.to_vec()])
+    }
+}
+
+/// Messages behind a 4-byte big-endian length, as sent with
+/// `Engine::with_length_prefix_framing`.
+pub type LengthPrefixed = FrameDecoder;
+
+impl MessageAssembler for FrameDecoder {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, FrameError> {
+        FrameDecoder::push(self, bytes)
+    }
+
+    fn finish(&self) -> Result<(), FrameError> {
+        FrameDecoder::finish(self)
+    }
+
+    fn encode(&self, payload: Vec<u8>) -> Result<Vec<u8>, FrameError> {
+        encode_frame(&payload, self.max_frame_size)
+    }
+
+    fn overhead(&self) -> usize {
+        FRAME_HEADER_LEN
+    }
+}
+
+/// Messages ended by `\n`, which is not part of the delivered payload.
+pub struct NewlineDelimited {
+    buffer: Vec<u8>,
+    max_message_size: usize,
+    // Stream offset of the first byte still held in `buffer`
+    offset: usize,
+}
+
+impl NewlineDelimited {
+    pub fn new(max_message_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_message_size,
+            offset: 0,
+        }
+    }
+}
+
+impl MessageAssembler for NewlineDelimited {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, FrameError> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut messages = Vec::new();
+        let mut start = 0;
+        while let Some(len) = self.buffer[start..].iter().position(|&b| b == b'\n') {
+            if len > self.max_message_size {
+                break;
+            }
+            messages.push(self.buffer[start..start + len].to_vec());
+            start += len + 1;
+        }
+        if self.buffer.len() - start > self.max_message_size {
+            return Err(FrameError::MessageExceedsMax {
+                max: self.max_message_size,
+                offset: self.offset + start,
+            });
+        }
+
+        self.buffer.drain(..start);
+        self.offset += start;
+        Ok(messages)
+    }
+
+    fn finish(&self) -> Result<(), FrameError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        Err(FrameError::UnterminatedMessage {
+            received: self.buffer.len(),
+            offset: self.offset,
+        })
+    }
+
+    fn encode(&self, mut payload: Vec<u8>) -> Result<Vec<u8>, FrameError> {
+        if payload.len() > self.max_message_size {
+            return Err(FrameError::PayloadExceedsMax {
+                len: payload.len(),
+                max: self.max_message_size,
+            });
+        }
+        payload.push(b'\n');
+        Ok(payload)
+    }
+
+    fn overhead(&self) -> usize {
+        1
+    }
+}
+
+/// How a listener splits TCP streams into messages, see
+/// `Engine::start_listener_with_framing`.
+#[derive(Clone)]
+pub enum Framing {
+    Raw,
+    LengthPrefixed {
+        max_frame_size: usize,
+    },
+    NewlineDelimited {
+        max_message_size: usize,
+    },
+    /// Builds a fresh assembler for every accepted connection.
+    Custom(Arc<dyn Fn() -> Box<dyn MessageAssembler> + Send + Sync>),
+}
+
+impl Framing {
+    pub fn assembler(&self) -> Box<dyn MessageAssembler> {
+        match self {
+            Framing::Raw => Box::new(RawPassthrough),
+            Framing::LengthPrefixed { max_frame_size } => {
+                Box::new(FrameDecoder::new(*max_frame_size))
+            }
+            Framing::NewlineDelimited { max_message_size } => {
+                Box::new(NewlineDelimited::new(*max_message_size))
+            }
+            Framing::Custom(build) => build(),
+        }
+    }
+}
+
+impl fmt::Debug for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Framing::Raw => f.write_str("Raw"),
+            Framing::LengthPrefixed { max_frame_size } => f
+                .debug_struct("LengthPrefixed")
+                .field("max_frame_size", max_frame_size)
+                .finish(),
+            Framing::NewlineDelimited { max_message_size } => f
+                .debug_struct("NewlineDelimited")
+                .field("max_message_size", max_message_size)
+                .finish(),
+            Framing::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
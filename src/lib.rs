@@ -1,4 +1,38 @@
-pub mod endpoint;
+//! Engine sending and receiving over UDP, TCP and BP sockets. The supported API
+//! is `prelude`, with `config` and `runtime` for the settings of engines and of
+//! their shared runtime; the hidden modules are the engine's own helpers.
+
+#[cfg(feature = "bp")]
+#[doc(hidden)]
+pub mod bp;
+pub mod config;
+#[doc(hidden)]
+pub mod echo;
+mod endpoint;
+#[doc(hidden)]
 pub mod engine;
-pub mod event;
+mod error;
+mod event;
+#[doc(hidden)]
+pub mod framing;
+mod pairing;
+#[doc(hidden)]
+pub mod peer_state;
+mod poll;
+mod pool;
+pub mod prelude;
+mod rate_limit;
+mod request;
+mod retry;
+pub mod runtime;
+mod sender;
+#[cfg(feature = "serde")]
+mod serde_support;
+#[doc(hidden)]
 pub mod socket;
+#[doc(hidden)]
+pub mod stats;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tracing")]
+mod tracing_support;
@@ -3,15 +3,14 @@ use std::io::{self, BufRead, Write};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
-use socket_engine::endpoint::{Endpoint, EndpointProto};
-use socket_engine::engine::Engine;
-use socket_engine::event::EngineObserver;
+use socket_engine::prelude::*;
 
 fn format_endpoint(endpoint: &Endpoint) -> String {
     let addr = endpoint.endpoint.clone();
     match endpoint.proto {
         EndpointProto::Udp => format!("UDP:{}", addr),
         EndpointProto::Tcp => format!("TCP:{}", addr),
+        #[cfg(feature = "bp")]
         EndpointProto::Bp => format!("BP:{}", addr),
     }
 }
@@ -21,50 +20,128 @@ static WAITING_FOR_INPUT: AtomicBool = AtomicBool::new(false);
 struct Obs;
 
 impl EngineObserver for Obs {
-    fn on_engine_event(&mut self, event: socket_engine::event::SocketEngineEvent) {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
         // Clear current line if we're waiting for input
         if WAITING_FOR_INPUT.load(Ordering::Relaxed) {
             print!("\r\x1b[K"); // Clear current line
         }
 
         match event {
-            socket_engine::event::SocketEngineEvent::Data(data_event) => match data_event {
-                socket_engine::event::DataEvent::Received { data, from } => {
+            SocketEngineEvent::Data(data_event) => match data_event {
+                DataEvent::Received { data, from, .. } => {
                     println!(
                         "[RECV] From {}: \"{}\"",
                         format_endpoint(&from),
                         String::from_utf8_lossy(&data).trim()
                     );
                 }
-                socket_engine::event::DataEvent::Sent {
+                DataEvent::Sent {
                     token: _,
                     to,
                     bytes_sent,
+                    wire_bytes,
+                    ..
                 } => {
-                    println!("[SENT] To {} ({} bytes)", format_endpoint(&to), bytes_sent);
+                    println!(
+                        "[SENT] To {} ({} bytes, {} on the wire)",
+                        format_endpoint(&to),
+                        bytes_sent,
+                        wire_bytes
+                    );
                 }
-                socket_engine::event::DataEvent::Sending {
+                DataEvent::Sending {
                     token: message_id,
                     to,
                     bytes,
+                    ..
                 } => {
                     println!(
                         "[SENDING] To {} ({} bytes, token: {})",
                         to, bytes, message_id
                     );
                 }
+                DataEvent::Cancelled { token, to } => {
+                    println!("[CANCELLED] To {} (token: {})", format_endpoint(&to), token);
+                }
+                DataEvent::Retrying {
+                    to,
+                    attempt,
+                    next_in,
+                    ..
+                } => {
+                    println!(
+                        "[RETRY] Attempt {} to {} failed, retrying in {} ms",
+                        attempt,
+                        format_endpoint(&to),
+                        next_in.as_millis()
+                    );
+                }
+                DataEvent::EchoReply { to, seq, rtt } => match rtt {
+                    Some(rtt) => println!(
+                        "[PING] Reply from {} seq={} time={:.2} ms",
+                        format_endpoint(&to),
+                        seq,
+                        rtt.as_secs_f64() * 1000.0
+                    ),
+                    None => println!("[PING] No reply from {} seq={}", format_endpoint(&to), seq),
+                },
+                // Probes of other engines pinging this one
+                DataEvent::Echoed { .. } => {}
             },
-            socket_engine::event::SocketEngineEvent::Connection(conn_event) => match conn_event {
-                socket_engine::event::ConnectionEvent::ListenerStarted { endpoint } => {
+            SocketEngineEvent::Connection(conn_event) => match conn_event {
+                ConnectionEvent::ListenerStarted { endpoint } => {
                     println!("[INFO] Listener started on {}", format_endpoint(&endpoint));
                 }
-                socket_engine::event::ConnectionEvent::Established { remote } => {
+                ConnectionEvent::ListenerStopped {
+                    endpoint,
+                    reason,
+                    messages,
+                } => {
+                    println!(
+                        "[INFO] Listener stopped on {} ({:?}, {} messages)",
+                        format_endpoint(&endpoint),
+                        reason,
+                        messages
+                    );
+                }
+                ConnectionEvent::Established { remote } => {
                     println!(
                         "[INFO] Connection established with {}",
                         format_endpoint(&remote)
                     );
                 }
-                socket_engine::event::ConnectionEvent::Closed { remote } => {
+                ConnectionEvent::Accepted { remote, local } => {
+                    println!(
+                        "[INFO] Accepted connection from {} on {}",
+                        format_endpoint(&remote),
+                        format_endpoint(&local)
+                    );
+                }
+                ConnectionEvent::PeerDiscovered { endpoint } => {
+                    println!("[INFO] Discovered peer {}", format_endpoint(&endpoint));
+                }
+                ConnectionEvent::Paired { alias, endpoint } => {
+                    println!(
+                        "[INFO] Paired with {} as `{}`",
+                        format_endpoint(&endpoint),
+                        alias
+                    );
+                }
+                ConnectionEvent::PeerStateChanged {
+                    endpoint,
+                    old,
+                    new,
+                    cause,
+                } => {
+                    println!(
+                        "[INFO] Peer {} went from {:?} to {:?} ({:?})",
+                        format_endpoint(&endpoint),
+                        old,
+                        new,
+                        cause
+                    );
+                }
+                ConnectionEvent::Closed { remote } => {
                     if let Some(remote) = remote {
                         println!("[INFO] Connection closed with {}", format_endpoint(&remote));
                     } else {
@@ -72,8 +149,8 @@ impl EngineObserver for Obs {
                     }
                 }
             },
-            socket_engine::event::SocketEngineEvent::Error(err_event) => match err_event {
-                socket_engine::event::ErrorEvent::ConnectionFailed {
+            SocketEngineEvent::Error(err_event) => match err_event {
+                ErrorEvent::ConnectionFailed {
                     endpoint,
                     reason: _,
                     token,
@@ -84,32 +161,38 @@ impl EngineObserver for Obs {
                         token
                     );
                 }
-                socket_engine::event::ErrorEvent::SendFailed {
+                ErrorEvent::SendFailed {
                     endpoint,
                     token,
-                    reason,
+                    error,
                 } => {
                     println!(
                         "[ERROR] Send failed to {} for id {}: {}",
                         format_endpoint(&endpoint),
                         token,
-                        reason
+                        error
                     );
                 }
-                socket_engine::event::ErrorEvent::ReceiveFailed { endpoint, reason } => {
+                ErrorEvent::ReceiveFailed { endpoint, error } => {
                     println!(
                         "[ERROR] Receive failed from {}: {}",
                         format_endpoint(&endpoint),
-                        reason
+                        error
                     );
                 }
-                socket_engine::event::ErrorEvent::SocketError { endpoint, reason } => {
+                ErrorEvent::SocketError { endpoint, error } => {
                     println!(
                         "[ERROR] Socket error on {}: {}",
                         format_endpoint(&endpoint),
-                        reason
+                        error
                     );
                 }
+                ErrorEvent::Misuse { kind, detail } => {
+                    println!("[ERROR] Misuse ({:?}): {}", kind, detail);
+                }
+                ErrorEvent::MisuseWarning { kind, detail } => {
+                    println!("[WARN] Misuse ({:?}): {}", kind, detail);
+                }
             },
         }
 
@@ -124,45 +207,72 @@ impl EngineObserver for Obs {
 fn main() -> io::Result<()> {
     // --- 1) parse CLI argument
     let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        eprintln!("Usage: {} <local-endpoint> <distant-endpoint>", args[0]);
-        eprintln!(
-            "Example: {} \"udp 127.0.0.1:8888\" \"udp 127.0.0.1:9999\"",
-            args[0]
-        );
-        std::process::exit(1);
-    }
-
-    let local_endpoint = match Endpoint::from_str(&args[1]) {
-        Ok(ep) => ep,
-        Err(e) => {
-            eprintln!("[ERROR] Invalid local endpoint `{}`: {}", args[1], e);
+    let pair_code = match args.len() {
+        3 => None,
+        4 if args[2] == "--pair" => Some(args[3].clone()),
+        _ => {
+            eprintln!(
+                "Usage: {} <local-endpoint> (<distant-endpoint> | --pair <code>)",
+                args[0]
+            );
+            eprintln!(
+                "Example: {} \"udp 127.0.0.1:8888\" \"udp 127.0.0.1:9999\"",
+                args[0]
+            );
+            eprintln!("Example: {} \"udp 0.0.0.0:8888\" --pair workshop", args[0]);
             std::process::exit(1);
         }
     };
-    let distant_endpoint = match Endpoint::from_str(&args[2]) {
+
+    let local_endpoint = match args[1].parse::<Endpoint>() {
         Ok(ep) => ep,
         Err(e) => {
-            eprintln!("[ERROR] Invalid distant endpoint `{}`: {}", args[2], e);
+            eprintln!("[ERROR] Invalid local endpoint `{}`: {}", args[1], e);
             std::process::exit(1);
         }
     };
 
     println!("Socket Engine Starting...");
     println!("Local endpoint:  {}", format_endpoint(&local_endpoint));
-    println!("Remote endpoint: {}", format_endpoint(&distant_endpoint));
-    println!("─────────────────────────────────────────");
-    println!("Type 'quit' or 'exit' to stop the program");
-    println!();
 
     // --- 2) create engine + observer
     let observer = Arc::new(Mutex::new(Obs));
-    let mut engine = Engine::new();
+    let runtime = tokio::runtime::Runtime::new()?;
+    let engine = Engine::new().with_runtime(runtime.handle().clone());
     engine.add_observer(observer);
-    let _ = engine.start_listener_async(local_endpoint.clone());
+    engine.enable_echo_responder(local_endpoint.clone());
+    let listener = engine.start_listener_async(local_endpoint.clone());
+    if let Err(e) = runtime.block_on(listener.wait_ready()) {
+        eprintln!("[ERROR] Could not listen on {}: {}", local_endpoint, e);
+        std::process::exit(1);
+    }
 
-    // Give some time for the listener to start
-    std::thread::sleep(std::time::Duration::from_millis(100));
+    let distant_endpoint = match pair_code {
+        Some(code) => {
+            println!("Waiting for the other participant using code `{}`...", code);
+            match engine.pair_with_code(&code, std::time::Duration::from_secs(60)) {
+                Ok(ep) => ep,
+                Err(e) => {
+                    eprintln!("[ERROR] Pairing failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => match args[2].parse::<Endpoint>() {
+            Ok(ep) => ep,
+            Err(e) => {
+                eprintln!("[ERROR] Invalid distant endpoint `{}`: {}", args[2], e);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    println!("Remote endpoint: {}", format_endpoint(&distant_endpoint));
+    println!("─────────────────────────────────────────");
+    println!("Type 'quit' or 'exit' to stop the program");
+    println!("Type '/ping <count>' to measure the round-trip time to the remote endpoint");
+    println!("Type '/listen <endpoint> [--once]' to open another listener, '--once' closing it after one message");
+    println!();
 
     // --- 3) read lines from stdin
     let stdin = io::stdin();
@@ -194,12 +304,58 @@ fn main() -> io::Result<()> {
             break;
         }
 
-        // --- 4) wrap in ProtoMessage + send
-        engine.send_async(
-            Some(local_endpoint.clone()),
+        if let Some(count) = text.strip_prefix("/ping") {
+            let count = count.trim().parse().unwrap_or(4);
+            let ping = engine.ping(
+                distant_endpoint.clone(),
+                64,
+                count,
+                std::time::Duration::from_secs(1),
+            );
+            match runtime.block_on(ping) {
+                Ok(report) => println!(
+                    "[PING] {} sent, {} received, {:.0}% loss, min/avg/max/p95 = {:?}/{:?}/{:?}/{:?}",
+                    report.sent,
+                    report.received,
+                    report.loss() * 100.0,
+                    report.min.unwrap_or_default(),
+                    report.avg.unwrap_or_default(),
+                    report.max.unwrap_or_default(),
+                    report.p95.unwrap_or_default()
+                ),
+                Err(e) => println!("[ERROR] Ping failed: {}", e),
+            }
+            continue;
+        }
+
+        if let Some(spec) = text.strip_prefix("/listen ") {
+            let (spec, once) = match spec.trim().strip_suffix("--once") {
+                Some(spec) => (spec.trim(), true),
+                None => (spec.trim(), false),
+            };
+            let endpoint = match spec.parse::<Endpoint>() {
+                Ok(ep) => ep,
+                Err(e) => {
+                    println!("[ERROR] Invalid endpoint `{}`: {}", spec, e);
+                    continue;
+                }
+            };
+            let limits = ListenerLimits {
+                max_messages: once.then_some(1),
+                max_duration: None,
+            };
+            let listener = engine.start_listener_with_limits(endpoint.clone(), limits);
+            if let Err(e) = runtime.block_on(listener.wait_ready()) {
+                println!("[ERROR] Could not listen on {}: {}", endpoint, e);
+            }
+            continue;
+        }
+
+        // --- 4) send, failures are printed by the observer
+        let _ = engine.send(
             distant_endpoint.clone(),
             text.into_bytes(),
-            "msg".to_string(),
+            SendOptions::default().source(local_endpoint.clone()),
         );
     }
 
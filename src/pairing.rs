@@ -0,0 +1,185 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::{BuildHasher, Hasher},
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{
+    endpoint::Endpoint,
+    event::{notify_all_observers, ConnectionEvent, EngineObserver, SocketEngineEvent},
+    socket::retry_on_eintr,
+};
+
+/// Alias under which the paired peer is registered.
+pub const PAIR_ALIAS: &str = "pair";
+
+const ANNOUNCE_MAGIC: &str = "SEPAIR1";
+const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(200);
+// Once a peer is seen, keep announcing so it sees us too, and watch for a third participant
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum PairingError {
+    /// The engine has no listener whose endpoint could be announced.
+    NoListener,
+    Timeout,
+    /// More than two participants announced themselves with the same code.
+    Ambiguous {
+        participants: usize,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for PairingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PairingError::NoListener => write!(f, "No listener to announce, start one first"),
+            PairingError::Timeout => write!(f, "No other participant found before the timeout"),
+            PairingError::Ambiguous { participants } => write!(
+                f,
+                "{} participants use this pairing code, expected exactly 2",
+                participants
+            ),
+            PairingError::Io(e) => write!(f, "Pairing socket error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PairingError {}
+
+impl From<io::Error> for PairingError {
+    fn from(e: io::Error) -> Self {
+        PairingError::Io(e)
+    }
+}
+
+// FNV-1a, stable across processes so both sides derive the same group
+fn code_hash(code: &str) -> u64 {
+    code.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Multicast group and port derived from a pairing code.
+pub fn pairing_address(code: &str) -> SocketAddrV4 {
+    let hash = code_hash(code);
+    let group = Ipv4Addr::new(239, 255, (hash >> 8) as u8, (hash as u8).max(1));
+    let port = 20000 + ((hash >> 16) % 10000) as u16;
+    SocketAddrV4::new(group, port)
+}
+
+fn open_socket(address: SocketAddrV4) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, address.port())).into())?;
+    socket.join_multicast_v4(address.ip(), &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    Ok(socket.into())
+}
+
+// Announcements advertising 0.0.0.0 are completed with the address they came from
+fn parse_announcement(data: &[u8], from: SocketAddr) -> Option<(u64, Endpoint)> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut parts = text.splitn(3, ' ');
+    if parts.next()? != ANNOUNCE_MAGIC {
+        return None;
+    }
+    let nonce = u64::from_str_radix(parts.next()?, 16).ok()?;
+    let mut endpoint = parts.next()?.parse::<Endpoint>().ok()?;
+    if !endpoint.proto.is_bp() {
+        if let Ok(addr) = endpoint.endpoint.parse::<SocketAddr>() {
+            if addr.ip().is_unspecified() {
+                endpoint.endpoint = SocketAddr::new(from.ip(), addr.port()).to_string();
+            }
+        }
+    }
+    Some((nonce, endpoint))
+}
+
+pub(crate) fn run_pairing(
+    observers: &Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
+    code: &str,
+    advertised: &Endpoint,
+    timeout: Duration,
+) -> Result<Endpoint, PairingError> {
+    let address = pairing_address(code);
+    let socket = open_socket(address)?;
+    let nonce = RandomState::new().build_hasher().finish();
+    let announcement = format!("{} {:016x} {}", ANNOUNCE_MAGIC, nonce, advertised);
+
+    let deadline = Instant::now() + timeout;
+    let mut next_announce = Instant::now();
+    let mut settle_until: Option<Instant> = None;
+    let mut peers: HashMap<u64, Endpoint> = HashMap::new();
+    let mut buffer = [0u8; 512];
+
+    loop {
+        let now = Instant::now();
+        if now >= next_announce {
+            retry_on_eintr(|| socket.send_to(announcement.as_bytes(), address))?;
+            next_announce = now + ANNOUNCE_INTERVAL;
+        }
+        match settle_until {
+            Some(until) if now >= until => break,
+            None if now >= deadline => return Err(PairingError::Timeout),
+            _ => {}
+        }
+
+        socket.set_read_timeout(Some(
+            next_announce
+                .saturating_duration_since(now)
+                .max(Duration::from_millis(1)),
+        ))?;
+        let (size, from) = match retry_on_eintr(|| socket.recv_from(&mut buffer)) {
+            Ok(res) => res,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let Some((peer_nonce, endpoint)) = parse_announcement(&buffer[..size], from) else {
+            continue;
+        };
+        if peer_nonce == nonce || peers.contains_key(&peer_nonce) {
+            continue;
+        }
+
+        notify_all_observers(
+            observers,
+            &SocketEngineEvent::Connection(ConnectionEvent::PeerDiscovered {
+                endpoint: endpoint.clone(),
+            }),
+        );
+        peers.insert(peer_nonce, endpoint);
+        if peers.len() > 1 {
+            // Keep announcing for a while, the others may not have seen us yet
+            // and would pair with each other
+            settle_until = Some(Instant::now() + SETTLE_TIME);
+        } else {
+            settle_until.get_or_insert(Instant::now() + SETTLE_TIME);
+        }
+    }
+
+    if peers.len() > 1 {
+        return Err(PairingError::Ambiguous {
+            participants: peers.len() + 1,
+        });
+    }
+    let endpoint = peers.into_values().next().ok_or(PairingError::Timeout)?;
+    notify_all_observers(
+        observers,
+        &SocketEngineEvent::Connection(ConnectionEvent::Paired {
+            alias: PAIR_ALIAS.to_string(),
+            endpoint: endpoint.clone(),
+        }),
+    );
+    Ok(endpoint)
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    endpoint::Endpoint,
+    event::{
+        notify_all_observers, ConnectionEvent, DataEvent, EngineObserver, ErrorEvent, Observers,
+        SocketEngineEvent,
+    },
+};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PeerState {
+    #[default]
+    Unknown,
+    Reachable,
+    /// Failed recently, but fewer times in a row than the unreachable threshold.
+    Degraded,
+    Unreachable,
+}
+
+/// Event that moved a peer to a new state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PeerStateCause {
+    Sent,
+    Established,
+    EchoReply,
+    SendFailed,
+    ConnectionFailed,
+    EchoLost,
+}
+
+impl PeerStateCause {
+    fn is_failure(self) -> bool {
+        matches!(
+            self,
+            PeerStateCause::SendFailed
+                | PeerStateCause::ConnectionFailed
+                | PeerStateCause::EchoLost
+        )
+    }
+}
+
+/// Consecutive failures after which a peer becomes `Degraded`, then `Unreachable`.
+/// A single success makes it `Reachable` again.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeerStateThresholds {
+    pub degraded_after: u32,
+    pub unreachable_after: u32,
+}
+
+impl Default for PeerStateThresholds {
+    fn default() -> Self {
+        Self {
+            degraded_after: 1,
+            unreachable_after: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerStateChange {
+    pub endpoint: Endpoint,
+    pub old: PeerState,
+    pub new: PeerState,
+    pub cause: PeerStateCause,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct PeerRecord {
+    state: PeerState,
+    failures: u32,
+}
+
+/// Per-peer state machine fed with engine events.
+///
+/// Only the order of the events matters, so the same sequence always produces
+/// the same transitions.
+#[derive(Clone, Debug, Default)]
+pub struct PeerStateTracker {
+    thresholds: PeerStateThresholds,
+    peers: HashMap<Endpoint, PeerRecord>,
+}
+
+impl PeerStateTracker {
+    pub fn new(thresholds: PeerStateThresholds) -> Self {
+        Self {
+            thresholds,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Peer and outcome carried by `event`, if it says anything about a peer.
+    fn outcome(event: &SocketEngineEvent) -> Option<(&Endpoint, PeerStateCause)> {
+        match event {
+            SocketEngineEvent::Data(DataEvent::Sent { to, .. }) => Some((to, PeerStateCause::Sent)),
+            SocketEngineEvent::Data(DataEvent::EchoReply { to, rtt, .. }) => Some((
+                to,
+                match rtt {
+                    Some(_) => PeerStateCause::EchoReply,
+                    None => PeerStateCause::EchoLost,
+                },
+            )),
+            SocketEngineEvent::Connection(ConnectionEvent::Established { remote }) => {
+                Some((remote, PeerStateCause::Established))
+            }
+            SocketEngineEvent::Error(ErrorEvent::SendFailed { endpoint, .. }) => {
+                Some((endpoint, PeerStateCause::SendFailed))
+            }
+            SocketEngineEvent::Error(ErrorEvent::ConnectionFailed { endpoint, .. }) => {
+                Some((endpoint, PeerStateCause::ConnectionFailed))
+            }
+            _ => None,
+        }
+    }
+
+    /// Applies one event, returning the transition it caused.
+    pub fn apply(&mut self, event: &SocketEngineEvent) -> Option<PeerStateChange> {
+        let (endpoint, cause) = Self::outcome(event)?;
+        let thresholds = self.thresholds;
+        let record = self.peers.entry(endpoint.clone()).or_default();
+        let old = record.state;
+        if cause.is_failure() {
+            record.failures = record.failures.saturating_add(1);
+            if record.failures >= thresholds.unreachable_after {
+                record.state = PeerState::Unreachable;
+            } else if record.failures >= thresholds.degraded_after {
+                record.state = PeerState::Degraded;
+            }
+        } else {
+            record.failures = 0;
+            record.state = PeerState::Reachable;
+        }
+
+        (record.state != old).then(|| PeerStateChange {
+            endpoint: endpoint.clone(),
+            old,
+            new: record.state,
+            cause,
+        })
+    }
+
+    pub fn state(&self, endpoint: &Endpoint) -> PeerState {
+        self.peers
+            .get(endpoint)
+            .map_or(PeerState::Unknown, |record| record.state)
+    }
+
+    pub fn states(&self) -> HashMap<Endpoint, PeerState> {
+        self.peers
+            .iter()
+            .map(|(endpoint, record)| (endpoint.clone(), record.state))
+            .collect()
+    }
+}
+
+// Feeds the tracker and reports transitions to the engine's other observers
+pub(crate) struct PeerStateObserver {
+    pub(crate) tracker: Arc<Mutex<PeerStateTracker>>,
+    pub(crate) subscribers: Arc<Mutex<Observers>>,
+}
+
+impl EngineObserver for PeerStateObserver {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        let change = self.tracker.lock().unwrap().apply(&event);
+        if let Some(change) = change {
+            let subscribers = self.subscribers.lock().unwrap().clone();
+            notify_all_observers(
+                &subscribers,
+                &SocketEngineEvent::Connection(ConnectionEvent::PeerStateChanged {
+                    endpoint: change.endpoint,
+                    old: change.old,
+                    new: change.new,
+                    cause: change.cause,
+                }),
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc;
+
+use crate::event::{EngineObserver, SocketEngineEvent};
+
+/// An event taken from the poll queue. `seq` increases by one per queued event,
+/// so a gap between two polled envelopes reveals events dropped on overflow.
+#[derive(Clone, Debug)]
+pub struct EventEnvelope {
+    pub seq: u64,
+    pub event: SocketEngineEvent,
+}
+
+struct PollState {
+    events: VecDeque<EventEnvelope>,
+    capacity: usize,
+    next_seq: u64,
+    dropped: usize,
+}
+
+/// Bounded event queue for consumers that poll instead of implementing
+/// `EngineObserver`. When full, the oldest event is dropped.
+pub struct PollQueue {
+    state: Mutex<PollState>,
+    ready: Condvar,
+}
+
+impl PollQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(PollState {
+                events: VecDeque::with_capacity(capacity),
+                capacity: capacity.max(1),
+                next_seq: 0,
+                dropped: 0,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    pub fn push(&self, event: SocketEngineEvent) {
+        let mut state = self.state.lock().unwrap();
+        if state.events.len() == state.capacity {
+            state.events.pop_front();
+            state.dropped += 1;
+        }
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.events.push_back(EventEnvelope { seq, event });
+        self.ready.notify_one();
+    }
+
+    /// Waits up to `timeout` for the next event.
+    pub fn poll(&self, timeout: Duration) -> Option<EventEnvelope> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(envelope) = state.events.pop_front() {
+                return Some(envelope);
+            }
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            state = self.ready.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+
+    /// Number of events dropped because the queue was full.
+    pub fn dropped(&self) -> usize {
+        self.state.lock().unwrap().dropped
+    }
+}
+
+pub(crate) struct PollQueueObserver(pub(crate) Arc<PollQueue>);
+
+impl EngineObserver for PollQueueObserver {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        self.0.push(event);
+    }
+}
+
+/// Observer forwarding events to a bounded Tokio channel, for async consumers
+/// that would rather `while let Some(event) = rx.recv().await` than implement
+/// `EngineObserver`. Register it with `Engine::add_observer`.
+///
+/// Events are never waited on: when the channel is full the new event is
+/// dropped and counted in `dropped()`. Once the receiver is dropped, events
+/// are discarded silently until the observer is removed.
+pub struct ChannelObserver {
+    sender: mpsc::Sender<SocketEngineEvent>,
+    dropped: u64,
+}
+
+impl ChannelObserver {
+    /// Creates the observer along with the receiving end of a channel holding
+    /// up to `capacity` events.
+    pub fn new(capacity: usize) -> (Self, mpsc::Receiver<SocketEngineEvent>) {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        (Self::from_sender(sender), receiver)
+    }
+
+    pub fn from_sender(sender: mpsc::Sender<SocketEngineEvent>) -> Self {
+        Self { sender, dropped: 0 }
+    }
+
+    /// Number of events dropped because the channel was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl EngineObserver for ChannelObserver {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.sender.try_send(event) {
+            self.dropped += 1;
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Outgoing TCP connections kept open between sends, see
+//! `Engine::with_close_after_send`.
+
+use std::{
+    collections::HashMap,
+    io,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use socket2::Socket;
+use tokio::{io::unix::AsyncFd, sync::Notify, task::AbortHandle};
+
+use crate::{
+    endpoint::Endpoint,
+    event::{notify_all_observers, ConnectionEvent, Observers, SocketEngineEvent},
+    framing::encode_frame,
+    socket::{register_readiness, retry_on_eintr, GenericSocket},
+};
+
+/// Heartbeat probe written to idle pooled connections. Engine listeners answer
+/// it with `HEARTBEAT_PONG` instead of delivering it.
+pub(crate) const HEARTBEAT_PING: &[u8] = b"\xE0SE-PING";
+/// Answer to `HEARTBEAT_PING`.
+pub(crate) const HEARTBEAT_PONG: &[u8] = b"\xE0SE-PONG";
+
+/// One connection per target, shared by the sends of an engine.
+pub(crate) type ConnectionPool = Arc<Mutex<HashMap<Endpoint, PooledConnection>>>;
+
+/// What the tasks using an outgoing connection share: the send, the reader of
+/// its replies and, while it is pooled, its watcher.
+#[derive(Default)]
+pub(crate) struct ConnectionState {
+    // Set once `Closed` is emitted for the connection, by whoever sees it first
+    pub(crate) closed: AtomicBool,
+    // Signalled by the reply reader for each heartbeat answer it reads
+    pub(crate) pongs: Notify,
+}
+
+/// Heartbeat of pooled connections, see `EngineConfig::heartbeat_interval`.
+#[derive(Clone, Debug)]
+pub(crate) struct Heartbeat {
+    interval: Duration,
+    timeout: Duration,
+    max_missed: u32,
+    // The probe as written, framed when the engine frames TCP payloads
+    ping: Vec<u8>,
+}
+
+impl Heartbeat {
+    pub(crate) fn new(
+        interval: Duration,
+        timeout: Duration,
+        max_missed: u32,
+        max_frame_size: Option<usize>,
+    ) -> Self {
+        let ping = match max_frame_size {
+            Some(max) => encode_frame(HEARTBEAT_PING, max).expect("the probe fits in any frame"),
+            None => HEARTBEAT_PING.to_vec(),
+        };
+        Self {
+            interval,
+            timeout,
+            max_missed,
+            ping,
+        }
+    }
+}
+
+/// How an engine keeps connections between sends.
+#[derive(Clone, Debug)]
+pub(crate) struct PoolSettings {
+    pub(crate) idle_timeout: Duration,
+    pub(crate) max_pooled: usize,
+    pub(crate) heartbeat: Option<Heartbeat>,
+    // Heartbeat answers are then read by the reply reader, which signals them
+    pub(crate) read_replies: bool,
+}
+
+pub(crate) struct PooledConnection {
+    pub(crate) socket: GenericSocket,
+    idle_since: Instant,
+    pub(crate) state: Arc<ConnectionState>,
+    // Stops the connection's watcher, and closes its descriptor, as soon as the
+    // connection leaves the pool
+    _watcher: WatcherGuard,
+    // Whether the watcher holds a duplicate of the socket to wait on
+    watched: bool,
+}
+
+struct WatcherGuard(AbortHandle);
+
+impl Drop for WatcherGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Emits `Closed` for a connection unless `closed` shows it already was.
+pub(crate) fn report_closed(observers: &Observers, closed: &AtomicBool, remote: &Endpoint) {
+    if !closed.swap(true, Ordering::Relaxed) {
+        notify_all_observers(
+            observers,
+            &SocketEngineEvent::Connection(ConnectionEvent::Closed {
+                remote: Some(remote.clone()),
+            }),
+        );
+    }
+}
+
+/// Takes the connection kept open to `target`, if any. One the peer closed
+/// since it was pooled is dropped instead, with a `Closed` event.
+pub(crate) fn take(
+    connections: &ConnectionPool,
+    target: &Endpoint,
+    observers: &Observers,
+) -> Option<PooledConnection> {
+    let conn = connections.lock().unwrap().remove(target)?;
+    if !conn.socket.peer_closed() {
+        return Some(conn);
+    }
+    report_closed(observers, &conn.state.closed, &conn.socket.endpoint);
+    None
+}
+
+/// Puts a connection back in the pool, along with the only task watching it.
+/// Only one connection per target is kept and at most `max_pooled` in all, the
+/// socket is handed back when there is no room for it.
+pub(crate) fn put_back(
+    connections: &ConnectionPool,
+    target: Endpoint,
+    socket: GenericSocket,
+    state: Arc<ConnectionState>,
+    settings: &PoolSettings,
+    observers: Observers,
+) -> Option<GenericSocket> {
+    let mut pool = connections.lock().unwrap();
+    // A concurrent send may have pooled its own in the meantime
+    if pool.len() >= settings.max_pooled || pool.contains_key(&target) {
+        return Some(socket);
+    }
+    let idle_since = Instant::now();
+    let readiness = register_readiness(&socket.socket);
+    let watched = readiness.is_some();
+    let watcher = tokio::spawn(watch(
+        connections.clone(),
+        target.clone(),
+        idle_since,
+        readiness,
+        state.clone(),
+        settings.clone(),
+        observers,
+    ));
+    pool.insert(
+        target,
+        PooledConnection {
+            socket,
+            idle_since,
+            state,
+            _watcher: WatcherGuard(watcher.abort_handle()),
+            watched,
+        },
+    );
+    None
+}
+
+/// Closes every pooled connection, with a `Closed` event each.
+pub(crate) fn close_all(connections: &ConnectionPool, observers: &Observers) {
+    let pooled: Vec<_> = connections.lock().unwrap().drain().collect();
+    for (_, conn) in pooled {
+        let _ = conn.socket.socket.shutdown(std::net::Shutdown::Both);
+        report_closed(observers, &conn.state.closed, &conn.socket.endpoint);
+    }
+}
+
+/// Descriptors held by the pool: each connection and the duplicate its watcher
+/// waits on.
+pub(crate) fn descriptor_count(connections: &ConnectionPool) -> usize {
+    connections
+        .lock()
+        .unwrap()
+        .values()
+        .map(|conn| 1 + usize::from(conn.watched))
+        .sum()
+}
+
+// What woke the watcher of a pooled connection up
+enum Wake {
+    Idle,
+    Probe,
+    Answered,
+    Readable,
+}
+
+// Watches a connection put back in the pool until a send takes it: closes it
+// once idle for `idle_timeout`, and drops it as soon as the peer closed it or
+// `max_missed` heartbeat probes in a row went unanswered
+async fn watch(
+    connections: ConnectionPool,
+    target: Endpoint,
+    pooled_at: Instant,
+    readiness: Option<AsyncFd<Socket>>,
+    state: Arc<ConnectionState>,
+    settings: PoolSettings,
+    observers: Observers,
+) {
+    let idle_deadline = tokio::time::Instant::from_std(pooled_at + settings.idle_timeout);
+    // Probes are written, and answers read, through the watcher's duplicate
+    let heartbeat = settings.heartbeat.as_ref().filter(|_| readiness.is_some());
+    let mut probe_at = heartbeat.map(|heartbeat| tokio::time::Instant::now() + heartbeat.interval);
+    // Whether the last probe awaits its answer, until `probe_at`
+    let mut probing = false;
+    let mut missed = 0;
+    loop {
+        let readable = async {
+            match &readiness {
+                Some(fd) => match fd.readable().await {
+                    Ok(mut guard) => guard.clear_ready(),
+                    Err(_) => std::future::pending().await,
+                },
+                None => std::future::pending().await,
+            }
+        };
+        let probe = async {
+            match probe_at {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        let wake = tokio::select! {
+            _ = tokio::time::sleep_until(idle_deadline) => Wake::Idle,
+            _ = probe => Wake::Probe,
+            _ = state.pongs.notified() => Wake::Answered,
+            _ = readable => Wake::Readable,
+        };
+        let conn = {
+            let mut pool = connections.lock().unwrap();
+            match pool.get(&target) {
+                // Otherwise taken by a send while this task was waking up, the
+                // send aborted it
+                Some(conn) if conn.idle_since == pooled_at => {}
+                _ => return,
+            }
+            // Probes are written and answers read under the pool lock, so that no
+            // send uses the connection meanwhile
+            let mut answered = false;
+            let evict = match wake {
+                Wake::Idle => true,
+                Wake::Answered => {
+                    answered = true;
+                    false
+                }
+                Wake::Readable if pool[&target].socket.peer_closed() => true,
+                // Data the peer sent, left unread unless it may be an answer
+                Wake::Readable => {
+                    if let (Some(_), Some(fd)) = (heartbeat, &readiness) {
+                        answered = !settings.read_replies && read_answer(fd.get_ref());
+                    }
+                    false
+                }
+                Wake::Probe => {
+                    let (Some(heartbeat), Some(fd)) = (heartbeat, &readiness) else {
+                        unreachable!("probes are only scheduled with a heartbeat")
+                    };
+                    missed += u32::from(probing);
+                    probing = true;
+                    probe_at = Some(tokio::time::Instant::now() + heartbeat.timeout);
+                    missed >= heartbeat.max_missed || !write_probe(fd.get_ref(), &heartbeat.ping)
+                }
+            };
+            if answered && probing {
+                probing = false;
+                missed = 0;
+                probe_at =
+                    heartbeat.map(|heartbeat| tokio::time::Instant::now() + heartbeat.interval);
+            }
+            if !evict {
+                continue;
+            }
+            pool.remove(&target).expect("checked above")
+        };
+        // Closed either way, the peer may already be gone
+        let _ = conn.socket.socket.shutdown(std::net::Shutdown::Both);
+        report_closed(&observers, &conn.state.closed, &conn.socket.endpoint);
+        return;
+    }
+}
+
+// Writes a heartbeat probe without waiting. A full send buffer only delays the
+// answer, while a failed or partial write leaves the connection unusable.
+fn write_probe(socket: &Socket, ping: &[u8]) -> bool {
+    match retry_on_eintr(|| socket.send_with_flags(ping, libc::MSG_DONTWAIT | libc::MSG_NOSIGNAL)) {
+        Ok(written) => written == ping.len(),
+        Err(e) => e.kind() == io::ErrorKind::WouldBlock,
+    }
+}
+
+// Reads what the peer sent without waiting, returns whether it holds the answer
+// to a heartbeat probe
+fn read_answer(socket: &Socket) -> bool {
+    let mut buffer = [MaybeUninit::<u8>::uninit(); 256];
+    let mut answered = false;
+    while let Ok(size @ 1..) =
+        retry_on_eintr(|| socket.recv_with_flags(&mut buffer, libc::MSG_DONTWAIT))
+    {
+        // SAFETY: `recv` initialized the first `size` bytes
+        let data = unsafe { &*(&buffer[..size] as *const [MaybeUninit<u8>] as *const [u8]) };
+        answered |= data
+            .windows(HEARTBEAT_PONG.len())
+            .any(|window| window == HEARTBEAT_PONG);
+    }
+    answered
+}
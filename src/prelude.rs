@@ -0,0 +1,32 @@
+//! Types needed by applications driving an `Engine`: `use socket_engine::prelude::*;`
+//!
+//! Everything reachable from here is the supported API, along with the `config`
+//! and `runtime` modules. The hidden modules expose lower-level helpers used by
+//! the engine itself, which may change between releases.
+
+pub use crate::{
+    config::EngineConfig,
+    echo::PingReport,
+    endpoint::{Endpoint, EndpointParseError, EndpointProto},
+    engine::{Engine, SendHandle, SendOptions, SendOutcome, SendState},
+    error::SocketEngineError,
+    event::{
+        AsyncEngineObserver, ConnectionEvent, ConnectionFailureReason, ConnectionId, DataEvent,
+        EngineObserver, ErrorEvent, EventMask, ListenerStopReason, MessageId, MisuseKind,
+        ObserverId, SocketEngineEvent,
+    },
+    framing::{FrameError, Framing, MessageAssembler},
+    pairing::{PairingError, PAIR_ALIAS},
+    peer_state::{PeerState, PeerStateCause, PeerStateThresholds},
+    poll::{ChannelObserver, EventEnvelope},
+    rate_limit::RateLimit,
+    request::ResponseMatcher,
+    retry::RetryPolicy,
+    socket::{AdoptedSocket, ListenerHandle, ListenerLimits, ListenerStatus, MulticastGroup},
+    stats::EndpointStats,
+};
+
+#[cfg(feature = "bp")]
+pub use crate::bp::BpConfig;
+#[cfg(feature = "tls")]
+pub use crate::tls::TlsConfig;
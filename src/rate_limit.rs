@@ -0,0 +1,55 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::endpoint::Endpoint;
+
+/// Cap on the bytes sent to one endpoint, see `Engine::set_rate_limit`.
+///
+/// Token bucket: up to `burst` bytes leave at once, then `bytes_per_sec` on
+/// average. A send that does not fit waits for the bucket to refill instead of
+/// failing, even one larger than `burst`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Treated as 1 when 0.
+    pub bytes_per_sec: u64,
+    pub burst: u64,
+}
+
+pub(crate) type RateLimits = Arc<Mutex<HashMap<Endpoint, TokenBucket>>>;
+
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    // Negative while sends are waiting for bytes already reserved
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes `bytes` from the bucket and returns how long the send must wait
+    /// before they are available.
+    pub(crate) fn reserve(&mut self, bytes: usize) -> Duration {
+        let rate = self.limit.bytes_per_sec.max(1) as f64;
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled_at).as_secs_f64() * rate;
+        self.tokens = (self.tokens + refill).min(self.limit.burst as f64);
+        self.refilled_at = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use tokio::sync::oneshot;
+
+use crate::{
+    endpoint::Endpoint,
+    error::SocketEngineError,
+    event::{DataEvent, EngineObserver, ErrorEvent, MessageId, SocketEngineEvent},
+};
+
+/// Tells which received payload answers a request sent with `Engine::request`.
+///
+/// Implemented by closures taking the request token, the sender and the payload,
+/// e.g. to compare the token with an id decoded from an acknowledgement.
+pub trait ResponseMatcher: Send + Sync + 'static {
+    fn matches(&self, token: &MessageId, from: &Endpoint, data: &[u8]) -> bool;
+}
+
+impl<F> ResponseMatcher for F
+where
+    F: Fn(&MessageId, &Endpoint, &[u8]) -> bool + Send + Sync + 'static,
+{
+    fn matches(&self, token: &MessageId, from: &Endpoint, data: &[u8]) -> bool {
+        self(token, from, data)
+    }
+}
+
+// Resolves a request with the first matching payload, or with the failure of
+// its send. Events are left for the other observers as they are
+pub(crate) struct ResponseObserver<M> {
+    pub(crate) token: MessageId,
+    pub(crate) matcher: M,
+    pub(crate) response: Option<oneshot::Sender<Result<Vec<u8>, SocketEngineError>>>,
+}
+
+impl<M: ResponseMatcher> EngineObserver for ResponseObserver<M> {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if self.response.is_none() {
+            return;
+        }
+        let result = match event {
+            SocketEngineEvent::Data(DataEvent::Received { data, from, .. })
+                if self.matcher.matches(&self.token, &from, &data) =>
+            {
+                Ok(data)
+            }
+            SocketEngineEvent::Data(DataEvent::Cancelled { token, .. }) if token == self.token => {
+                Err(SocketEngineError::Cancelled)
+            }
+            SocketEngineEvent::Error(ErrorEvent::SendFailed { token, error, .. })
+                if token == self.token =>
+            {
+                Err(error)
+            }
+            SocketEngineEvent::Error(ErrorEvent::ConnectionFailed { token, reason, .. })
+                if token == self.token =>
+            {
+                Err(SocketEngineError::Connect(reason))
+            }
+            _ => return,
+        };
+        if let Some(response) = self.response.take() {
+            // The request may have timed out already
+            let _ = response.send(result);
+        }
+    }
+}
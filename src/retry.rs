@@ -0,0 +1,103 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io,
+    time::Duration,
+};
+
+use crate::event::ConnectionFailureReason;
+
+/// Retries of sends that fail with a transient error (`Refused`, `Timeout` or
+/// `NetworkUnreachable`), see `Engine::with_retry_policy` and
+/// `SendOptions::retry_policy`.
+///
+/// The delay before retry `n` is `base_delay * 2^(n-1)`, capped at `max_delay`.
+/// With `jitter`, each delay is drawn at random between half and all of it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included. 1 disables retries.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+        jitter: false,
+    };
+
+    /// Delay before the attempt following attempt number `attempt` (from 1),
+    /// `None` once all attempts are used.
+    pub fn delay_after(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(attempt - 1);
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        if !self.jitter {
+            return Some(delay);
+        }
+        // A fresh `RandomState` is randomly keyed, good enough to spread retries
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(attempt);
+        let fraction = (hasher.finish() % 1024) as f64 / 1024.0;
+        Some(delay.mul_f64(0.5 + fraction / 2.0))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+fn is_transient(error: &io::Error) -> bool {
+    !matches!(
+        ConnectionFailureReason::from_io_error_kind(error.kind()),
+        ConnectionFailureReason::Other
+    )
+}
+
+/// Attempts at an operation under a `RetryPolicy`. The operation is run by the
+/// caller, which asks after each attempt whether to make another one.
+pub(crate) struct Retries<'a> {
+    policy: &'a RetryPolicy,
+    attempt: u32,
+}
+
+impl<'a> Retries<'a> {
+    pub(crate) fn new(policy: &'a RetryPolicy) -> Self {
+        Self { policy, attempt: 1 }
+    }
+
+    /// Number of the current attempt, from 1.
+    pub(crate) fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Whether to make another attempt after one that ended with `result`:
+    /// only after a transient error while `policy` has attempts left, in which
+    /// case this sleeps first. `on_retry` is called with the failed attempt and
+    /// the delay before the next one.
+    pub(crate) async fn again<T>(
+        &mut self,
+        result: &io::Result<T>,
+        on_retry: impl FnOnce(u32, Duration),
+    ) -> bool {
+        let delay = match result {
+            Err(e) if is_transient(e) => self.policy.delay_after(self.attempt),
+            _ => None,
+        };
+        let Some(delay) = delay else {
+            return false;
+        };
+        on_retry(self.attempt, delay);
+        tokio::time::sleep(delay).await;
+        self.attempt += 1;
+        true
+    }
+}
@@ -0,0 +1,92 @@
+//! The Tokio runtime shared by engines without a runtime of their own, and the
+//! threads it may use. Its settings apply to the whole process.
+
+use once_cell::sync::{Lazy, OnceCell};
+use tokio::runtime::Runtime;
+
+use crate::error::SocketEngineError;
+
+// Shared by the engines without a runtime of their own, see `Engine::with_runtime`
+pub(crate) static TOKIO_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(budget) = THREAD_BUDGET.get() {
+        builder
+            .worker_threads(budget.workers)
+            .max_blocking_threads(budget.blocking);
+    }
+    builder.build().expect("Failed to create Tokio runtime")
+});
+
+// Runs the listeners of engines on the shared runtime when the thread budget
+// keeps them off its workers
+pub(crate) static LISTENER_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .max_blocking_threads(1)
+        .thread_name(LISTENER_THREAD_NAME)
+        .enable_all()
+        .build()
+        .expect("Failed to create the listener runtime")
+});
+
+/// Name of the thread running listeners apart from the workers, see
+/// `ThreadBudget::listeners_share_workers`.
+pub const LISTENER_THREAD_NAME: &str = "se-listeners";
+
+static THREAD_BUDGET: OnceCell<ThreadBudget> = OnceCell::new();
+
+/// Thread caps for the shared runtime.
+///
+/// `workers` run the send tasks, and the listeners and TCP connection handlers
+/// when `listeners_share_workers` is set, while `blocking` threads run pings.
+/// Otherwise, listeners run on one thread of their own, named
+/// `LISTENER_THREAD_NAME`, so that the process has at most
+/// `workers + blocking + 1` runtime threads.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ThreadBudget {
+    pub workers: usize,
+    pub blocking: usize,
+    pub listeners_share_workers: bool,
+}
+
+// Tokio's own defaults
+const DEFAULT_MAX_BLOCKING_THREADS: usize = 512;
+
+/// The budget of the shared runtime: the one given to `set_thread_budget`, or
+/// Tokio's defaults (a worker per CPU and up to 512 blocking threads, listeners
+/// sharing the workers).
+pub fn thread_budget() -> ThreadBudget {
+    THREAD_BUDGET
+        .get()
+        .copied()
+        .unwrap_or_else(|| ThreadBudget {
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            blocking: DEFAULT_MAX_BLOCKING_THREADS,
+            listeners_share_workers: true,
+        })
+}
+
+/// Restricts the number of threads of the runtime shared by all engines.
+///
+/// Must be called once, before any engine starts a listener or sends data.
+pub fn set_thread_budget(budget: ThreadBudget) -> Result<(), SocketEngineError> {
+    if budget.workers == 0 {
+        return Err(SocketEngineError::InvalidConfig(
+            "Thread budget needs at least one worker thread".to_string(),
+        ));
+    }
+    if budget.blocking == 0 {
+        return Err(SocketEngineError::InvalidConfig(
+            "Thread budget needs at least one blocking thread, pings run on them".to_string(),
+        ));
+    }
+    if Lazy::get(&TOKIO_RUNTIME).is_some() {
+        return Err(SocketEngineError::AlreadyConfigured(
+            "Runtime already started, the thread budget can no longer change".to_string(),
+        ));
+    }
+    THREAD_BUDGET
+        .set(budget)
+        .map_err(|_| SocketEngineError::AlreadyConfigured("Thread budget already set".to_string()))
+}
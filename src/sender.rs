@@ -0,0 +1,478 @@
+//! The task behind each send once its socket is known: datagrams for UDP and
+//! BP, a pooled or fresh connection for TCP. See `Engine::send`.
+
+use std::{
+    future::Future,
+    io::{self, Write},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use once_cell::sync::OnceCell;
+use socket2::{Protocol, SockAddr, Socket, Type};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch, Mutex},
+};
+
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsClient};
+use crate::{
+    endpoint::{Endpoint, EndpointProto},
+    engine::{report_outcome, SendOutcome},
+    error::SocketEngineError,
+    event::{
+        notify_all_observers, ConnectionEvent, ConnectionFailureReason, DataEvent, ErrorEvent,
+        MessageId, Observers, SocketEngineEvent,
+    },
+    pool::{self, ConnectionPool, ConnectionState, PoolSettings},
+    rate_limit::RateLimits,
+    retry::{Retries, RetryPolicy},
+    socket::{retry_on_eintr, spawn_reply_reader, GenericSocket},
+};
+
+/// A send waiting for a worker, see `SendQueue`.
+pub(crate) type SendJob = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Sends of an engine waiting to run, drained by a fixed number of worker
+/// tasks. See `EngineConfig::send_queue_capacity` and `EngineConfig::send_workers`.
+pub(crate) struct SendQueue {
+    capacity: usize,
+    workers: usize,
+    // Started along with the workers by the first send
+    jobs: OnceCell<mpsc::Sender<SendJob>>,
+}
+
+impl SendQueue {
+    pub(crate) fn new(capacity: usize, workers: usize) -> Self {
+        Self {
+            capacity,
+            workers,
+            jobs: OnceCell::new(),
+        }
+    }
+
+    /// Queues `job`, waiting for room when `wait` is set and failing with
+    /// `QueueFull` otherwise. Waiting blocks the calling thread.
+    pub(crate) fn push(
+        &self,
+        runtime: &Handle,
+        job: SendJob,
+        wait: bool,
+    ) -> Result<(), SocketEngineError> {
+        let jobs = self.jobs.get_or_init(|| {
+            let (jobs, queue) = mpsc::channel(self.capacity);
+            let queue = Arc::new(Mutex::new(queue));
+            for _ in 0..self.workers {
+                runtime.spawn(work(queue.clone()));
+            }
+            jobs
+        });
+        if wait {
+            // Workers only stop once the engine, and so this sender, is gone
+            let _ = runtime.block_on(jobs.send(job));
+            return Ok(());
+        }
+        jobs.try_send(job).map_err(|_| SocketEngineError::QueueFull)
+    }
+}
+
+// Runs queued sends one after the other, until the engine is dropped and the
+// queue drained
+async fn work(queue: Arc<Mutex<mpsc::Receiver<SendJob>>>) {
+    loop {
+        let job = queue.lock().await.recv().await;
+        match job {
+            Some(job) => job.await,
+            None => return,
+        }
+    }
+}
+
+/// Engine settings a send runs with, `SendOptions` overrides applied.
+#[derive(Clone)]
+pub(crate) struct SendSettings {
+    pub(crate) connect_timeout: Duration,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) pool: PoolSettings,
+    pub(crate) close_after_send: bool,
+    pub(crate) reconnect: bool,
+    pub(crate) read_replies: bool,
+    pub(crate) tcp_buffer_size: usize,
+    pub(crate) max_frame_size: Option<usize>,
+    pub(crate) tcp_nodelay: bool,
+    pub(crate) ttl: Option<u32>,
+    pub(crate) recv_buffer_size: Option<usize>,
+    pub(crate) send_buffer_size: Option<usize>,
+    /// Set when TCP sends go over TLS
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsClient>,
+}
+
+/// One send, reporting its progress to the observers and its outcome to its
+/// `SendHandle`.
+pub(crate) struct SendTask {
+    pub(crate) token: MessageId,
+    pub(crate) target: Endpoint,
+    pub(crate) data: Vec<u8>,
+    /// What goes on the wire instead of `data` for framed TCP sends
+    pub(crate) frame: Option<Vec<u8>>,
+    /// Endpoint the socket is bound to, if any
+    pub(crate) source: Option<Endpoint>,
+    /// Addresses of `target`, never empty
+    pub(crate) candidates: Vec<SockAddr>,
+    pub(crate) observers: Observers,
+    pub(crate) outcome: Arc<watch::Sender<Option<SendOutcome>>>,
+    /// Current attempt, see `SendState::attempts`
+    pub(crate) attempts: Arc<AtomicU32>,
+    pub(crate) connections: ConnectionPool,
+    pub(crate) rate_limits: RateLimits,
+    pub(crate) settings: SendSettings,
+}
+
+impl SendTask {
+    /// Sends the payload through `socket`, or through the connection kept open
+    /// to the target for TCP.
+    pub(crate) async fn run(self, socket: GenericSocket) {
+        self.emit(SocketEngineEvent::Data(DataEvent::Sending {
+            token: self.token.clone(),
+            to: self.target.clone(),
+            bytes: self.data.len(),
+            local: false,
+        }));
+
+        let wire_bytes = self.frame.as_ref().map_or(self.data.len(), Vec::len);
+        let wait = self
+            .rate_limits
+            .lock()
+            .unwrap()
+            .get_mut(&self.target)
+            .map(|bucket| bucket.reserve(wire_bytes));
+        if let Some(wait) = wait.filter(|wait| !wait.is_zero()) {
+            tokio::time::sleep(wait).await;
+        }
+
+        if self.target.proto == EndpointProto::Tcp {
+            self.send_tcp(socket).await;
+        } else {
+            self.send_datagram(socket).await;
+        }
+    }
+
+    fn emit(&self, event: SocketEngineEvent) {
+        notify_all_observers(&self.observers, &event);
+    }
+
+    fn on_retry(&self, attempt: u32, next_in: Duration) {
+        self.attempts.store(attempt + 1, Ordering::Relaxed);
+        self.emit(SocketEngineEvent::Data(DataEvent::Retrying {
+            token: self.token.clone(),
+            to: self.target.clone(),
+            attempt,
+            next_in,
+        }));
+    }
+
+    fn succeeded(&self, wire_bytes: usize) {
+        self.emit(SocketEngineEvent::Data(DataEvent::Sent {
+            token: self.token.clone(),
+            to: self.target.clone(),
+            bytes_sent: self.data.len(),
+            wire_bytes,
+            from: self.source.clone(),
+            local: false,
+        }));
+        report_outcome(
+            &self.outcome,
+            SendOutcome::Sent {
+                bytes: self.data.len(),
+            },
+        );
+    }
+
+    // Emits `SendFailed`, failing the send unless its outcome is already known
+    fn failed(&self, error: SocketEngineError) {
+        self.emit(SocketEngineEvent::Error(ErrorEvent::SendFailed {
+            endpoint: self.target.clone(),
+            token: self.token.clone(),
+            error: error.clone(),
+        }));
+        report_outcome(&self.outcome, SendOutcome::Failed { error });
+    }
+
+    // UDP and BP datagrams
+    async fn send_datagram(&self, socket: GenericSocket) {
+        let mut retries = Retries::new(&self.settings.retry_policy);
+        let sent = loop {
+            let sent = socket.send_datagram(&self.data, &self.candidates[0]).await;
+            if !retries
+                .again(&sent, |attempt, next_in| self.on_retry(attempt, next_in))
+                .await
+            {
+                break sent;
+            }
+        };
+        match sent {
+            Ok(_) => self.succeeded(self.data.len()),
+            Err(err) => self.failed(SocketEngineError::send(err)),
+        }
+    }
+
+    async fn send_tcp(&self, mut socket: GenericSocket) {
+        #[cfg(feature = "tls")]
+        if let Some(client) = &self.settings.tls {
+            return self.send_tls(socket, client).await;
+        }
+        let settings = &self.settings;
+        let wire = self.frame.as_deref().unwrap_or(&self.data);
+        // A pooled connection the peer dropped is only noticed when writing to
+        // it, it is then replaced once when reconnecting is enabled
+        let mut pooled = pool::take(&self.connections, &self.target, &self.observers);
+        let mut candidates = self.candidates.clone();
+        let mut reconnected = false;
+        let (state, written) = loop {
+            let reused = pooled.is_some();
+            let state = match pooled.take() {
+                Some(conn) => {
+                    socket = conn.socket;
+                    conn.state
+                }
+                None => {
+                    if !self.connect(&mut socket, &candidates).await {
+                        return;
+                    }
+                    self.established(&socket)
+                }
+            };
+            // Read before writing, a reset connection no longer has a peer
+            let peer = socket.socket.peer_addr().ok();
+            let written = socket.write_all(wire).await;
+            let dropped = matches!(
+                &written,
+                Err(err) if matches!(
+                    err.kind(),
+                    io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+                )
+            );
+            if !(settings.reconnect && reused && dropped && !reconnected) {
+                break (state, written);
+            }
+            let _ = socket.socket.shutdown(std::net::Shutdown::Both);
+            pool::report_closed(&self.observers, &state.closed, &socket.endpoint);
+            // The address the dropped connection went to is tried first again
+            if let Some(peer) = peer {
+                candidates.retain(|addr| addr.as_socket() != peer.as_socket());
+                candidates.insert(0, peer);
+            }
+            match Socket::new(candidates[0].domain(), Type::STREAM, Some(Protocol::TCP)) {
+                Ok(fresh) => socket.socket = fresh,
+                Err(_) => break (state, written),
+            }
+            reconnected = true;
+        };
+
+        let mut broken = false;
+        match written {
+            Ok(()) => self.succeeded(wire.len()),
+            Err(err) => {
+                broken = true;
+                self.failed(SocketEngineError::send(err));
+            }
+        }
+        if let Err(err) = retry_on_eintr(|| socket.socket.flush()) {
+            broken = true;
+            self.emit(SocketEngineEvent::Error(ErrorEvent::SendFailed {
+                endpoint: self.target.clone(),
+                token: self.token.clone(),
+                error: SocketEngineError::send(err),
+            }));
+        }
+
+        if !settings.close_after_send && !broken {
+            match pool::put_back(
+                &self.connections,
+                self.target.clone(),
+                socket,
+                state.clone(),
+                &settings.pool,
+                self.observers.clone(),
+            ) {
+                Some(unpooled) => socket = unpooled,
+                None => return,
+            }
+        }
+
+        // The reply reader closes the connection once the peer is done
+        let how = if settings.read_replies {
+            std::net::Shutdown::Write
+        } else {
+            std::net::Shutdown::Both
+        };
+        if let Err(err) = socket.socket.shutdown(how) {
+            self.emit(SocketEngineEvent::Error(ErrorEvent::SendFailed {
+                endpoint: self.target.clone(),
+                token: self.token.clone(),
+                error: SocketEngineError::Shutdown(Arc::new(err)),
+            }));
+        } else if !settings.read_replies {
+            pool::report_closed(&self.observers, &state.closed, &socket.endpoint);
+        }
+    }
+
+    // TCP sends over TLS. Their connections are not pooled: each send connects,
+    // performs the handshake and closes the connection once the payload is
+    // written. With `read_replies`, it stays open until the peer closes it or
+    // sends nothing for the idle timeout.
+    #[cfg(feature = "tls")]
+    async fn send_tls(&self, mut socket: GenericSocket, client: &TlsClient) {
+        use tokio::io::AsyncWriteExt;
+
+        let settings = &self.settings;
+        let wire = self.frame.as_deref().unwrap_or(&self.data);
+        if !self.connect(&mut socket, &self.candidates).await {
+            return;
+        }
+        let remote = socket.endpoint;
+        let stream = match socket.socket.set_nonblocking(true).and_then(|()| {
+            tokio::net::TcpStream::from_std(std::net::TcpStream::from(socket.socket))
+        }) {
+            Ok(stream) => stream,
+            Err(err) => return self.failed(SocketEngineError::send(err)),
+        };
+        let handshake = tokio::time::timeout(
+            settings.connect_timeout,
+            client.connect(&self.target, stream),
+        );
+        let mut stream = match handshake.await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(_)) => return self.connection_failed(ConnectionFailureReason::Handshake),
+            Err(_elapsed) => return self.connection_failed(ConnectionFailureReason::Timeout),
+        };
+        self.emit(SocketEngineEvent::Connection(
+            ConnectionEvent::Established {
+                remote: self.target.clone(),
+            },
+        ));
+
+        let written = async {
+            stream.write_all(wire).await?;
+            stream.flush().await
+        }
+        .await;
+        let broken = written.is_err();
+        match written {
+            Ok(()) => self.succeeded(wire.len()),
+            Err(err) => self.failed(SocketEngineError::send(err)),
+        }
+        if settings.read_replies && !broken {
+            tls::spawn_reply_reader(
+                stream,
+                remote,
+                self.observers.clone(),
+                settings.tcp_buffer_size,
+                settings.max_frame_size,
+                settings.pool.idle_timeout,
+            );
+            return;
+        }
+        // Sends `close_notify` and shuts the write side down
+        if let Err(err) = stream.shutdown().await {
+            self.emit(SocketEngineEvent::Error(ErrorEvent::SendFailed {
+                endpoint: self.target.clone(),
+                token: self.token.clone(),
+                error: SocketEngineError::Shutdown(Arc::new(err)),
+            }));
+            return;
+        }
+        self.emit(SocketEngineEvent::Connection(ConnectionEvent::Closed {
+            remote: Some(remote),
+        }));
+    }
+
+    // Connects `socket` to the first of `candidates` that accepts, retrying under
+    // the send's policy. Reports the failure and returns `false` when none does.
+    async fn connect(&self, socket: &mut GenericSocket, candidates: &[SockAddr]) -> bool {
+        let settings = &self.settings;
+        let mut retries = Retries::new(&settings.retry_policy);
+        let connected = loop {
+            let connected = async {
+                // A socket whose connect failed cannot be connected again, the
+                // new one is for the address tried first
+                if retries.attempt() > 1 {
+                    socket.socket =
+                        Socket::new(candidates[0].domain(), Type::STREAM, Some(Protocol::TCP))?;
+                }
+                socket
+                    .connect_any(candidates, settings.connect_timeout)
+                    .await?;
+                if let Some(ttl) = settings.ttl {
+                    socket.set_ttl(ttl)?;
+                }
+                socket.set_buffer_sizes(settings.recv_buffer_size, settings.send_buffer_size)?;
+                if settings.tcp_nodelay {
+                    // Best effort, the connection works the same without it
+                    let _ = socket.socket.set_nodelay(true);
+                }
+                Ok::<_, io::Error>(())
+            }
+            .await;
+            if !retries
+                .again(&connected, |attempt, next_in| {
+                    self.on_retry(attempt, next_in)
+                })
+                .await
+            {
+                break connected;
+            }
+        };
+        if let Err(err) = connected {
+            self.connection_failed(ConnectionFailureReason::from_io_error_kind(err.kind()));
+            return false;
+        }
+        true
+    }
+
+    fn connection_failed(&self, reason: ConnectionFailureReason) {
+        self.emit(SocketEngineEvent::Error(ErrorEvent::ConnectionFailed {
+            endpoint: self.target.clone(),
+            reason,
+            token: self.token.clone(),
+        }));
+        report_outcome(
+            &self.outcome,
+            SendOutcome::Failed {
+                error: SocketEngineError::Connect(reason),
+            },
+        );
+    }
+
+    // Emits `Established` for a new connection and returns the state it shares
+    // with its reply reader
+    fn established(&self, socket: &GenericSocket) -> Arc<ConnectionState> {
+        let settings = &self.settings;
+        self.emit(SocketEngineEvent::Connection(
+            ConnectionEvent::Established {
+                remote: self.target.clone(),
+            },
+        ));
+        let state = Arc::new(ConnectionState::default());
+        if settings.read_replies {
+            spawn_reply_reader(
+                &socket.socket,
+                socket.endpoint.clone(),
+                self.observers.clone(),
+                settings.tcp_buffer_size,
+                settings.max_frame_size,
+                settings
+                    .close_after_send
+                    .then_some(settings.pool.idle_timeout),
+                state.clone(),
+            );
+        }
+        state
+    }
+}
@@ -0,0 +1,86 @@
+//! Field codecs used by the `serde` feature.
+
+/// Byte payloads as standard base64 strings (with padding), to keep JSON readable.
+pub(crate) mod base64 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    pub(crate) fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+        for chunk in data.chunks(3) {
+            let bytes = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    let index = (group >> (18 - 6 * i)) & 0x3f;
+                    encoded.push(ALPHABET[index as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+        serializer.serialize_str(&encoded)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let encoded = encoded.trim_end_matches('=');
+        let mut data = Vec::with_capacity(encoded.len() * 3 / 4);
+        let mut group = 0u32;
+        let mut bits = 0;
+        for c in encoded.bytes() {
+            let value = ALPHABET.iter().position(|&a| a == c).ok_or_else(|| {
+                D::Error::custom(format!("invalid base64 character `{}`", c as char))
+            })?;
+            group = (group << 6) | value as u32;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                data.push((group >> bits) as u8);
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// I/O errors as their OS error code when they have one, which restores the
+/// error kind on deserialization, and their message otherwise.
+pub(crate) mod io_error {
+    use std::{io, sync::Arc};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct IoError {
+        os_error: Option<i32>,
+        message: String,
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        error: &Arc<io::Error>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        IoError {
+            os_error: error.raw_os_error(),
+            message: error.to_string(),
+        }
+        .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<io::Error>, D::Error> {
+        let error = IoError::deserialize(deserializer)?;
+        Ok(Arc::new(match error.os_error {
+            Some(code) => io::Error::from_raw_os_error(code),
+            None => io::Error::other(error.message),
+        }))
+    }
+}
@@ -1,46 +1,405 @@
 use std::{
-    io::{self, Read},
+    collections::HashMap,
+    io,
     mem::MaybeUninit,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
-    thread,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
-use libc::c_int;
+use tokio::{
+    io::{unix::AsyncFd, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Interest},
+    net::TcpStream,
+    runtime::Handle,
+    sync::{mpsc, watch, Semaphore},
+};
 
-use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use socket2::{Domain, Protocol, SockAddr, SockRef, Socket, Type};
 
+#[cfg(feature = "bp")]
+pub use crate::bp::AF_BP;
+#[cfg(feature = "bp")]
+use crate::bp::{bp_sockaddr_to_endpoint, create_bp_sockaddr_with_string, BpConfig};
+#[cfg(feature = "tls")]
+use crate::tls::{self, SharedTls};
 use crate::{
-    endpoint::{create_bp_sockaddr_with_string, Endpoint, EndpointProto, SockAddrBp},
-    engine::TOKIO_RUNTIME,
+    config::EngineConfig,
+    echo::is_echo_probe,
+    endpoint::{Endpoint, EndpointProto},
+    error::SocketEngineError,
     event::{
-        notify_all_observers, ConnectionEvent, DataEvent, EngineObserver, ErrorEvent,
-        SocketEngineEvent,
+        notify_all_observers, notify_received, ConnectionEvent, ConnectionId, DataEvent,
+        EngineObserver, ErrorEvent, ListenerStopReason, MessageId, SocketEngineEvent,
     },
+    framing::{FrameDecoder, Framing, FRAME_HEADER_LEN},
+    pool::{report_closed, ConnectionState, HEARTBEAT_PING, HEARTBEAT_PONG},
+    runtime::TOKIO_RUNTIME,
 };
-pub const AF_BP: c_int = 28;
+
+/// Bytes read from a TCP connection at a time, see `EngineConfig::tcp_buffer_size`.
+pub const DEFAULT_TCP_BUFFER_SIZE: usize = 4096;
+
+/// Largest UDP payload over IPv4, see `EngineConfig::udp_buffer_size`.
+pub const DEFAULT_UDP_BUFFER_SIZE: usize = 65507;
+
+/// Wake-up interval of a listener with nothing to receive, see `EngineConfig::poll_interval`.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// How often a send polls a socket the reactor cannot watch for room to write
+const WRITABLE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Pending connections queue of TCP listeners, see `EngineConfig::tcp_backlog`.
+pub const DEFAULT_TCP_BACKLOG: i32 = 128;
 
 pub struct GenericSocket {
     pub socket: Socket,
     pub endpoint: Endpoint,
     pub sockaddr: SockAddr,
     pub listening: bool,
+    /// Set for sockets handed over with `from_socket`, which are already bound
+    pub adopted: bool,
 }
 
-pub fn endpoint_to_sockaddr(endpoint: Endpoint) -> Option<SockAddr> {
-    match endpoint.proto {
-        EndpointProto::Udp | EndpointProto::Tcp => {
-            if let Ok(std_sock) = endpoint.endpoint.parse::<SocketAddr>() {
-                return Some(SockAddr::from(std_sock));
-            }
+/// A socket created and bound outside the engine, e.g. with extra socket options
+/// or received from another process, to listen on with `Engine::adopt_listener`
+/// or send from with `Engine::adopt_send_socket`.
+pub struct AdoptedSocket(pub(crate) GenericSocket);
+
+impl AdoptedSocket {
+    /// Wraps `socket` for `endpoint`.
+    ///
+    /// The socket type and address family must match the endpoint protocol, and a
+    /// UDP or TCP socket must already be bound to the endpoint address. It is used
+    /// as is: the engine never binds it again. The family of a BP socket is checked
+    /// against the `BpConfig` of the engine adopting it.
+    pub fn new(socket: impl Into<Socket>, endpoint: Endpoint) -> Result<Self, SocketEngineError> {
+        GenericSocket::from_socket(socket.into(), endpoint).map(Self)
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.0.endpoint
+    }
+}
+
+/// Conditions under which a listener stops by itself; whichever is reached first wins.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ListenerLimits {
+    pub max_messages: Option<u64>,
+    pub max_duration: Option<Duration>,
+}
+
+/// Lifecycle of a listener, as seen through its `ListenerHandle`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ListenerStatus {
+    #[default]
+    Starting,
+    /// The socket is bound and receiving.
+    Running,
+    Stopped,
+    Failed(String),
+}
+
+/// Returned when a listener is started, to follow and control it.
+#[derive(Clone, Debug)]
+pub struct ListenerHandle {
+    endpoint: Endpoint,
+    stop: Arc<AtomicBool>,
+    status: watch::Receiver<ListenerStatus>,
+}
+
+impl ListenerHandle {
+    pub(crate) fn new(
+        endpoint: Endpoint,
+        stop: Arc<AtomicBool>,
+        status: watch::Receiver<ListenerStatus>,
+    ) -> Self {
+        Self {
+            endpoint,
+            stop,
+            status,
         }
-        EndpointProto::Bp => {
-            if let Ok(sockaddr) = create_bp_sockaddr_with_string(&endpoint.endpoint) {
-                return Some(sockaddr);
-            }
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    pub fn status(&self) -> ListenerStatus {
+        self.status.borrow().clone()
+    }
+
+    pub(crate) fn status_receiver(&self) -> watch::Receiver<ListenerStatus> {
+        self.status.clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        *self.status.borrow() == ListenerStatus::Running
+    }
+
+    /// Stops the listener, like `Engine::stop_listener`.
+    pub fn abort(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Resolves once the socket is bound, or with `ListenerFailed` and the reason
+    /// it could not be.
+    pub async fn wait_ready(&self) -> Result<(), SocketEngineError> {
+        let failed = |reason: String| SocketEngineError::ListenerFailed {
+            endpoint: self.endpoint.clone(),
+            reason,
+        };
+        let mut status = self.status.clone();
+        let status = status
+            .wait_for(|status| *status != ListenerStatus::Starting)
+            .await
+            .map_err(|_| failed("Listener task ended without reporting its status".to_string()))?;
+        match &*status {
+            ListenerStatus::Failed(reason) => Err(failed(reason.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A multicast group joined by a UDP listener, see `Engine::start_multicast_listener`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MulticastGroup {
+    /// Joined on the interface with the address `interface`, or one chosen by
+    /// the system when it is `Ipv4Addr::UNSPECIFIED`.
+    V4 {
+        group: Ipv4Addr,
+        interface: Ipv4Addr,
+    },
+    /// Joined on the interface with the index `interface`, or one chosen by the
+    /// system when it is 0.
+    V6 { group: Ipv6Addr, interface: u32 },
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) struct QueuedReply {
+    pub(crate) data: Vec<u8>,
+    pub(crate) token: MessageId,
+}
+
+/// Writers of the TCP connections accepted by listeners, see `Engine::reply`.
+/// Shared by all the listeners of an engine.
+#[derive(Clone, Debug, Default)]
+pub struct ReplyQueues(Arc<Mutex<HashMap<ConnectionId, mpsc::UnboundedSender<QueuedReply>>>>);
+
+impl ReplyQueues {
+    fn register(&self) -> ReplyRegistration {
+        let id = ConnectionId(NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.0.lock().unwrap().insert(id, sender);
+        ReplyRegistration {
+            queues: self.clone(),
+            id,
+            receiver,
+        }
+    }
+
+    /// Queues `reply` for the connection, handing it back when the connection is closed.
+    pub(crate) fn push(&self, id: ConnectionId, reply: QueuedReply) -> Result<(), QueuedReply> {
+        match self.0.lock().unwrap().get(&id) {
+            Some(sender) => sender.send(reply).map_err(|e| e.0),
+            None => Err(reply),
+        }
+    }
+}
+
+// Replies of one connection, which stops taking them once dropped
+struct ReplyRegistration {
+    queues: ReplyQueues,
+    id: ConnectionId,
+    receiver: mpsc::UnboundedReceiver<QueuedReply>,
+}
+
+impl Drop for ReplyRegistration {
+    fn drop(&mut self) {
+        self.queues.0.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Per-listener settings handed to `GenericSocket::start_listener`.
+#[derive(Clone, Debug)]
+pub struct ListenerOptions {
+    /// How accepted TCP connections are split into messages
+    pub framing: Framing,
+    /// `IPV6_V6ONLY` for IPv6 UDP/TCP listeners, the system default when `None`
+    pub ipv6_only: Option<bool>,
+    /// TTL or hop limit of the packets sent from UDP and TCP listener sockets, the
+    /// system default when `None`
+    pub ttl: Option<u32>,
+    /// `SO_RCVBUF` and `SO_SNDBUF` of listener sockets, the system default when `None`
+    pub recv_buffer_size: Option<usize>,
+    pub send_buffer_size: Option<usize>,
+    /// `SO_REUSEADDR`; when `None`, set for TCP, BP and multicast UDP listeners only
+    pub reuse_address: Option<bool>,
+    /// `SO_REUSEPORT`, only available on Unix systems
+    pub reuse_port: bool,
+    /// TCP connections handled at a time, further ones are rejected; unbounded when `None`
+    pub max_connections: Option<usize>,
+    /// Size of the read buffer of each accepted TCP connection, must not be 0
+    pub tcp_buffer_size: usize,
+    /// Size of the receive buffer of UDP and BP listeners, longer datagrams are truncated
+    pub udp_buffer_size: usize,
+    /// Longest wait for the socket to become readable before checking `stop`
+    pub poll_interval: Duration,
+    pub tcp_backlog: i32,
+    /// Disables Nagle's algorithm on accepted TCP connections
+    pub tcp_nodelay: bool,
+    /// Groups a UDP listener joins once bound and leaves when it stops
+    pub multicast: Vec<MulticastGroup>,
+    pub limits: ListenerLimits,
+    /// When set, echo probes are sent back to their source instead of being delivered
+    pub echo: Arc<AtomicBool>,
+    /// Set to make the listener stop, checked between two receives
+    pub stop: Arc<AtomicBool>,
+    /// Where accepted TCP connections take the replies written to them
+    pub replies: ReplyQueues,
+    /// Switched to `Running` once the socket is bound
+    pub status: Arc<watch::Sender<ListenerStatus>>,
+    /// Runtime of the engine, where `Received` events delayed by the `with_delay`
+    /// feature are notified from
+    pub runtime: Handle,
+    /// Family the sources of received bundles are decoded with
+    #[cfg(feature = "bp")]
+    pub bp: BpConfig,
+    /// Identity accepted TCP connections are wrapped in TLS with, plaintext without one
+    #[cfg(feature = "tls")]
+    pub tls: SharedTls,
+}
+
+impl Default for ListenerOptions {
+    fn default() -> Self {
+        Self {
+            framing: Framing::Raw,
+            ipv6_only: None,
+            ttl: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            reuse_address: None,
+            reuse_port: false,
+            max_connections: None,
+            tcp_buffer_size: DEFAULT_TCP_BUFFER_SIZE,
+            udp_buffer_size: DEFAULT_UDP_BUFFER_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            tcp_backlog: DEFAULT_TCP_BACKLOG,
+            tcp_nodelay: false,
+            multicast: Vec::new(),
+            limits: ListenerLimits::default(),
+            echo: Arc::default(),
+            stop: Arc::default(),
+            replies: ReplyQueues::default(),
+            status: Arc::new(watch::channel(ListenerStatus::Starting).0),
+            runtime: TOKIO_RUNTIME.handle().clone(),
+            #[cfg(feature = "bp")]
+            bp: BpConfig::default(),
+            #[cfg(feature = "tls")]
+            tls: SharedTls::default(),
+        }
+    }
+}
+
+// Messages delivered by a listener, shared with its TCP connection handlers
+struct MessageBudget {
+    handled: AtomicU64,
+    max: Option<u64>,
+}
+
+impl MessageBudget {
+    fn new(max: Option<u64>) -> Self {
+        Self {
+            handled: AtomicU64::new(0),
+            max,
+        }
+    }
+
+    // Counts one more message, returns false once the budget is exhausted
+    fn try_take(&self) -> bool {
+        let count = self.handled.fetch_add(1, Ordering::SeqCst);
+        self.max.is_none_or(|max| count < max)
+    }
+
+    fn handled(&self) -> u64 {
+        let handled = self.handled.load(Ordering::SeqCst);
+        self.max.map_or(handled, |max| handled.min(max))
+    }
+
+    fn exhausted(&self) -> bool {
+        self.max
+            .is_some_and(|max| self.handled.load(Ordering::SeqCst) >= max)
+    }
+}
+
+/// Runs a socket call again for as long as it fails with `EINTR`.
+///
+/// Signals delivered to the process (profilers, debuggers) interrupt blocking
+/// calls; this is never a reason to tear down a listener or fail a send.
+pub fn retry_on_eintr<T, F>(mut f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    loop {
+        match f() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
         }
     }
-    None
+}
+
+/// Addresses of a UDP/TCP endpoint: the literal IP, or every address the host
+/// name resolves to. IPv6 literals need brackets around the host, e.g. `[::1]:8080`.
+pub fn resolve_socket_addrs(addr: &str) -> io::Result<Vec<SocketAddr>> {
+    if let Ok(std_sock) = addr.parse::<SocketAddr>() {
+        return Ok(vec![std_sock]);
+    }
+    let resolved: Vec<SocketAddr> = addr
+        .to_socket_addrs()
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid address `{}`: {}", addr, e),
+            )
+        })?
+        .collect();
+    if resolved.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("`{}` did not resolve to any address", addr),
+        ));
+    }
+    Ok(resolved)
+}
+
+// A host name resolving to several addresses binds or sends to the first one
+fn parse_socket_addr(addr: &str) -> Result<SocketAddr, SocketEngineError> {
+    resolve_socket_addrs(addr)
+        .map(|addrs| addrs[0])
+        .map_err(|e| SocketEngineError::AddrParse(e.to_string()))
+}
+
+/// Every address `endpoint` designates, see `resolve_socket_addrs`. BP
+/// addresses are of the family of `config`.
+pub fn endpoint_to_sockaddrs(endpoint: &Endpoint, config: &EngineConfig) -> Vec<SockAddr> {
+    #[cfg(not(feature = "bp"))]
+    let _ = config;
+    match endpoint.proto {
+        EndpointProto::Udp | EndpointProto::Tcp => resolve_socket_addrs(&endpoint.endpoint)
+            .map(|addrs| addrs.into_iter().map(SockAddr::from).collect())
+            .unwrap_or_default(),
+        #[cfg(feature = "bp")]
+        EndpointProto::Bp => create_bp_sockaddr_with_string(&endpoint.endpoint, &config.bp)
+            .into_iter()
+            .collect(),
+    }
+}
+
+pub fn endpoint_to_sockaddr(endpoint: Endpoint, config: &EngineConfig) -> Option<SockAddr> {
+    endpoint_to_sockaddrs(&endpoint, config).into_iter().next()
 }
 
 impl GenericSocket {
@@ -51,15 +410,20 @@ impl GenericSocket {
             endpoint: self.endpoint.clone(),
             sockaddr: self.sockaddr.clone(),
             listening: self.listening,
+            adopted: self.adopted,
         })
     }
 
-    pub fn new(endpoint: Endpoint) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+    /// Creates an unbound socket for `endpoint`, BP ones with the family and
+    /// protocol of `config`.
+    pub fn new(endpoint: Endpoint, config: &EngineConfig) -> Result<Self, SocketEngineError> {
+        #[cfg(not(feature = "bp"))]
+        let _ = config;
         let addr = endpoint.endpoint.clone();
         let (domain, semtype, proto, address): (Domain, Type, Protocol, SockAddr) =
             match &endpoint.proto {
                 EndpointProto::Udp => {
-                    let std_sock = addr.parse()?;
+                    let std_sock = parse_socket_addr(&addr)?;
                     (
                         Domain::for_address(std_sock),
                         Type::DGRAM,
@@ -68,7 +432,7 @@ impl GenericSocket {
                     )
                 }
                 EndpointProto::Tcp => {
-                    let std_sock = addr.parse()?;
+                    let std_sock = parse_socket_addr(&addr)?;
                     (
                         Domain::for_address(std_sock),
                         Type::STREAM,
@@ -76,193 +440,571 @@ impl GenericSocket {
                         SockAddr::from(std_sock),
                     )
                 }
+                #[cfg(feature = "bp")]
                 EndpointProto::Bp => (
-                    Domain::from(AF_BP),
+                    Domain::from(config.bp.family),
                     Type::DGRAM,
-                    Protocol::UDP,
-                    create_bp_sockaddr_with_string(&addr)?,
+                    Protocol::from(config.bp.protocol),
+                    create_bp_sockaddr_with_string(&addr, &config.bp)?,
                 ),
             };
 
-        let socket = Socket::new(domain, semtype, Some(proto))?;
+        let socket =
+            Socket::new(domain, semtype, Some(proto)).map_err(SocketEngineError::socket)?;
 
-        return Ok(Self {
-            socket: socket,
+        Ok(Self {
+            socket,
             endpoint,
             sockaddr: address,
             listening: false,
+            adopted: false,
+        })
+    }
+
+    // See `AdoptedSocket::new`
+    pub(crate) fn from_socket(
+        socket: Socket,
+        endpoint: Endpoint,
+    ) -> Result<Self, SocketEngineError> {
+        let domain = socket.domain().map_err(SocketEngineError::socket)?;
+        let config = EngineConfig::default();
+        #[cfg(feature = "bp")]
+        let config = config.bp(BpConfig {
+            family: domain.into(),
+            ..BpConfig::default()
         });
+        let sockaddr = endpoint_to_sockaddr(endpoint.clone(), &config).ok_or_else(|| {
+            SocketEngineError::AddrParse(format!("Invalid address for {}", endpoint))
+        })?;
+        let semtype = match endpoint.proto {
+            EndpointProto::Udp => Type::DGRAM,
+            EndpointProto::Tcp => Type::STREAM,
+            #[cfg(feature = "bp")]
+            EndpointProto::Bp => Type::DGRAM,
+        };
+        let mismatch = |reason: String| Err(SocketEngineError::SocketMismatch(reason));
+        if domain != sockaddr.domain() {
+            return mismatch(format!("Socket address family does not match {}", endpoint));
+        }
+        if socket.r#type().map_err(SocketEngineError::socket)? != semtype {
+            return mismatch(format!("Socket type does not match {}", endpoint));
+        }
+        if !endpoint.proto.is_bp()
+            && socket.local_addr().map_err(SocketEngineError::socket)? != sockaddr
+        {
+            return mismatch(format!("Socket is not bound to {}", endpoint));
+        }
+
+        Ok(Self {
+            socket,
+            endpoint,
+            sockaddr,
+            listening: false,
+            adopted: true,
+        })
     }
 
-    fn prepare_socket(&mut self) -> io::Result<()> {
-        match self.endpoint.proto {
-            EndpointProto::Udp => {
-                self.socket.set_nonblocking(true)?;
-                self.socket.set_reuse_address(false)?;
-                self.socket.set_reuse_port(false)?;
-                self.socket.bind(&SockAddr::from(self.sockaddr.clone()))?;
+    /// Connects the TCP socket to the first of `candidates` that accepts within
+    /// `timeout`, using a fresh socket for each attempt after the first one.
+    pub async fn connect_any(
+        &mut self,
+        candidates: &[SockAddr],
+        timeout: Duration,
+    ) -> io::Result<()> {
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, "No address to connect to");
+        for (attempt, addr) in candidates.iter().enumerate() {
+            if attempt > 0 {
+                self.socket = Socket::new(addr.domain(), Type::STREAM, Some(Protocol::TCP))?;
             }
-            EndpointProto::Tcp => {
-                self.socket.set_nonblocking(true)?;
-                self.socket.set_reuse_address(true)?;
-                self.socket.set_reuse_port(false)?;
-                self.socket.bind(&SockAddr::from(self.sockaddr.clone()))?;
+            match connect_within(&self.socket, addr, timeout).await {
+                Ok(()) => return Ok(()),
+                Err(e) => last_err = e,
             }
-            EndpointProto::Bp => {
-                self.socket.set_nonblocking(true)?;
-                self.socket.set_reuse_address(true)?;
-                self.socket.set_reuse_port(false)?;
-                self.socket.bind(&SockAddr::from(self.sockaddr.clone()))?;
+        }
+        Err(last_err)
+    }
+
+    /// Sends a datagram to `addr`, waiting on the runtime rather than blocking
+    /// its worker while the send buffer is full.
+    pub async fn send_datagram(&self, data: &[u8], addr: &SockAddr) -> io::Result<usize> {
+        send_when_writable(&self.socket, || {
+            self.socket
+                .send_to_with_flags(data, addr, libc::MSG_DONTWAIT)
+        })
+        .await
+    }
+
+    /// Writes all of `data` to a connected TCP socket, waiting on the runtime
+    /// rather than blocking its worker while the peer is slow to read.
+    pub async fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let written = send_when_writable(&self.socket, || {
+                self.socket.send_with_flags(data, libc::MSG_DONTWAIT)
+            })
+            .await?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            data = &data[written..];
+        }
+        Ok(())
+    }
+
+    /// Whether the peer of a connected TCP socket has closed or reset the
+    /// connection. Looks without blocking and without consuming any data.
+    pub fn peer_closed(&self) -> bool {
+        let mut buf = [MaybeUninit::<u8>::uninit(); 1];
+        match retry_on_eintr(|| {
+            self.socket
+                .recv_with_flags(&mut buf, libc::MSG_PEEK | libc::MSG_DONTWAIT)
+        }) {
+            Ok(0) => true,
+            Ok(_) => false,
+            Err(e) => e.kind() != io::ErrorKind::WouldBlock,
+        }
+    }
+
+    /// Sets the TTL of IPv4 packets, or the hop limit of IPv6 ones. BP sockets
+    /// have no such option and are left unchanged.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        if self.endpoint.proto.is_bp() {
+            return Ok(());
+        }
+        // The family of the socket, which may differ from the first address a
+        // host name resolved to after `connect_any`
+        if self.socket.domain()? == Domain::IPV6 {
+            self.socket.set_unicast_hops_v6(ttl)
+        } else {
+            self.socket.set_ttl(ttl)
+        }
+    }
+
+    /// Sets the kernel's receive and send buffers (`SO_RCVBUF`, `SO_SNDBUF`) when
+    /// given. The system may round or cap the sizes without failing, e.g. Linux
+    /// doubles them and caps them at `net.core.rmem_max` / `wmem_max`.
+    pub fn set_buffer_sizes(&self, recv: Option<usize>, send: Option<usize>) -> io::Result<()> {
+        if let Some(size) = recv {
+            self.socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = send {
+            self.socket.set_send_buffer_size(size)?;
+        }
+        Ok(())
+    }
+
+    fn prepare_socket(&mut self, options: &ListenerOptions) -> io::Result<()> {
+        if self.adopted {
+            return self.socket.set_nonblocking(true);
+        }
+        if let Some(only_v6) = options.ipv6_only.filter(|_| self.sockaddr.is_ipv6()) {
+            self.socket.set_only_v6(only_v6)?;
+        }
+        if let Some(ttl) = options.ttl {
+            self.set_ttl(ttl)?;
+        }
+        self.set_buffer_sizes(options.recv_buffer_size, options.send_buffer_size)?;
+        let reuse_address = options.reuse_address.unwrap_or(match self.endpoint.proto {
+            // Other receivers of the groups may share the port
+            EndpointProto::Udp => !options.multicast.is_empty(),
+            _ => true,
+        });
+        self.socket.set_nonblocking(true)?;
+        self.socket.set_reuse_address(reuse_address)?;
+        #[cfg(unix)]
+        self.socket.set_reuse_port(options.reuse_port)?;
+        #[cfg(not(unix))]
+        if options.reuse_port {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "SO_REUSEPORT is not available on this platform",
+            ));
+        }
+        self.socket.bind(&self.sockaddr)?;
+        for group in &options.multicast {
+            match group {
+                MulticastGroup::V4 { group, interface } => {
+                    self.socket.join_multicast_v4(group, interface)?
+                }
+                MulticastGroup::V6 { group, interface } => {
+                    self.socket.join_multicast_v6(group, *interface)?
+                }
             }
         }
         Ok(())
     }
 
-    pub fn start_listener(
+    /// Binds the socket and runs the receive loop until the limits in `options`
+    /// are reached, returning why it stopped and how many messages were delivered.
+    ///
+    /// Must run on a Tokio runtime with I/O enabled: the loop waits for the socket
+    /// to become readable instead of holding a thread.
+    pub async fn start_listener(
         &mut self,
         observers: Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
-    ) -> io::Result<()> {
+        options: ListenerOptions,
+    ) -> Result<(ListenerStopReason, u64), SocketEngineError> {
         if self.listening {
-            return Ok(());
+            return Err(SocketEngineError::AlreadyInUse(self.endpoint.clone()));
         }
 
         self.listening = true;
-        self.prepare_socket()?;
-
-        match &self.endpoint.proto {
-            EndpointProto::Udp | EndpointProto::Bp => {
-                let endpoint_clone = self.endpoint.clone();
-                let socket = self.socket.try_clone()?;
-                let observers_cloned = observers.clone();
-                loop {
-                    let mut buffer: Vec<MaybeUninit<u8>> = Vec::with_capacity(65507);
-                    unsafe {
-                        buffer.set_len(65507);
-                    }
-                    match socket.recv_from(&mut buffer.as_mut_slice()) {
-                        Ok((size, peer_addr)) => {
-                            let data: Vec<u8> = unsafe {
-                                buffer.set_len(size);
-                                std::mem::transmute(buffer)
-                            };
-
-                            let client_addr_str = match &self.endpoint.proto {
-                                EndpointProto::Udp => match peer_addr.as_socket() {
-                                    Some(addr) => format!("{}:{}", addr.ip(), addr.port()),
-                                    None => format!("{:?}", peer_addr),
-                                },
-                                EndpointProto::Bp => unsafe {
-                                    let addr_ptr = peer_addr.as_ptr() as *const SockAddrBp;
-                                    (*addr_ptr).to_string()
-                                },
-                                _ => String::new(),
-                            };
+        self.prepare_socket(&options)
+            .map_err(SocketEngineError::bind)?;
+        if self.endpoint.proto == EndpointProto::Tcp {
+            self.socket
+                .listen(options.tcp_backlog)
+                .map_err(SocketEngineError::bind)?;
+        }
+        options.status.send_replace(ListenerStatus::Running);
+        notify_all_observers(
+            &observers,
+            &SocketEngineEvent::Connection(ConnectionEvent::ListenerStarted {
+                endpoint: self.endpoint.clone(),
+            }),
+        );
 
-                            notify_all_observers(
-                                &observers_cloned,
-                                &SocketEngineEvent::Data(DataEvent::Received {
-                                    data,
-                                    from: Endpoint {
-                                        proto: self.endpoint.proto.clone(),
-                                        endpoint: client_addr_str,
-                                    },
-                                }),
-                            );
-                        }
-                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                            thread::sleep(std::time::Duration::from_millis(10));
-                        }
-                        Err(_e) => {
-                            // TODO: Not sur if this is the best way to handle errors
-                            notify_all_observers(
-                                &observers_cloned,
-                                &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
-                                    endpoint: endpoint_clone.clone(),
-                                    reason: "UDP/BP read error".to_string(),
-                                }),
-                            );
-                            continue;
-                        }
-                    }
-                }
-            }
+        let started = Instant::now();
+        let limits = options.limits;
+        let budget = Arc::new(MessageBudget::new(limits.max_messages));
+        let should_stop = |budget: &MessageBudget| {
+            options.stop.load(Ordering::Relaxed)
+                || budget.exhausted()
+                || limits
+                    .max_duration
+                    .is_some_and(|max| started.elapsed() >= max)
+        };
 
-            EndpointProto::Tcp => {
-                self.socket.listen(128)?;
-                let endpoint_clone = self.endpoint.clone();
+        let readiness = register_readiness(&self.socket);
 
-                let socket = self.socket.try_clone()?;
-                loop {
-                    match socket.accept() {
-                        Ok((stream, peer_addr)) => {
-                            let client_addr = match peer_addr.as_socket() {
-                                Some(addr) => format!("{}:{}", addr.ip(), addr.port()),
+        // UDP and BP datagrams
+        if self.endpoint.proto != EndpointProto::Tcp {
+            let endpoint_clone = self.endpoint.clone();
+            let socket = self.socket.try_clone().map_err(SocketEngineError::socket)?;
+            let observers_cloned = observers.clone();
+            // Reused by every receive, each datagram is copied out at its size
+            let mut buffer = vec![MaybeUninit::<u8>::uninit(); options.udp_buffer_size];
+            while !should_stop(&budget) {
+                match retry_on_eintr(|| socket.recv_from(&mut buffer)) {
+                    Ok((size, peer_addr)) => {
+                        // SAFETY: `recv_from` initialized the first `size` bytes
+                        let data =
+                            unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast(), size) }
+                                .to_vec();
+                        let client_addr_str = match &self.endpoint.proto {
+                            EndpointProto::Udp => match peer_addr.as_socket() {
+                                Some(addr) => addr.to_string(),
                                 None => format!("{:?}", peer_addr),
-                            };
-                            // TODO: should we add ConnectionAccepted event?
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Connection(ConnectionEvent::Established {
-                                    remote: Endpoint {
-                                        proto: EndpointProto::Tcp,
-                                        endpoint: client_addr,
-                                    },
-                                }),
-                            );
-                            let observers_cloned = observers.clone();
-                            let endpoint_for_handler = endpoint_clone.clone();
-                            TOKIO_RUNTIME.spawn(async move {
-                                handle_tcp_connection(
-                                    stream.into(),
+                            },
+                            #[cfg(feature = "bp")]
+                            EndpointProto::Bp => {
+                                match bp_sockaddr_to_endpoint(&peer_addr, &options.bp) {
+                                    Ok(eid) => eid.endpoint,
+                                    Err(e) => {
+                                        notify_all_observers(
+                                            &observers_cloned,
+                                            &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                                                endpoint: endpoint_clone.clone(),
+                                                error: SocketEngineError::receive(e),
+                                            }),
+                                        );
+                                        continue;
+                                    }
+                                }
+                            }
+                            _ => String::new(),
+                        };
+                        let from = Endpoint {
+                            proto: self.endpoint.proto.clone(),
+                            endpoint: client_addr_str,
+                        };
+                        if options.echo.load(Ordering::Relaxed) && is_echo_probe(&data) {
+                            if retry_on_eintr(|| socket.send_to(&data, &peer_addr)).is_ok() {
+                                notify_all_observers(
                                     &observers_cloned,
-                                    endpoint_for_handler,
-                                )
-                                .await;
-                            });
-                        }
-                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Connection(ConnectionEvent::Closed {
-                                    remote: None,
-                                }),
-                            );
+                                    &SocketEngineEvent::Data(DataEvent::Echoed {
+                                        from,
+                                        listener: endpoint_clone.clone(),
+                                        bytes: data.len(),
+                                    }),
+                                );
+                            }
+                            continue;
                         }
-                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                            thread::sleep(std::time::Duration::from_millis(10));
+                        if !budget.try_take() {
+                            break;
                         }
 
-                        Err(e) => {
-                            notify_all_observers(
-                                &observers,
-                                &SocketEngineEvent::Error(ErrorEvent::SocketError {
-                                    endpoint: endpoint_clone.clone(),
-                                    reason: e.to_string(),
-                                }),
-                            );
-                            break;
+                        notify_received(
+                            &observers_cloned,
+                            &SocketEngineEvent::Data(DataEvent::Received {
+                                wire_bytes: data.len(),
+                                data,
+                                from,
+                                listener: endpoint_clone.clone(),
+                                connection: None,
+                                local: false,
+                            }),
+                            &options.runtime,
+                        );
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        wait_readable(readiness.as_ref(), options.poll_interval).await;
+                    }
+                    Err(e) => {
+                        let back_off = match e.raw_os_error() {
+                            // ICMP errors for earlier sends from the socket, the
+                            // next datagram is not affected
+                            Some(
+                                libc::ECONNREFUSED
+                                | libc::ECONNRESET
+                                | libc::EHOSTUNREACH
+                                | libc::ENETUNREACH,
+                            ) => false,
+                            // Short of memory, the next receive would likely fail too
+                            Some(libc::ENOBUFS | libc::ENOMEM) => true,
+                            // e.g. EBADF: the socket is unusable, failing again at once
+                            _ => return Err(SocketEngineError::receive(e)),
+                        };
+                        notify_all_observers(
+                            &observers_cloned,
+                            &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                                endpoint: endpoint_clone.clone(),
+                                error: SocketEngineError::receive(e),
+                            }),
+                        );
+                        if back_off {
+                            tokio::time::sleep(options.poll_interval).await;
                         }
                     }
                 }
             }
+        } else {
+            let endpoint_clone = self.endpoint.clone();
+
+            let socket = self.socket.try_clone().map_err(SocketEngineError::socket)?;
+            let connection_slots = options
+                .max_connections
+                .map(|max| (Arc::new(Semaphore::new(max)), max));
+            while !should_stop(&budget) {
+                match retry_on_eintr(|| socket.accept()) {
+                    Ok((stream, peer_addr)) => {
+                        let client_addr = match peer_addr.as_socket() {
+                            Some(addr) => addr.to_string(),
+                            None => format!("{:?}", peer_addr),
+                        };
+                        // Held by the connection handler until it returns
+                        let slot = match &connection_slots {
+                            Some((slots, max)) => match slots.clone().try_acquire_owned() {
+                                Ok(permit) => Some(permit),
+                                Err(_) => {
+                                    drop(stream);
+                                    notify_all_observers(
+                                        &observers,
+                                        &SocketEngineEvent::Error(ErrorEvent::SocketError {
+                                            endpoint: endpoint_clone.clone(),
+                                            error: SocketEngineError::TooManyConnections {
+                                                remote: Endpoint {
+                                                    proto: EndpointProto::Tcp,
+                                                    endpoint: client_addr,
+                                                },
+                                                max: *max,
+                                            },
+                                        }),
+                                    );
+                                    continue;
+                                }
+                            },
+                            None => None,
+                        };
+                        #[cfg(feature = "tracing")]
+                        let span =
+                            crate::tracing_support::connection_span(&client_addr, &endpoint_clone);
+                        notify_all_observers(
+                            &observers,
+                            &SocketEngineEvent::Connection(ConnectionEvent::Accepted {
+                                remote: Endpoint {
+                                    proto: EndpointProto::Tcp,
+                                    endpoint: client_addr,
+                                },
+                                local: endpoint_clone.clone(),
+                            }),
+                        );
+                        let observers_cloned = observers.clone();
+                        let endpoint_for_handler = endpoint_clone.clone();
+                        let budget = budget.clone();
+                        let options = options.clone();
+                        let connection = async move {
+                            handle_tcp_connection(
+                                stream.into(),
+                                &observers_cloned,
+                                endpoint_for_handler,
+                                &options,
+                                &budget,
+                            )
+                            .await;
+                            drop(slot);
+                        };
+                        #[cfg(feature = "tracing")]
+                        let connection = tracing::Instrument::instrument(connection, span);
+                        tokio::spawn(connection);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        wait_readable(readiness.as_ref(), options.poll_interval).await;
+                    }
+
+                    Err(e) => return Err(SocketEngineError::receive(e)),
+                }
+            }
         }
-        Ok(())
+        for group in &options.multicast {
+            // Best effort, closing the socket leaves the groups anyway
+            let _ = match group {
+                MulticastGroup::V4 { group, interface } => {
+                    self.socket.leave_multicast_v4(group, interface)
+                }
+                MulticastGroup::V6 { group, interface } => {
+                    self.socket.leave_multicast_v6(group, *interface)
+                }
+            };
+        }
+        let reason = if options.stop.load(Ordering::Relaxed) {
+            ListenerStopReason::Stopped
+        } else {
+            ListenerStopReason::LimitReached
+        };
+        Ok((reason, budget.handled()))
+    }
+}
+
+// Registers a duplicate of a listener socket with the runtime's reactor, to
+// wait for it to become readable. `None` for sockets the reactor cannot watch,
+// such as a BP module without poll support, which are polled instead.
+pub(crate) fn register_readiness(socket: &Socket) -> Option<AsyncFd<Socket>> {
+    let socket = socket.try_clone().ok()?;
+    // SAFETY: the `AsyncFd` owns this duplicate, which stays open and is not
+    // replaced until the `AsyncFd` is dropped
+    unsafe { AsyncFd::register_with_interest(socket, Interest::READABLE) }.ok()
+}
+
+// Registers a duplicate of a socket with the runtime's reactor, to wait for it
+// to become writable. `None` for sockets the reactor cannot watch.
+fn register_writable(socket: &Socket) -> Option<AsyncFd<Socket>> {
+    let socket = socket.try_clone().ok()?;
+    // SAFETY: the `AsyncFd` owns this duplicate, which stays open and is not
+    // replaced until the `AsyncFd` is dropped
+    unsafe { AsyncFd::register_with_interest(socket, Interest::WRITABLE) }.ok()
+}
+
+// Returns once the socket may accept more data. Sockets the reactor cannot
+// watch are polled every `WRITABLE_POLL_INTERVAL`.
+async fn wait_writable(readiness: Option<&AsyncFd<Socket>>) -> io::Result<()> {
+    match readiness {
+        Some(fd) => fd.writable().await?.clear_ready(),
+        None => tokio::time::sleep(WRITABLE_POLL_INTERVAL).await,
     }
+    Ok(())
+}
+
+// Runs `send` without letting it block: it is retried each time the socket
+// becomes writable again for as long as it would block. `send` must pass
+// `MSG_DONTWAIT`.
+async fn send_when_writable<T>(
+    socket: &Socket,
+    mut send: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut readiness = None;
+    loop {
+        match retry_on_eintr(&mut send) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                // Only registered once a send would block, which is rare
+                let fd = readiness.get_or_insert_with(|| register_writable(socket));
+                wait_writable(fd.as_ref()).await?;
+            }
+            res => return res,
+        }
+    }
+}
+
+// Connects `socket` to `addr` within `timeout` without blocking the runtime
+// worker. The socket is only nonblocking while connecting.
+async fn connect_within(socket: &Socket, addr: &SockAddr, timeout: Duration) -> io::Result<()> {
+    socket.set_nonblocking(true)?;
+    let connected = match socket.connect(addr) {
+        // An interrupted connect goes on in the background, like one in progress
+        Err(e)
+            if e.raw_os_error() == Some(libc::EINPROGRESS)
+                || e.kind() == io::ErrorKind::Interrupted =>
+        {
+            let readiness = register_writable(socket);
+            match tokio::time::timeout(timeout, wait_writable(readiness.as_ref())).await {
+                Ok(Ok(())) => socket.take_error()?.map_or(Ok(()), Err),
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection timed out",
+                )),
+            }
+        }
+        res => res,
+    };
+    socket.set_nonblocking(false)?;
+    connected
+}
+
+// Returns once the socket may have something to receive, or after `interval`
+// so that the listener can check whether to stop
+async fn wait_readable(readiness: Option<&AsyncFd<Socket>>, interval: Duration) {
+    match readiness {
+        Some(fd) => {
+            if let Ok(Ok(mut guard)) = tokio::time::timeout(interval, fd.readable()).await {
+                // The listener receives until `WouldBlock` before waiting again
+                guard.clear_ready();
+            }
+        }
+        None => tokio::time::sleep(interval).await,
+    }
+}
+
+enum Incoming {
+    Read(io::Result<usize>),
+    Reply(QueuedReply),
 }
 
 async fn handle_tcp_connection(
-    mut stream: std::net::TcpStream,
+    stream: std::net::TcpStream,
     observers: &Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
     local_endpoint: Endpoint,
+    options: &ListenerOptions,
+    budget: &MessageBudget,
 ) {
     let peer_addr = match stream.peer_addr() {
         Ok(addr) => addr,
-        Err(_) => {
+        Err(e) => {
+            notify_all_observers(
+                observers,
+                &SocketEngineEvent::Error(ErrorEvent::SocketError {
+                    endpoint: local_endpoint.clone(),
+                    error: SocketEngineError::receive(e),
+                }),
+            );
+            return;
+        }
+    };
+    if options.tcp_nodelay {
+        // Best effort, the connection works the same without it
+        let _ = stream.set_nodelay(true);
+    }
+    let stream = match stream
+        .set_nonblocking(true)
+        .and_then(|()| TcpStream::from_std(stream))
+    {
+        Ok(stream) => stream,
+        Err(e) => {
             notify_all_observers(
                 observers,
                 &SocketEngineEvent::Error(ErrorEvent::SocketError {
                     endpoint: local_endpoint.clone(),
-                    reason: "Failed to get peer address".to_string(),
+                    error: SocketEngineError::receive(e),
                 }),
             );
             return;
@@ -271,13 +1013,151 @@ async fn handle_tcp_connection(
 
     let peer_endpoint = Endpoint {
         proto: EndpointProto::Tcp,
-        endpoint: format!("{}:{}", peer_addr.ip(), peer_addr.port()),
+        endpoint: peer_addr.to_string(),
     };
-    let mut buffer = [0; 1024];
+
+    #[cfg(feature = "tls")]
+    if let Some(acceptor) = tls::acceptor(&options.tls) {
+        match acceptor.accept(stream).await {
+            Ok(stream) => {
+                serve_connection(
+                    stream,
+                    peer_endpoint,
+                    observers,
+                    local_endpoint,
+                    options,
+                    budget,
+                )
+                .await
+            }
+            Err(e) => {
+                notify_all_observers(
+                    observers,
+                    &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                        endpoint: local_endpoint,
+                        error: SocketEngineError::receive(e),
+                    }),
+                );
+                notify_all_observers(
+                    observers,
+                    &SocketEngineEvent::Connection(ConnectionEvent::Closed {
+                        remote: Some(peer_endpoint),
+                    }),
+                );
+            }
+        }
+        return;
+    }
+    serve_connection(
+        stream,
+        peer_endpoint,
+        observers,
+        local_endpoint,
+        options,
+        budget,
+    )
+    .await;
+}
+
+/// An accepted TCP connection, in plaintext or wrapped in TLS.
+trait AcceptedStream: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Shuts both directions of the connection down, without waiting.
+    fn abort(&self);
+}
+
+impl AcceptedStream for TcpStream {
+    fn abort(&self) {
+        let _ = SockRef::from(self).shutdown(std::net::Shutdown::Both);
+    }
+}
+
+#[cfg(feature = "tls")]
+impl AcceptedStream for tokio_rustls::server::TlsStream<TcpStream> {
+    fn abort(&self) {
+        self.get_ref().0.abort();
+    }
+}
+
+// Delivers the messages of an accepted connection and writes the replies to it,
+// until either side closes it
+async fn serve_connection(
+    mut stream: impl AcceptedStream,
+    peer_endpoint: Endpoint,
+    observers: &Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
+    local_endpoint: Endpoint,
+    options: &ListenerOptions,
+    budget: &MessageBudget,
+) {
+    let mut buffer = vec![0; options.tcp_buffer_size];
+    let mut assembler = options.framing.assembler();
+    let mut replies = options.replies.register();
+    // In raw mode a probe may be split across reads or be larger than the
+    // buffer, so a connection whose first read starts with one is echoed as a
+    // whole. Other framings check each assembled message.
+    let raw = matches!(options.framing, Framing::Raw);
+    let mut echo_stream = None;
 
     loop {
-        match stream.read(&mut buffer) {
+        let incoming = tokio::select! {
+            read = stream.read(&mut buffer) => Incoming::Read(read),
+            Some(reply) = replies.receiver.recv() => Incoming::Reply(reply),
+        };
+        let read = match incoming {
+            Incoming::Read(read) => read,
+            Incoming::Reply(reply) => {
+                let bytes = reply.data.len();
+                let encoded = assembler.encode(reply.data);
+                notify_all_observers(
+                    observers,
+                    &SocketEngineEvent::Data(DataEvent::Sending {
+                        token: reply.token.clone(),
+                        to: peer_endpoint.clone(),
+                        bytes,
+                        local: false,
+                    }),
+                );
+                let written = match &encoded {
+                    Ok(wire) => write_flushed(&mut stream, wire)
+                        .await
+                        .map_err(SocketEngineError::send),
+                    Err(error) => Err(SocketEngineError::Frame {
+                        peer: peer_endpoint.clone(),
+                        error: error.clone(),
+                    }),
+                };
+                let event = match written {
+                    Ok(()) => SocketEngineEvent::Data(DataEvent::Sent {
+                        token: reply.token,
+                        to: peer_endpoint.clone(),
+                        bytes_sent: bytes,
+                        wire_bytes: encoded.map_or(0, |wire| wire.len()),
+                        from: Some(local_endpoint.clone()),
+                        local: false,
+                    }),
+                    Err(error) => SocketEngineEvent::Error(ErrorEvent::SendFailed {
+                        endpoint: peer_endpoint.clone(),
+                        token: reply.token,
+                        error,
+                    }),
+                };
+                notify_all_observers(observers, &event);
+                continue;
+            }
+        };
+        match read {
             Ok(0) => {
+                if let Err(e) = assembler.finish() {
+                    notify_all_observers(
+                        observers,
+                        &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                            endpoint: local_endpoint.clone(),
+                            error: SocketEngineError::Frame {
+                                peer: peer_endpoint.clone(),
+                                error: e,
+                            },
+                        }),
+                    );
+                }
                 notify_all_observers(
                     observers,
                     &SocketEngineEvent::Connection(ConnectionEvent::Closed {
@@ -287,22 +1167,123 @@ async fn handle_tcp_connection(
                 break;
             }
             Ok(size) => {
-                let received_data = buffer[..size].to_vec();
+                let chunk = &buffer[..size];
+                let echo_stream = *echo_stream.get_or_insert_with(|| {
+                    raw && options.echo.load(Ordering::Relaxed) && is_echo_probe(chunk)
+                });
+                if echo_stream {
+                    if !echo(
+                        &mut stream,
+                        chunk,
+                        observers,
+                        &peer_endpoint,
+                        &local_endpoint,
+                    )
+                    .await
+                    {
+                        return;
+                    }
+                    continue;
+                }
+                // Heartbeat probes of the peer's engine, answered right away
+                let mut chunk = chunk;
+                if raw {
+                    if let Some(rest) = chunk.strip_prefix(HEARTBEAT_PING) {
+                        if !write_or_close(&mut stream, HEARTBEAT_PONG, observers, &peer_endpoint)
+                            .await
+                        {
+                            return;
+                        }
+                        chunk = rest;
+                        if chunk.is_empty() {
+                            continue;
+                        }
+                    }
+                }
+                let messages = match assembler.push(chunk) {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        notify_all_observers(
+                            observers,
+                            &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                                endpoint: local_endpoint.clone(),
+                                error: SocketEngineError::Frame {
+                                    peer: peer_endpoint.clone(),
+                                    error: e,
+                                },
+                            }),
+                        );
+                        stream.abort();
+                        notify_all_observers(
+                            observers,
+                            &SocketEngineEvent::Connection(ConnectionEvent::Closed {
+                                remote: Some(peer_endpoint.clone()),
+                            }),
+                        );
+                        break;
+                    }
+                };
+                let overhead = assembler.overhead();
 
-                notify_all_observers(
-                    observers,
-                    &SocketEngineEvent::Data(DataEvent::Received {
-                        data: received_data,
-                        from: peer_endpoint.clone(),
-                    }),
-                );
+                for received_data in messages {
+                    if !raw && received_data == HEARTBEAT_PING {
+                        let Ok(pong) = assembler.encode(HEARTBEAT_PONG.to_vec()) else {
+                            continue;
+                        };
+                        if !write_or_close(&mut stream, &pong, observers, &peer_endpoint).await {
+                            return;
+                        }
+                        continue;
+                    }
+                    if !raw && options.echo.load(Ordering::Relaxed) && is_echo_probe(&received_data)
+                    {
+                        // Probes are no larger than the messages the assembler accepts
+                        let Ok(echoed) = assembler.encode(received_data) else {
+                            continue;
+                        };
+                        if !echo(
+                            &mut stream,
+                            &echoed,
+                            observers,
+                            &peer_endpoint,
+                            &local_endpoint,
+                        )
+                        .await
+                        {
+                            return;
+                        }
+                        continue;
+                    }
+                    if !budget.try_take() {
+                        stream.abort();
+                        notify_all_observers(
+                            observers,
+                            &SocketEngineEvent::Connection(ConnectionEvent::Closed {
+                                remote: Some(peer_endpoint.clone()),
+                            }),
+                        );
+                        return;
+                    }
+                    notify_received(
+                        observers,
+                        &SocketEngineEvent::Data(DataEvent::Received {
+                            wire_bytes: received_data.len() + overhead,
+                            data: received_data,
+                            from: peer_endpoint.clone(),
+                            listener: local_endpoint.clone(),
+                            connection: Some(replies.id),
+                            local: false,
+                        }),
+                        &options.runtime,
+                    );
+                }
             }
-            Err(_e) => {
+            Err(e) => {
                 notify_all_observers(
                     observers,
                     &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
                         endpoint: local_endpoint,
-                        reason: format!("{}", peer_endpoint),
+                        error: SocketEngineError::receive(e),
                     }),
                 );
                 break;
@@ -310,3 +1291,202 @@ async fn handle_tcp_connection(
         }
     }
 }
+
+// Writes to an accepted connection on the engine's own behalf. On failure the
+// connection is shut down and reported closed, and `false` is returned.
+async fn write_or_close(
+    stream: &mut impl AcceptedStream,
+    wire: &[u8],
+    observers: &Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
+    peer_endpoint: &Endpoint,
+) -> bool {
+    if write_flushed(stream, wire).await.is_err() {
+        stream.abort();
+        notify_all_observers(
+            observers,
+            &SocketEngineEvent::Connection(ConnectionEvent::Closed {
+                remote: Some(peer_endpoint.clone()),
+            }),
+        );
+        return false;
+    }
+    true
+}
+
+// TLS buffers what is written until it is flushed
+async fn write_flushed(stream: &mut impl AcceptedStream, wire: &[u8]) -> io::Result<()> {
+    stream.write_all(wire).await?;
+    stream.flush().await
+}
+
+// Sends echo probes back over an accepted connection, see `write_or_close`
+async fn echo(
+    stream: &mut impl AcceptedStream,
+    wire: &[u8],
+    observers: &Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
+    peer_endpoint: &Endpoint,
+    local_endpoint: &Endpoint,
+) -> bool {
+    if !write_or_close(stream, wire, observers, peer_endpoint).await {
+        return false;
+    }
+    notify_all_observers(
+        observers,
+        &SocketEngineEvent::Data(DataEvent::Echoed {
+            from: peer_endpoint.clone(),
+            listener: local_endpoint.clone(),
+            bytes: wire.len(),
+        }),
+    );
+    true
+}
+
+// Receives without waiting, whatever the blocking mode of the socket
+fn recv_nonblocking(socket: &Socket, buffer: &mut [u8]) -> io::Result<usize> {
+    // SAFETY: `recv` only writes initialized bytes, and `u8` and
+    // `MaybeUninit<u8>` have the same layout
+    let buffer = unsafe { &mut *(buffer as *mut [u8] as *mut [MaybeUninit<u8>]) };
+    retry_on_eintr(|| socket.recv_with_flags(buffer, libc::MSG_DONTWAIT))
+}
+
+/// Delivers what the peer of an outgoing TCP connection sends back as `Received`
+/// events, until either side closes the connection. `Closed` is then emitted,
+/// unless `state` shows it already was. Answers to heartbeat probes are only
+/// signalled through `state`. With `idle_timeout`, the connection is closed once
+/// the peer has sent nothing for that long.
+///
+/// The socket keeps its blocking mode, so sends can go on writing to it.
+pub(crate) fn spawn_reply_reader(
+    socket: &Socket,
+    peer: Endpoint,
+    observers: Vec<Arc<Mutex<dyn EngineObserver + Send + Sync>>>,
+    buffer_size: usize,
+    max_frame_size: Option<usize>,
+    idle_timeout: Option<Duration>,
+    state: Arc<ConnectionState>,
+) {
+    let local = match socket.local_addr().map(|addr| addr.as_socket()) {
+        Ok(Some(addr)) => Endpoint {
+            proto: EndpointProto::Tcp,
+            endpoint: addr.to_string(),
+        },
+        _ => peer.clone(),
+    };
+    let readiness = socket.try_clone().and_then(|socket| {
+        // SAFETY: the `AsyncFd` owns this duplicate, which stays open and is not
+        // replaced until the `AsyncFd` is dropped
+        unsafe { AsyncFd::register_with_interest(socket, Interest::READABLE) }
+            .map_err(io::Error::from)
+    });
+    let readiness = match readiness {
+        Ok(readiness) => readiness,
+        Err(e) => {
+            notify_all_observers(
+                &observers,
+                &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                    endpoint: local,
+                    error: SocketEngineError::receive(e),
+                }),
+            );
+            return;
+        }
+    };
+
+    // Called by the send that set the connection up, on the engine's runtime
+    let runtime = Handle::current();
+    tokio::spawn(async move {
+        let mut buffer = vec![0; buffer_size];
+        let mut decoder = max_frame_size.map(FrameDecoder::new);
+        let receive_failed = |error| {
+            notify_all_observers(
+                &observers,
+                &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                    endpoint: local.clone(),
+                    error,
+                }),
+            );
+        };
+        loop {
+            let ready = match idle_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, readiness.readable()).await,
+                None => Ok(readiness.readable().await),
+            };
+            let mut guard = match ready {
+                Ok(Ok(guard)) => guard,
+                Ok(Err(e)) => {
+                    receive_failed(SocketEngineError::receive(e));
+                    break;
+                }
+                Err(_) => {
+                    let _ = readiness.get_ref().shutdown(std::net::Shutdown::Both);
+                    break;
+                }
+            };
+            let size = match guard.try_io(|fd| recv_nonblocking(fd.get_ref(), &mut buffer)) {
+                Ok(Ok(0)) => {
+                    if let Some(Err(e)) = decoder.as_ref().map(FrameDecoder::finish) {
+                        receive_failed(SocketEngineError::Frame {
+                            peer: peer.clone(),
+                            error: e,
+                        });
+                    }
+                    break;
+                }
+                Ok(Ok(size)) => size,
+                Ok(Err(e)) => {
+                    receive_failed(SocketEngineError::receive(e));
+                    break;
+                }
+                Err(_would_block) => continue,
+            };
+            let messages = match decoder.as_mut() {
+                Some(decoder) => match decoder.push(&buffer[..size]) {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        receive_failed(SocketEngineError::Frame {
+                            peer: peer.clone(),
+                            error: e,
+                        });
+                        let _ = readiness.get_ref().shutdown(std::net::Shutdown::Both);
+                        break;
+                    }
+                },
+                None => {
+                    let mut data = &buffer[..size];
+                    if let Some(rest) = data.strip_prefix(HEARTBEAT_PONG) {
+                        state.pongs.notify_one();
+                        data = rest;
+                    }
+                    if data.is_empty() {
+                        continue;
+                    }
+                    vec![data.to_vec()]
+                }
+            };
+            let overhead = if decoder.is_some() {
+                FRAME_HEADER_LEN
+            } else {
+                0
+            };
+            for data in messages {
+                if data == HEARTBEAT_PONG {
+                    state.pongs.notify_one();
+                    continue;
+                }
+                notify_received(
+                    &observers,
+                    &SocketEngineEvent::Data(DataEvent::Received {
+                        wire_bytes: data.len() + overhead,
+                        data,
+                        from: peer.clone(),
+                        listener: local.clone(),
+                        connection: None,
+                        local: false,
+                    }),
+                    &runtime,
+                );
+            }
+        }
+        report_closed(&observers, &state.closed, &peer);
+    });
+}
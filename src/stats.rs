@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    endpoint::Endpoint,
+    event::{DataEvent, EngineObserver, ErrorEvent, SocketEngineEvent},
+};
+
+/// Traffic counters, in payload bytes, and in bytes on the wire as reported by
+/// the `wire_bytes` of events (frame headers included, UDP/IP and lower headers
+/// not). Local deliveries are included.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EndpointStats {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub wire_bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    pub wire_bytes_received: u64,
+    /// `SendFailed` and `ConnectionFailed` events.
+    pub send_failures: u64,
+    pub receive_failures: u64,
+    /// Bytes of echo probes sent back by echo responders (`Echoed` events),
+    /// which the other counters leave out.
+    pub echo_bytes: u64,
+    /// Misuses tolerated in lenient mode (see `Engine::with_strict`), only
+    /// counted in the engine total.
+    pub misuse_warnings: u64,
+    /// Sockets open when the snapshot was taken (`Engine::socket_count`), only
+    /// in the engine total.
+    pub socket_count: u64,
+}
+
+impl EndpointStats {
+    /// Bytes the engine added to the payloads sent, in percent of them.
+    pub fn send_overhead(&self) -> f64 {
+        overhead(self.bytes_sent, self.wire_bytes_sent)
+    }
+
+    /// Bytes the engine stripped from the data received, in percent of the
+    /// payloads delivered.
+    pub fn receive_overhead(&self) -> f64 {
+        overhead(self.bytes_received, self.wire_bytes_received)
+    }
+}
+
+fn overhead(payload: u64, wire: u64) -> f64 {
+    if payload == 0 {
+        return 0.0;
+    }
+    wire.saturating_sub(payload) as f64 * 100.0 / payload as f64
+}
+
+#[derive(Default)]
+struct Counters {
+    messages_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    wire_bytes_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_received: AtomicU64,
+    wire_bytes_received: AtomicU64,
+    send_failures: AtomicU64,
+    receive_failures: AtomicU64,
+    echo_bytes: AtomicU64,
+}
+
+#[derive(Copy, Clone)]
+enum Traffic {
+    Sent,
+    Received,
+    SendFailed,
+    ReceiveFailed,
+    Echoed,
+}
+
+impl Counters {
+    // `wire` only applies to sent and received messages
+    fn add(&self, kind: Traffic, bytes: u64, wire: u64) {
+        match kind {
+            Traffic::Sent => {
+                self.messages_sent.fetch_add(1, Ordering::Relaxed);
+                self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+                self.wire_bytes_sent.fetch_add(wire, Ordering::Relaxed);
+            }
+            Traffic::Received => {
+                self.messages_received.fetch_add(1, Ordering::Relaxed);
+                self.bytes_received.fetch_add(bytes, Ordering::Relaxed);
+                self.wire_bytes_received.fetch_add(wire, Ordering::Relaxed);
+            }
+            Traffic::SendFailed => {
+                self.send_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            Traffic::ReceiveFailed => {
+                self.receive_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            Traffic::Echoed => {
+                self.echo_bytes.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn snapshot(&self) -> EndpointStats {
+        EndpointStats {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            wire_bytes_sent: self.wire_bytes_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            wire_bytes_received: self.wire_bytes_received.load(Ordering::Relaxed),
+            send_failures: self.send_failures.load(Ordering::Relaxed),
+            receive_failures: self.receive_failures.load(Ordering::Relaxed),
+            echo_bytes: self.echo_bytes.load(Ordering::Relaxed),
+            misuse_warnings: 0,
+            socket_count: 0,
+        }
+    }
+}
+
+/// Remote endpoints whose traffic is counted apart, see `Engine::stats`. Past
+/// it, the endpoint with the oldest traffic is forgotten to make room, so that
+/// peers coming and going (e.g. ephemeral source ports) do not grow the map for
+/// the lifetime of the engine. The engine total is not affected.
+pub const MAX_TRACKED_ENDPOINTS: usize = 1024;
+
+#[derive(Default)]
+struct Tracked {
+    counters: Arc<Counters>,
+    // Value of `Endpoints::clock` at the endpoint's last traffic
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Endpoints {
+    tracked: HashMap<Endpoint, Tracked>,
+    clock: u64,
+}
+
+/// Counters per endpoint and for the whole engine. The map is only locked to
+/// find an endpoint's counters, which are then updated and read atomically.
+#[derive(Default)]
+pub(crate) struct TrafficStats {
+    endpoints: Mutex<Endpoints>,
+    total: Counters,
+    misuse_warnings: AtomicU64,
+}
+
+impl TrafficStats {
+    fn counters(&self, endpoint: &Endpoint) -> Arc<Counters> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        endpoints.clock += 1;
+        let clock = endpoints.clock;
+        let tracked = &mut endpoints.tracked;
+        if tracked.len() >= MAX_TRACKED_ENDPOINTS && !tracked.contains_key(endpoint) {
+            // Linear, but only when a new endpoint shows up once the map is full
+            let oldest = tracked
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(endpoint, _)| endpoint.clone());
+            if let Some(oldest) = oldest {
+                tracked.remove(&oldest);
+            }
+        }
+        let entry = tracked.entry(endpoint.clone()).or_default();
+        entry.last_used = clock;
+        entry.counters.clone()
+    }
+
+    fn record(&self, event: &SocketEngineEvent) {
+        let (endpoint, kind, bytes, wire) = match event {
+            SocketEngineEvent::Data(DataEvent::Sent {
+                to,
+                bytes_sent,
+                wire_bytes,
+                ..
+            }) => (to, Traffic::Sent, *bytes_sent, *wire_bytes),
+            SocketEngineEvent::Data(DataEvent::Received {
+                from,
+                data,
+                wire_bytes,
+                ..
+            }) => (from, Traffic::Received, data.len(), *wire_bytes),
+            SocketEngineEvent::Data(DataEvent::Echoed { from, bytes, .. }) => {
+                (from, Traffic::Echoed, *bytes, 0)
+            }
+            SocketEngineEvent::Error(
+                ErrorEvent::SendFailed { endpoint, .. }
+                | ErrorEvent::ConnectionFailed { endpoint, .. },
+            ) => (endpoint, Traffic::SendFailed, 0, 0),
+            SocketEngineEvent::Error(ErrorEvent::ReceiveFailed { endpoint, .. }) => {
+                (endpoint, Traffic::ReceiveFailed, 0, 0)
+            }
+            _ => return,
+        };
+        self.total.add(kind, bytes as u64, wire as u64);
+        self.counters(endpoint).add(kind, bytes as u64, wire as u64);
+    }
+
+    pub(crate) fn get(&self, endpoint: &Endpoint) -> EndpointStats {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .tracked
+            .get(endpoint)
+            .map_or_else(EndpointStats::default, |entry| entry.counters.snapshot())
+    }
+
+    pub(crate) fn total(&self) -> EndpointStats {
+        EndpointStats {
+            misuse_warnings: self.misuse_warnings.load(Ordering::Relaxed),
+            ..self.total.snapshot()
+        }
+    }
+
+    pub(crate) fn record_misuse(&self) {
+        self.misuse_warnings.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub(crate) struct StatsObserver(pub(crate) Arc<TrafficStats>);
+
+impl EngineObserver for StatsObserver {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        self.0.record(&event);
+    }
+}
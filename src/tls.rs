@@ -0,0 +1,283 @@
+//! TLS over the TCP transport, behind the `tls` feature. See `EngineConfig::tls`.
+
+use std::{
+    io,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    runtime::Handle,
+};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        crypto::{ring, CryptoProvider},
+        pki_types::{CertificateDer, ServerName},
+        ClientConfig, RootCertStore, ServerConfig,
+    },
+    TlsAcceptor, TlsConnector,
+};
+
+use crate::{
+    endpoint::{Endpoint, EndpointProto},
+    error::SocketEngineError,
+    event::{
+        notify_all_observers, notify_received, ConnectionEvent, DataEvent, ErrorEvent, Observers,
+        SocketEngineEvent,
+    },
+    framing::{FrameDecoder, FRAME_HEADER_LEN},
+};
+
+/// Certificates the TCP listeners and sends of an engine use for TLS, see
+/// `EngineConfig::tls`. Listeners accept TLS sessions once the config has an
+/// identity, sends perform a client handshake once it has roots; without them
+/// the TCP transport stays plaintext.
+///
+/// ```no_run
+/// use socket_engine::prelude::*;
+///
+/// let tls = TlsConfig::default()
+///     .identity_files("server.pem", "server.key")?
+///     .roots_file("ca.pem")?;
+/// let engine = Engine::with_config(EngineConfig::default().tls(tls));
+/// # drop(engine);
+/// # Ok::<(), SocketEngineError>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    server: Option<Arc<ServerConfig>>,
+    client: Option<Arc<ClientConfig>>,
+    server_name: Option<ServerName<'static>>,
+}
+
+impl TlsConfig {
+    /// Sets the certificate chain and private key listeners present, both PEM
+    /// encoded, the chain starting with the certificate of the listener.
+    pub fn identity(mut self, cert_chain: &[u8], key: &[u8]) -> Result<Self, SocketEngineError> {
+        let certs = parse_certs(cert_chain)?;
+        let key = rustls_pemfile::private_key(&mut &key[..])
+            .map_err(|e| invalid(format!("Unreadable TLS private key: {e}")))?
+            .ok_or_else(|| invalid("No private key in the TLS key PEM".to_string()))?;
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| invalid(e.to_string()))?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid(format!("Invalid TLS identity: {e}")))?;
+        self.server = Some(Arc::new(config));
+        Ok(self)
+    }
+
+    /// Reads the identity from PEM files, see `identity`.
+    pub fn identity_files(
+        self,
+        cert_chain: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> Result<Self, SocketEngineError> {
+        let cert_chain = read(cert_chain.as_ref())?;
+        let key = read(key.as_ref())?;
+        self.identity(&cert_chain, &key)
+    }
+
+    /// Sets the PEM encoded certificates sends trust the certificate of the
+    /// peer listener to be issued by.
+    pub fn roots(mut self, certs: &[u8]) -> Result<Self, SocketEngineError> {
+        let mut roots = RootCertStore::empty();
+        for cert in parse_certs(certs)? {
+            roots
+                .add(cert)
+                .map_err(|e| invalid(format!("Invalid TLS root certificate: {e}")))?;
+        }
+        let config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| invalid(e.to_string()))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        self.client = Some(Arc::new(config));
+        Ok(self)
+    }
+
+    /// Reads the roots from a PEM file, see `roots`.
+    pub fn roots_file(self, certs: impl AsRef<Path>) -> Result<Self, SocketEngineError> {
+        let certs = read(certs.as_ref())?;
+        self.roots(&certs)
+    }
+
+    /// Sets the name sends expect the certificate of every peer to carry, and
+    /// send as SNI. By default it is the host of the target endpoint.
+    pub fn server_name(mut self, name: &str) -> Result<Self, SocketEngineError> {
+        let name = ServerName::try_from(name.to_string())
+            .map_err(|e| invalid(format!("Invalid TLS server name {name}: {e}")))?;
+        self.server_name = Some(name);
+        Ok(self)
+    }
+}
+
+/// The TLS config of an engine, replaced by `Engine::reload_tls`.
+pub(crate) type SharedTls = Arc<RwLock<TlsConfig>>;
+
+/// What accepted connections are wrapped in, `None` for plaintext.
+pub(crate) fn acceptor(tls: &SharedTls) -> Option<TlsAcceptor> {
+    tls.read().unwrap().server.clone().map(TlsAcceptor::from)
+}
+
+/// What outgoing connections are wrapped in, `None` for plaintext.
+pub(crate) fn client(tls: &SharedTls) -> Option<TlsClient> {
+    let tls = tls.read().unwrap();
+    tls.client.clone().map(|config| TlsClient {
+        connector: TlsConnector::from(config),
+        server_name: tls.server_name.clone(),
+    })
+}
+
+/// The client half of the TLS config, as a send found it.
+#[derive(Clone)]
+pub(crate) struct TlsClient {
+    connector: TlsConnector,
+    server_name: Option<ServerName<'static>>,
+}
+
+impl TlsClient {
+    /// Performs the client handshake with `target` over `stream`.
+    pub(crate) async fn connect(
+        &self,
+        target: &Endpoint,
+        stream: TcpStream,
+    ) -> io::Result<TlsStream<TcpStream>> {
+        let name = match &self.server_name {
+            Some(name) => name.clone(),
+            None => host_name(target)?,
+        };
+        self.connector.connect(name, stream).await
+    }
+}
+
+// The host part of a TCP endpoint, a DNS name or an IP address
+fn host_name(target: &Endpoint) -> io::Result<ServerName<'static>> {
+    let host = target
+        .endpoint
+        .rsplit_once(':')
+        .map_or(target.endpoint.as_str(), |(host, _port)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Delivers what the peer of an outgoing TLS connection sends back as
+/// `Received` events, like `socket::spawn_reply_reader` for plaintext ones.
+/// Closes the connection, emitting `Closed`, once the peer closed it or sent
+/// nothing for `idle_timeout`.
+pub(crate) fn spawn_reply_reader(
+    mut stream: TlsStream<TcpStream>,
+    peer: Endpoint,
+    observers: Observers,
+    buffer_size: usize,
+    max_frame_size: Option<usize>,
+    idle_timeout: Duration,
+) {
+    let local = match stream.get_ref().0.local_addr() {
+        Ok(addr) => Endpoint {
+            proto: EndpointProto::Tcp,
+            endpoint: addr.to_string(),
+        },
+        Err(_) => peer.clone(),
+    };
+    // Called by the send that set the connection up, on the engine's runtime
+    let runtime = Handle::current();
+    tokio::spawn(async move {
+        let mut buffer = vec![0; buffer_size];
+        let mut decoder = max_frame_size.map(FrameDecoder::new);
+        let receive_failed = |error| {
+            notify_all_observers(
+                &observers,
+                &SocketEngineEvent::Error(ErrorEvent::ReceiveFailed {
+                    endpoint: local.clone(),
+                    error,
+                }),
+            );
+        };
+        loop {
+            let size = match tokio::time::timeout(idle_timeout, stream.read(&mut buffer)).await {
+                Ok(Ok(0)) => {
+                    if let Some(Err(e)) = decoder.as_ref().map(FrameDecoder::finish) {
+                        receive_failed(SocketEngineError::Frame {
+                            peer: peer.clone(),
+                            error: e,
+                        });
+                    }
+                    break;
+                }
+                Ok(Ok(size)) => size,
+                Ok(Err(e)) => {
+                    receive_failed(SocketEngineError::receive(e));
+                    break;
+                }
+                Err(_idle) => break,
+            };
+            let messages = match decoder.as_mut() {
+                Some(decoder) => match decoder.push(&buffer[..size]) {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        receive_failed(SocketEngineError::Frame {
+                            peer: peer.clone(),
+                            error: e,
+                        });
+                        break;
+                    }
+                },
+                None => vec![buffer[..size].to_vec()],
+            };
+            let overhead = if decoder.is_some() {
+                FRAME_HEADER_LEN
+            } else {
+                0
+            };
+            for data in messages {
+                notify_received(
+                    &observers,
+                    &SocketEngineEvent::Data(DataEvent::Received {
+                        wire_bytes: data.len() + overhead,
+                        data,
+                        from: peer.clone(),
+                        listener: local.clone(),
+                        connection: None,
+                        local: false,
+                    }),
+                    &runtime,
+                );
+            }
+        }
+        let _ = stream.shutdown().await;
+        notify_all_observers(
+            &observers,
+            &SocketEngineEvent::Connection(ConnectionEvent::Closed { remote: Some(peer) }),
+        );
+    });
+}
+
+// Only ring is built in, the process-wide default provider is left alone
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn parse_certs(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, SocketEngineError> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(format!("Unreadable TLS certificate: {e}")))?;
+    if certs.is_empty() {
+        return Err(invalid("No certificate in the TLS PEM".to_string()));
+    }
+    Ok(certs)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, SocketEngineError> {
+    std::fs::read(path).map_err(|e| invalid(format!("{}: {e}", path.display())))
+}
+
+fn invalid(reason: String) -> SocketEngineError {
+    SocketEngineError::InvalidConfig(reason)
+}
@@ -0,0 +1,103 @@
+//! `tracing` output of the `tracing` feature.
+//!
+//! Every event handed to the observers is also recorded, at `debug` for data and
+//! connection events, `warn` for failures and `error` for socket errors. Events
+//! emitted by a send or an accepted TCP connection belong to its span.
+
+use tracing::{debug, error, warn, Span};
+
+use crate::{
+    endpoint::Endpoint,
+    event::{ConnectionEvent, DataEvent, ErrorEvent, MessageId, SocketEngineEvent},
+};
+
+pub(crate) fn connection_span(peer: &str, listener: &Endpoint) -> Span {
+    tracing::debug_span!("connection", peer = %peer, listener = %listener)
+}
+
+pub(crate) fn send_span(token: &MessageId, to: &Endpoint) -> Span {
+    tracing::debug_span!("send", token = %token, to = %to)
+}
+
+pub(crate) fn trace_event(event: &SocketEngineEvent) {
+    match event {
+        SocketEngineEvent::Data(event) => match event {
+            DataEvent::Received {
+                from,
+                listener,
+                wire_bytes,
+                local,
+                data,
+                ..
+            } => debug!(%from, %listener, bytes = data.len(), wire_bytes, local, "received"),
+            DataEvent::Sending {
+                token,
+                to,
+                bytes,
+                local,
+            } => debug!(%token, %to, bytes, local, "sending"),
+            DataEvent::Sent {
+                token,
+                to,
+                bytes_sent,
+                wire_bytes,
+                local,
+                ..
+            } => debug!(%token, %to, bytes_sent, wire_bytes, local, "sent"),
+            DataEvent::Cancelled { token, to } => debug!(%token, %to, "cancelled"),
+            DataEvent::Retrying {
+                token,
+                to,
+                attempt,
+                next_in,
+            } => warn!(%token, %to, attempt, ?next_in, "retrying"),
+            DataEvent::EchoReply { to, seq, rtt } => debug!(%to, seq, ?rtt, "echo reply"),
+            DataEvent::Echoed {
+                from,
+                listener,
+                bytes,
+            } => debug!(%from, %listener, bytes, "echoed"),
+        },
+        SocketEngineEvent::Connection(event) => match event {
+            ConnectionEvent::ListenerStarted { endpoint } => {
+                debug!(%endpoint, "listener started")
+            }
+            ConnectionEvent::ListenerStopped {
+                endpoint,
+                reason,
+                messages,
+            } => debug!(%endpoint, ?reason, messages, "listener stopped"),
+            ConnectionEvent::Established { remote } => debug!(%remote, "established"),
+            ConnectionEvent::Accepted { remote, local } => debug!(%remote, %local, "accepted"),
+            ConnectionEvent::Closed { remote } => debug!(?remote, "closed"),
+            ConnectionEvent::PeerDiscovered { endpoint } => debug!(%endpoint, "peer discovered"),
+            ConnectionEvent::Paired { alias, endpoint } => debug!(%alias, %endpoint, "paired"),
+            ConnectionEvent::PeerStateChanged {
+                endpoint,
+                old,
+                new,
+                cause,
+            } => debug!(%endpoint, ?old, ?new, ?cause, "peer state changed"),
+        },
+        SocketEngineEvent::Error(event) => match event {
+            ErrorEvent::ConnectionFailed {
+                endpoint,
+                reason,
+                token,
+            } => warn!(%endpoint, ?reason, %token, "connection failed"),
+            ErrorEvent::SendFailed {
+                endpoint,
+                token,
+                error,
+            } => warn!(%endpoint, %token, %error, "send failed"),
+            ErrorEvent::ReceiveFailed { endpoint, error } => {
+                warn!(%endpoint, %error, "receive failed")
+            }
+            ErrorEvent::SocketError { endpoint, error } => {
+                error!(%endpoint, %error, "socket error")
+            }
+            ErrorEvent::Misuse { kind, detail } => warn!(?kind, %detail, "misuse"),
+            ErrorEvent::MisuseWarning { kind, detail } => warn!(?kind, %detail, "misuse warning"),
+        },
+    }
+}
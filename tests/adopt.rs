@@ -0,0 +1,80 @@
+mod common;
+
+use std::net::{SocketAddr, UdpSocket};
+
+use common::*;
+use socket2::{Domain, Protocol, Socket, Type};
+use socket_engine::prelude::*;
+
+// A UDP socket bound outside the engine, on a free port
+fn bound_udp() -> (Socket, Endpoint, SocketAddr) {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    socket
+        .bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into())
+        .unwrap();
+    let address = socket.local_addr().unwrap().as_socket().unwrap();
+    let endpoint = format!("udp {}", address).parse().unwrap();
+    (socket, endpoint, address)
+}
+
+#[test]
+fn adopted_listener_receives_and_closes_on_stop() {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let (socket, endpoint, address) = bound_udp();
+    let socket = AdoptedSocket::new(socket, endpoint.clone()).unwrap();
+    let handle = engine.adopt_listener(socket);
+    block_on(handle.wait_ready()).unwrap();
+
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .send_to(b"hello", address)
+        .unwrap();
+    assert!(events.wait_for(1, is_received));
+    assert_eq!(events.received(), [b"hello"]);
+
+    engine.stop_listener(endpoint).unwrap();
+    wait_until(|| handle.status() == ListenerStatus::Stopped);
+    // Bound without `SO_REUSEADDR`, the port is only free once the socket is closed
+    wait_until(|| UdpSocket::bind(address).is_ok());
+}
+
+#[test]
+fn adopted_send_socket_is_the_source() {
+    let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let target: Endpoint = format!("udp {}", peer.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let engine = Engine::new();
+    let (socket, endpoint, address) = bound_udp();
+    engine
+        .adopt_send_socket(AdoptedSocket::new(socket, endpoint.clone()).unwrap())
+        .unwrap();
+
+    engine
+        .send(
+            target,
+            b"hello".to_vec(),
+            SendOptions::default().source(endpoint).token("1"),
+        )
+        .unwrap();
+    let mut buffer = [0; 16];
+    let (size, from) = peer.recv_from(&mut buffer).unwrap();
+    assert_eq!((&buffer[..size], from), (&b"hello"[..], address));
+}
+
+#[test]
+fn mismatched_sockets_are_refused() {
+    let (_, endpoint, _) = bound_udp();
+    let tcp = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).unwrap();
+    let ipv6 = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    let unbound = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    for socket in [tcp, ipv6, unbound] {
+        let result = AdoptedSocket::new(socket, endpoint.clone());
+        assert!(
+            matches!(result, Err(SocketEngineError::SocketMismatch(_))),
+            "{:?}",
+            result.err()
+        );
+    }
+}
@@ -0,0 +1,143 @@
+#![cfg(feature = "bp")]
+
+use socket2::SockAddr;
+use socket_engine::{
+    bp::{bp_sockaddr_to_endpoint, create_bp_sockaddr_with_string},
+    prelude::*,
+};
+
+// Family and scheme, then node and service, then the allocator
+const IPN_LEGACY_LEN: u32 = 16;
+const IPN_LEN: u32 = 20;
+
+fn round_trip(eid: &str) -> String {
+    let addr = create_bp_sockaddr_with_string(eid, &BpConfig::default()).unwrap();
+    let endpoint = bp_sockaddr_to_endpoint(&addr, &BpConfig::default()).unwrap();
+    assert_eq!(endpoint.proto, EndpointProto::Bp);
+    endpoint.endpoint
+}
+
+fn is_addr_parse_error(eid: &str) -> bool {
+    matches!(
+        create_bp_sockaddr_with_string(eid, &BpConfig::default()),
+        Err(SocketEngineError::AddrParse(_))
+    )
+}
+
+// `addr` with its length changed, the bytes past it left as they are
+fn with_len(addr: &SockAddr, len: u32) -> SockAddr {
+    unsafe { SockAddr::new(addr.clone().as_storage(), len) }
+}
+
+#[test]
+fn ipn_addresses_round_trip() {
+    assert_eq!(round_trip("ipn:1.2"), "ipn:1.2");
+    assert_eq!(round_trip("ipn:3.1.2"), "ipn:3.1.2");
+    assert_eq!(round_trip("ipn:4294967295.7"), "ipn:4294967295.7");
+}
+
+#[test]
+fn dtn_addresses_round_trip() {
+    assert_eq!(round_trip("dtn://nodeA/chat"), "dtn://nodeA/chat");
+    assert_eq!(round_trip("dtn://node/~service"), "dtn://node/~service");
+    // As long as it may be, the length includes the leading `//`
+    let longest = format!("dtn://{}", "n".repeat(110));
+    assert_eq!(round_trip(&longest), longest);
+}
+
+#[test]
+fn node_zero_and_overlong_eids_are_rejected() {
+    for eid in [
+        "ipn:0.1",
+        "ipn:7.0.1",
+        &format!("dtn://{}", "n".repeat(111)),
+    ] {
+        assert!(is_addr_parse_error(eid), "{}", eid);
+    }
+}
+
+#[test]
+fn ipn_lengths_tell_the_layout() {
+    let legacy = create_bp_sockaddr_with_string("ipn:5.6", &BpConfig::default()).unwrap();
+    assert_eq!(legacy.len(), IPN_LEGACY_LEN);
+    let allocated = create_bp_sockaddr_with_string("ipn:3.5.6", &BpConfig::default()).unwrap();
+    assert_eq!(allocated.len(), IPN_LEN);
+
+    // A legacy address says nothing of the allocator, whatever follows it
+    let truncated = with_len(&allocated, IPN_LEGACY_LEN);
+    assert_eq!(
+        bp_sockaddr_to_endpoint(&truncated, &BpConfig::default())
+            .unwrap()
+            .endpoint,
+        "ipn:5.6"
+    );
+
+    for len in [4, IPN_LEGACY_LEN + 1, IPN_LEN + 1] {
+        let error =
+            bp_sockaddr_to_endpoint(&with_len(&allocated, len), &BpConfig::default()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", len);
+    }
+}
+
+#[test]
+fn foreign_addresses_are_rejected() {
+    let udp = SockAddr::from("127.0.0.1:9".parse::<std::net::SocketAddr>().unwrap());
+    assert!(bp_sockaddr_to_endpoint(&udp, &BpConfig::default()).is_err());
+
+    let mut storage = create_bp_sockaddr_with_string("ipn:1.2", &BpConfig::default())
+        .unwrap()
+        .as_storage();
+    // The scheme follows the family, padded to 4 bytes
+    let bytes = &mut storage as *mut _ as *mut u8;
+    unsafe { bytes.add(4).cast::<u32>().write_unaligned(9) };
+    let unknown = unsafe { SockAddr::new(storage, IPN_LEGACY_LEN) };
+    assert!(bp_sockaddr_to_endpoint(&unknown, &BpConfig::default()).is_err());
+}
+
+#[test]
+fn malformed_dtn_eids_are_rejected() {
+    for eid in ["dtn:nodeA/chat", "dtn:/nodeA/chat", "dtn://", "dtn:///chat"] {
+        assert!(is_addr_parse_error(eid), "{}", eid);
+    }
+    let endpoint: Endpoint = "bp dtn://nodeA/chat".parse().unwrap();
+    assert!(endpoint.validate().is_ok());
+    let endpoint: Endpoint = "bp dtn:nodeA/chat".parse().unwrap();
+    assert!(endpoint.validate().is_err());
+}
+
+#[test]
+fn ipn_eids_take_two_or_three_components() {
+    // Allocator 0 is the default one, left out of the legacy form
+    assert_eq!(round_trip("ipn:0.1.2"), "ipn:1.2");
+    assert_eq!(
+        create_bp_sockaddr_with_string("ipn:0.1.2", &BpConfig::default())
+            .unwrap()
+            .len(),
+        IPN_LEGACY_LEN
+    );
+    for eid in [
+        "ipn:1",
+        "ipn:1.2.3.4",
+        "ipn:1..2",
+        "ipn:a.2",
+        "ipn:1.4294967296",
+        "ipn:-1.2",
+    ] {
+        assert!(is_addr_parse_error(eid), "{}", eid);
+    }
+}
+
+#[test]
+fn addresses_take_the_family_of_their_config() {
+    let custom = BpConfig {
+        family: 40,
+        protocol: 0,
+    };
+    let addr = create_bp_sockaddr_with_string("ipn:1.2", &custom).unwrap();
+    assert_eq!(addr.family(), 40);
+    assert_eq!(
+        bp_sockaddr_to_endpoint(&addr, &custom).unwrap().endpoint,
+        "ipn:1.2"
+    );
+    assert!(bp_sockaddr_to_endpoint(&addr, &BpConfig::default()).is_err());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use std::{
+    net::TcpListener,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+fn is_cancelled(e: &SocketEngineEvent) -> bool {
+    matches!(e, SocketEngineEvent::Data(DataEvent::Cancelled { .. }))
+}
+
+// Cancels every send as soon as it starts, from inside the notification
+struct Canceller(Arc<OnceLock<Arc<Engine>>>);
+
+impl EngineObserver for Canceller {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if let SocketEngineEvent::Data(DataEvent::Sending { token, .. }) = event {
+            self.0.get().unwrap().cancel_send(&token);
+        }
+    }
+}
+
+#[test]
+fn observer_can_cancel_while_notified() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let engine_cell = Arc::new(OnceLock::new());
+    let engine = Arc::new(Engine::new());
+    let _ = engine_cell.set(engine.clone());
+    engine.add_observer(Arc::new(Mutex::new(Canceller(engine_cell))));
+    let events = Events::attach(&engine);
+
+    let handle = engine
+        .send(
+            tcp_target(&listener),
+            b"never".to_vec(),
+            SendOptions::default().token("cancelled"),
+        )
+        .unwrap();
+    assert!(events.wait_for(1, is_cancelled), "observer deadlocked");
+    let outcome = block_on(handle.outcome());
+    assert!(matches!(
+        outcome,
+        SendOutcome::Failed {
+            error: SocketEngineError::Cancelled
+        }
+    ));
+}
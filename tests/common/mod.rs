@@ -0,0 +1,177 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::{
+    net::{TcpListener, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use socket_engine::prelude::*;
+
+/// Records every event an engine emits.
+#[derive(Clone, Default)]
+pub struct Events(Arc<Mutex<Vec<SocketEngineEvent>>>);
+
+struct Recorder(Events);
+
+impl EngineObserver for Recorder {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        self.0 .0.lock().unwrap().push(event);
+    }
+}
+
+impl Events {
+    pub fn attach(engine: &Engine) -> Self {
+        let events = Events::default();
+        engine.add_observer(Arc::new(Mutex::new(Recorder(events.clone()))));
+        events
+    }
+
+    pub fn all(&self) -> Vec<SocketEngineEvent> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn count(&self, pred: impl Fn(&SocketEngineEvent) -> bool) -> usize {
+        self.0.lock().unwrap().iter().filter(|e| pred(e)).count()
+    }
+
+    /// Waits until `pred` holds for `n` events, false on timeout.
+    pub fn wait_for(&self, n: usize, pred: impl Fn(&SocketEngineEvent) -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if self.count(&pred) >= n {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    /// Payloads of the `Received` events, in order.
+    pub fn received(&self) -> Vec<Vec<u8>> {
+        self.all()
+            .into_iter()
+            .filter_map(|e| match e {
+                SocketEngineEvent::Data(DataEvent::Received { data, .. }) => Some(data),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub fn is_received(e: &SocketEngineEvent) -> bool {
+    matches!(e, SocketEngineEvent::Data(DataEvent::Received { .. }))
+}
+
+pub fn is_sent(e: &SocketEngineEvent) -> bool {
+    matches!(e, SocketEngineEvent::Data(DataEvent::Sent { .. }))
+}
+
+pub fn is_closed(e: &SocketEngineEvent) -> bool {
+    matches!(
+        e,
+        SocketEngineEvent::Connection(ConnectionEvent::Closed { .. })
+    )
+}
+
+pub fn is_established(e: &SocketEngineEvent) -> bool {
+    matches!(
+        e,
+        SocketEngineEvent::Connection(ConnectionEvent::Established { .. })
+    )
+}
+
+pub fn is_error(e: &SocketEngineEvent) -> bool {
+    matches!(e, SocketEngineEvent::Error(_))
+}
+
+/// A loopback endpoint on a port free at the time of the call.
+pub fn free_endpoint(proto: &str) -> Endpoint {
+    let port = match proto {
+        "udp" => UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port(),
+        _ => TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port(),
+    };
+    format!("{} 127.0.0.1:{}", proto, port).parse().unwrap()
+}
+
+/// Polls `condition` for up to 5 seconds, panicking if it never holds.
+pub fn wait_until(condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "condition not met in time");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Endpoint of a plain TCP listener standing for a peer.
+pub fn tcp_target(listener: &TcpListener) -> Endpoint {
+    format!("tcp {}", listener.local_addr().unwrap())
+        .parse()
+        .unwrap()
+}
+
+/// Starts a listener and waits until it is bound.
+pub fn listen(engine: &Engine, endpoint: &Endpoint) -> ListenerHandle {
+    let handle = engine.start_listener_async(endpoint.clone());
+    wait_running(&handle);
+    handle
+}
+
+/// Waits until the listener behind `handle` runs, panicking if it failed.
+pub fn wait_running(handle: &ListenerHandle) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !handle.is_running() {
+        assert!(Instant::now() < deadline, "listener did not start");
+        assert!(
+            !matches!(handle.status(), ListenerStatus::Failed(_)),
+            "{:?}",
+            handle.status()
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// A runtime for `Engine::with_runtime` with `workers` worker threads and a
+/// single blocking thread.
+pub fn small_runtime(workers: usize) -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(workers)
+        .max_blocking_threads(1)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Runs `future` to completion on a fresh current-thread runtime.
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// Names of the threads of the test process.
+#[cfg(target_os = "linux")]
+pub fn threads() -> Vec<String> {
+    std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .filter_map(|task| std::fs::read_to_string(task.ok()?.path().join("comm")).ok())
+        .map(|name| name.trim_end().to_string())
+        .collect()
+}
+
+/// Open file descriptors of the test process.
+#[cfg(target_os = "linux")]
+pub fn open_fds() -> usize {
+    std::fs::read_dir("/proc/self/fd").unwrap().count()
+}
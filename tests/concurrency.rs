@@ -0,0 +1,64 @@
+mod common;
+
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn engine_is_shared_across_threads() {
+    assert_send_sync::<Engine>();
+}
+
+#[test]
+fn concurrent_start_send_stop_on_shared_endpoints() {
+    let engine = Arc::new(Engine::new());
+    let events = Events::attach(&engine);
+    let endpoints: Arc<Vec<Endpoint>> = Arc::new(
+        ["udp", "udp", "tcp", "tcp"]
+            .iter()
+            .map(|proto| free_endpoint(proto))
+            .collect(),
+    );
+
+    let (done, finished) = mpsc::channel();
+    for worker in 0..8 {
+        let (engine, endpoints, done) = (engine.clone(), endpoints.clone(), done.clone());
+        thread::spawn(move || {
+            for round in 0..25 {
+                let endpoint = endpoints[(worker + round) % endpoints.len()].clone();
+                // Any of these may lose the race for the endpoint, none may hang
+                engine.start_listener_async(endpoint.clone());
+                let _ = engine.send(endpoint.clone(), vec![worker as u8], SendOptions::default());
+                let _ = engine.stop_listener(endpoint);
+            }
+            done.send(()).unwrap();
+        });
+    }
+    drop(done);
+    for _ in 0..8 {
+        finished
+            .recv_timeout(Duration::from_secs(20))
+            .expect("a worker is stuck");
+    }
+
+    // No socket is left behind, and the endpoints can be listened on again
+    for endpoint in endpoints.iter() {
+        let _ = engine.stop_listener(endpoint.clone());
+    }
+    wait_until(|| engine.socket_count() == 0);
+
+    let endpoint = endpoints[0].clone();
+    listen(&engine, &endpoint);
+    let before = events.count(is_received);
+    engine
+        .send(endpoint, b"after".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(events.wait_for(before + 1, is_received));
+}
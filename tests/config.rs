@@ -0,0 +1,31 @@
+mod common;
+
+use std::{io::Write, net::TcpStream};
+
+use common::*;
+use socket_engine::prelude::*;
+
+// Sizes of the `Received` events of a 64 KiB message written at once
+fn received_chunks(config: EngineConfig) -> Vec<usize> {
+    let engine = Engine::with_config(config);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("tcp");
+    listen(&engine, &endpoint);
+
+    let address = endpoint.to_string();
+    let mut stream = TcpStream::connect(address.trim_start_matches("tcp ")).unwrap();
+    stream.write_all(&[7; 64 * 1024]).unwrap();
+    drop(stream);
+    wait_until(|| events.received().iter().map(Vec::len).sum::<usize>() == 64 * 1024);
+    events.received().iter().map(Vec::len).collect()
+}
+
+#[test]
+fn tcp_buffer_size_sets_the_read_chunks() {
+    let chunks = received_chunks(EngineConfig::default());
+    assert_eq!(chunks.iter().max(), Some(&4096));
+
+    let chunks = received_chunks(EngineConfig::default().tcp_buffer_size(8 * 1024));
+    assert_eq!(chunks.iter().max(), Some(&(8 * 1024)));
+    assert!(chunks.len() < 64 / 4, "{:?}", chunks);
+}
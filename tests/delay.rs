@@ -0,0 +1,58 @@
+#![cfg(feature = "with_delay")]
+
+mod common;
+
+use std::{
+    net::UdpSocket,
+    sync::{Arc, Mutex},
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+// Names of the threads `Received` events are notified on
+#[derive(Default)]
+struct ReceivedOn(Vec<Option<String>>);
+
+impl EngineObserver for ReceivedOn {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if let SocketEngineEvent::Data(DataEvent::Received { .. }) = event {
+            let name = std::thread::current().name().map(str::to_owned);
+            self.0.push(name);
+        }
+    }
+}
+
+#[test]
+fn delayed_events_use_the_engine_runtime() {
+    std::env::set_var("ENGINE_RECEIVE_DELAY_MS", "10");
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("engine-runtime")
+        .enable_all()
+        .build()
+        .unwrap();
+    let engine = Engine::new().with_runtime(runtime.handle().clone());
+    let received = Arc::new(Mutex::new(ReceivedOn::default()));
+    engine.add_observer(received.clone());
+
+    let endpoint = free_endpoint("udp");
+    let _listener = listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .send_to(b"remote", address.trim_start_matches("udp "))
+        .unwrap();
+    engine
+        .send_blocking(
+            endpoint.clone(),
+            b"local".to_vec(),
+            SendOptions::default().source(endpoint.clone()),
+        )
+        .unwrap();
+
+    wait_until(|| received.lock().unwrap().0.len() == 2);
+    for name in &received.lock().unwrap().0 {
+        assert_eq!(name.as_deref(), Some("engine-runtime"));
+    }
+}
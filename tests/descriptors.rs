@@ -0,0 +1,128 @@
+//! Descriptor leak checks. They count the descriptors of the whole process, so
+//! they run one at a time in their own test binary.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::{
+    net::TcpListener,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+// Runs the tests one at a time, whether or not an earlier one failed
+fn serial() -> MutexGuard<'static, ()> {
+    SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Starts the shared runtime with a UDP send, and waits for its socket to close
+fn start_runtime() {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    engine
+        .send(free_endpoint("udp"), Vec::new(), SendOptions::default())
+        .unwrap();
+    assert!(events.wait_for(1, is_sent));
+    let mut fds = open_fds();
+    loop {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let settled = open_fds();
+        if settled == fds {
+            break;
+        }
+        fds = settled;
+    }
+}
+
+// Waits for descriptors closed by background tasks to go
+fn settle_to(baseline: usize) -> usize {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while open_fds() > baseline && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    open_fds()
+}
+
+#[test]
+fn reused_connection_does_not_leak_descriptors() {
+    let _serial = serial();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&listener);
+    let engine = Engine::new().with_close_after_send(false);
+
+    engine
+        .send_blocking(target.clone(), b"warm up".to_vec(), SendOptions::default())
+        .unwrap();
+    // The outcome is known before the connection, and the duplicate its watcher
+    // waits on, are back in the pool
+    wait_until(|| engine.socket_count() == 2);
+    let baseline = open_fds();
+    for _ in 0..50 {
+        engine
+            .send_blocking(target.clone(), b"again".to_vec(), SendOptions::default())
+            .unwrap();
+    }
+    // Watchers of connections taken by a send are aborted asynchronously
+    let open = settle_to(baseline);
+    assert!(open <= baseline, "{} > {}", open, baseline);
+}
+
+#[test]
+fn stopped_listeners_return_to_baseline() {
+    let _serial = serial();
+    // Built first, the runtime's own descriptors are not the engine's
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let engine = Engine::new().with_runtime(runtime.handle().clone());
+    let baseline = open_fds();
+
+    for _ in 0..10 {
+        let handles: Vec<_> = ["udp", "tcp"]
+            .into_iter()
+            .map(|proto| engine.start_listener_async(free_endpoint(proto)))
+            .collect();
+        for handle in &handles {
+            block_on(handle.wait_ready()).unwrap();
+        }
+        assert_eq!(engine.socket_count(), 2);
+        assert_eq!(engine.total_stats().socket_count, 2);
+        for handle in &handles {
+            engine.stop_listener(handle.endpoint().clone()).unwrap();
+        }
+        wait_until(|| {
+            handles
+                .iter()
+                .all(|handle| handle.status() == ListenerStatus::Stopped)
+        });
+        assert_eq!(engine.socket_count(), 0);
+    }
+    let open = settle_to(baseline);
+    assert!(open <= baseline, "{} > {}", open, baseline);
+}
+
+#[test]
+fn pooled_connection_is_counted() {
+    let _serial = serial();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let engine = Engine::new().with_close_after_send(false);
+    // The runtime's own descriptors are not the engine's
+    start_runtime();
+    let baseline = open_fds();
+
+    engine
+        .send(
+            tcp_target(&listener),
+            b"hello".to_vec(),
+            SendOptions::default().token("hello"),
+        )
+        .unwrap();
+    wait_until(|| engine.socket_count() == 2);
+    assert_eq!(open_fds(), baseline + engine.socket_count());
+    assert_eq!(engine.total_stats().socket_count, 2);
+}
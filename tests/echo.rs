@@ -0,0 +1,140 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+fn rtts(events: &Events) -> Vec<(u32, Option<Duration>)> {
+    events
+        .all()
+        .into_iter()
+        .filter_map(|e| match e {
+            SocketEngineEvent::Data(DataEvent::EchoReply { seq, rtt, .. }) => Some((seq, rtt)),
+            _ => None,
+        })
+        .collect()
+}
+
+// An engine answering probes on a listener of `proto`
+fn responder(proto: &str) -> (Engine, Events, Endpoint) {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint(proto);
+    engine.enable_echo_responder(endpoint.clone());
+    listen(&engine, &endpoint);
+    (engine, events, endpoint)
+}
+
+fn assert_sane(report: &PingReport) {
+    let (min, avg, max, p95) = (
+        report.min.unwrap(),
+        report.avg.unwrap(),
+        report.max.unwrap(),
+        report.p95.unwrap(),
+    );
+    assert!(min <= avg && avg <= max && min <= p95 && p95 <= max);
+    assert!(max < Duration::from_secs(1), "{:?}", report);
+}
+
+#[test]
+fn udp_ping_measures_round_trips() {
+    let (engine, events, endpoint) = responder("udp");
+    let report = block_on(engine.ping(endpoint.clone(), 64, 5, Duration::from_millis(10))).unwrap();
+
+    assert_eq!((report.sent, report.received), (5, 5));
+    assert_eq!(report.loss(), 0.0);
+    assert_sane(&report);
+    assert_eq!(rtts(&events).len(), 5);
+    // Echo traffic is kept out of the user's messages and counters
+    assert_eq!(events.count(is_received), 0);
+    wait_until(|| engine.total_stats().echo_bytes == 5 * 64);
+    assert_eq!(engine.total_stats().messages_received, 0);
+}
+
+#[test]
+fn tcp_ping_with_probes_larger_than_reads() {
+    let (engine, events, endpoint) = responder("tcp");
+    // Raw framing, probes arrive over several reads of `DEFAULT_TCP_BUFFER_SIZE`
+    let report = block_on(engine.ping(endpoint, 10_000, 3, Duration::from_millis(10))).unwrap();
+
+    assert_eq!(report.received, 3);
+    assert_sane(&report);
+    assert_eq!(events.count(is_received), 0);
+    wait_until(|| engine.total_stats().echo_bytes == 3 * 10_000);
+}
+
+#[test]
+fn loss_is_accounted_under_injected_drops() {
+    // Echoes every probe but one in five, a 20% drop rate
+    let lossy = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let target: Endpoint = format!("udp {}", lossy.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    thread::spawn(move || {
+        let mut buffer = [0; 2048];
+        for n in 0.. {
+            let Ok((size, from)) = lossy.recv_from(&mut buffer) else {
+                return;
+            };
+            if n % 5 != 4 {
+                let _ = lossy.send_to(&buffer[..size], from);
+            }
+        }
+    });
+
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let report = block_on(engine.ping(target, 32, 10, Duration::ZERO)).unwrap();
+
+    assert_eq!((report.sent, report.received), (10, 8));
+    assert!((report.loss() - 0.2).abs() < 1e-9);
+    assert_sane(&report);
+    let lost: Vec<_> = rtts(&events)
+        .into_iter()
+        .filter(|(_, rtt)| rtt.is_none())
+        .map(|(seq, _)| seq)
+        .collect();
+    assert_eq!(lost, [4, 9]);
+}
+
+#[test]
+fn late_tcp_echo_does_not_skew_later_probes() {
+    // Answers the first probe after it timed out, the next ones at once
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&server);
+    thread::spawn(move || {
+        for (n, stream) in server.incoming().enumerate() {
+            let Ok(mut stream) = stream else { return };
+            thread::spawn(move || {
+                let mut probe = [0; 32];
+                let mut late = n == 0;
+                while stream.read_exact(&mut probe).is_ok() {
+                    if late {
+                        thread::sleep(Duration::from_millis(1200));
+                        late = false;
+                    }
+                    if stream.write_all(&probe).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let report = block_on(engine.ping(target, 32, 3, Duration::ZERO)).unwrap();
+
+    assert_eq!(report.received, 2);
+    let rtts = rtts(&events);
+    assert_eq!(rtts[0], (0, None));
+    for (_, rtt) in &rtts[1..] {
+        assert!(rtt.unwrap() < Duration::from_millis(100), "{:?}", rtts);
+    }
+}
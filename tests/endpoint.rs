@@ -0,0 +1,50 @@
+use socket_engine::prelude::*;
+
+fn parse(input: &str) -> Result<Endpoint, EndpointParseError> {
+    input.parse()
+}
+
+#[test]
+fn both_syntaxes_give_the_same_endpoint() {
+    let mut pairs = vec![
+        ("udp 127.0.0.1:8888", "udp://127.0.0.1:8888"),
+        ("tcp 127.0.0.1:8888", "tcp://127.0.0.1:8888"),
+        ("tcp localhost:80", "TCP://localhost:80"),
+    ];
+    if cfg!(feature = "bp") {
+        pairs.push(("bp ipn:1.2", "bp://ipn:1.2"));
+        pairs.push(("bp dtn://node/chat", "bp://dtn://node/chat"));
+    }
+    for (spaced, url) in pairs {
+        let endpoint = parse(url).unwrap();
+        assert_eq!(endpoint, parse(spaced).unwrap(), "{}", url);
+        // Displayed in the spaced form, which parses back to the same endpoint
+        assert_eq!(endpoint.to_string().to_lowercase(), spaced.to_lowercase());
+        assert_eq!(parse(&endpoint.to_string()).unwrap(), endpoint);
+    }
+}
+
+#[test]
+fn ambiguous_and_incomplete_inputs_are_rejected() {
+    assert_eq!(
+        parse("udp://udp 1.2.3.4"),
+        Err(EndpointParseError::MalformedAddress("udp 1.2.3.4".into()))
+    );
+    assert_eq!(parse("udp://"), Err(EndpointParseError::MissingAddress));
+    assert_eq!(parse("udp"), Err(EndpointParseError::MissingAddress));
+    assert_eq!(
+        parse("sctp://127.0.0.1:1"),
+        Err(EndpointParseError::UnsupportedScheme("sctp".into()))
+    );
+    for input in [
+        "udp://127.0.0.1",
+        "tcp://127.0.0.1:99999",
+        "udp 300.1.1.1:x",
+    ] {
+        assert!(
+            matches!(parse(input), Err(EndpointParseError::InvalidAddress { .. })),
+            "{}",
+            input
+        );
+    }
+}
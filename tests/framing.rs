@@ -0,0 +1,158 @@
+mod common;
+
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use common::*;
+use socket_engine::{
+    framing::{encode_frame, FrameDecoder, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN},
+    prelude::*,
+};
+
+fn is_receive_failed(e: &SocketEngineEvent) -> bool {
+    matches!(
+        e,
+        SocketEngineEvent::Error(ErrorEvent::ReceiveFailed { .. })
+    )
+}
+
+// Connects to a framed listener of a fresh engine
+fn framed_listener() -> (Engine, Events, TcpStream) {
+    let engine = Engine::new().with_length_prefix_framing(true);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("tcp");
+    listen(&engine, &endpoint);
+    let stream = TcpStream::connect(&endpoint.endpoint).unwrap();
+    (engine, events, stream)
+}
+
+#[test]
+fn frames_split_across_reads_are_reassembled() {
+    let mut stream = encode_frame(b"first", DEFAULT_MAX_FRAME_SIZE).unwrap();
+    stream.extend(encode_frame(b"", DEFAULT_MAX_FRAME_SIZE).unwrap());
+    stream.extend(encode_frame(b"second", DEFAULT_MAX_FRAME_SIZE).unwrap());
+
+    let mut decoder = FrameDecoder::new(DEFAULT_MAX_FRAME_SIZE);
+    let mut frames = Vec::new();
+    for byte in &stream {
+        frames.extend(decoder.push(std::slice::from_ref(byte)).unwrap());
+    }
+    assert_eq!(frames, [&b"first"[..], b"", b"second"]);
+    assert_eq!(decoder.finish(), Ok(()));
+}
+
+#[test]
+fn truncated_stream_is_reported() {
+    let frame = encode_frame(b"hello", DEFAULT_MAX_FRAME_SIZE).unwrap();
+    let mut decoder = FrameDecoder::new(DEFAULT_MAX_FRAME_SIZE);
+    assert_eq!(decoder.push(&frame).unwrap().len(), 1);
+    assert!(decoder
+        .push(&frame[..FRAME_HEADER_LEN + 2])
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        decoder.finish(),
+        Err(FrameError::StreamEndedMidFrame {
+            expected: 5,
+            received: 2,
+            offset: frame.len(),
+        })
+    );
+
+    let mut decoder = FrameDecoder::new(DEFAULT_MAX_FRAME_SIZE);
+    decoder.push(&frame[..2]).unwrap();
+    assert_eq!(
+        decoder.finish(),
+        Err(FrameError::StreamEndedMidFrame {
+            expected: FRAME_HEADER_LEN,
+            received: 2,
+            offset: 0,
+        })
+    );
+}
+
+#[test]
+fn oversized_prefix_is_rejected() {
+    let mut decoder = FrameDecoder::new(16);
+    decoder.push(&encode_frame(b"ok", 16).unwrap()).unwrap();
+    assert_eq!(
+        decoder.push(&17u32.to_be_bytes()),
+        Err(FrameError::PrefixExceedsMax {
+            declared: 17,
+            max: 16,
+            offset: FRAME_HEADER_LEN + 2,
+        })
+    );
+}
+
+#[test]
+fn oversized_payload_is_not_encoded() {
+    assert_eq!(
+        encode_frame(&[0; 17], 16),
+        Err(FrameError::PayloadExceedsMax { len: 17, max: 16 })
+    );
+    assert_eq!(
+        encode_frame(&[0; 16], 16).unwrap().len(),
+        FRAME_HEADER_LEN + 16
+    );
+}
+
+#[test]
+fn oversized_send_fails_before_writing() {
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let engine = Engine::new().with_length_prefix_framing(true);
+    let events = Events::attach(&engine);
+
+    let sent = engine.send(
+        tcp_target(&peer),
+        vec![0; DEFAULT_MAX_FRAME_SIZE + 1],
+        SendOptions::default().token("oversized"),
+    );
+    assert!(matches!(
+        sent,
+        Err(SocketEngineError::Frame {
+            error: FrameError::PayloadExceedsMax { .. },
+            ..
+        })
+    ));
+    assert!(events.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Error(ErrorEvent::SendFailed { .. })
+    )));
+    peer.set_nonblocking(true).unwrap();
+    assert!(peer.accept().is_err(), "a connection was opened");
+}
+
+#[test]
+fn listener_reassembles_split_frame() {
+    let (_engine, events, mut stream) = framed_listener();
+    let frame = encode_frame(b"hello", DEFAULT_MAX_FRAME_SIZE).unwrap();
+    stream.write_all(&frame[..3]).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    stream.write_all(&frame[3..]).unwrap();
+    assert!(events.wait_for(1, is_received));
+    assert_eq!(events.received(), [b"hello"]);
+}
+
+#[test]
+fn listener_reports_truncated_stream() {
+    let (_engine, events, mut stream) = framed_listener();
+    let frame = encode_frame(b"hello", DEFAULT_MAX_FRAME_SIZE).unwrap();
+    stream.write_all(&frame[..FRAME_HEADER_LEN + 1]).unwrap();
+    drop(stream);
+    assert!(events.wait_for(1, is_receive_failed));
+    assert_eq!(events.count(is_received), 0);
+}
+
+#[test]
+fn listener_drops_oversized_prefix() {
+    let (_engine, events, mut stream) = framed_listener();
+    stream
+        .write_all(&((DEFAULT_MAX_FRAME_SIZE + 1) as u32).to_be_bytes())
+        .unwrap();
+    assert!(events.wait_for(1, is_receive_failed));
+    assert!(events.wait_for(1, is_closed));
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::net::{TcpListener, TcpStream, UdpSocket};
+
+use common::*;
+use socket_engine::prelude::*;
+
+// An IPv6 endpoint on a port free at the time of the call
+fn free_v6(proto: &str, host: &str) -> Endpoint {
+    let port = TcpListener::bind("[::]:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    format!("{} [{}]:{}", proto, host, port).parse().unwrap()
+}
+
+fn senders(events: &Events) -> Vec<Endpoint> {
+    events
+        .all()
+        .into_iter()
+        .filter_map(|e| match e {
+            SocketEngineEvent::Data(DataEvent::Received { from, .. }) => Some(from),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn loopback_v6_peers_round_trip() {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let endpoint = free_v6("udp", "::1");
+    listen(&engine, &endpoint);
+
+    let client = UdpSocket::bind("[::1]:0").unwrap();
+    let address = endpoint.to_string();
+    client
+        .send_to(b"hello", address.trim_start_matches("udp "))
+        .unwrap();
+    assert!(events.wait_for(1, is_received));
+
+    let from = senders(&events).remove(0);
+    assert_eq!(
+        from.to_string(),
+        format!("udp {}", client.local_addr().unwrap())
+    );
+    assert_eq!(from.to_string().parse::<Endpoint>().unwrap(), from);
+}
+
+#[test]
+fn dual_stack_listener_sees_v4_mapped_peers() {
+    let engine = Engine::new().with_ipv6_only(false);
+    let events = Events::attach(&engine);
+    let endpoint = free_v6("udp", "::");
+    listen(&engine, &endpoint);
+
+    let port = endpoint.endpoint.rsplit_once(':').unwrap().1;
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .send_to(b"hello", format!("127.0.0.1:{}", port))
+        .unwrap();
+    assert!(events.wait_for(1, is_received));
+
+    let from = senders(&events).remove(0);
+    let client_port = client.local_addr().unwrap().port();
+    assert_eq!(
+        from.to_string(),
+        format!("udp [::ffff:127.0.0.1]:{}", client_port)
+    );
+}
+
+#[test]
+fn v6_only_listener_refuses_v4_peers() {
+    let engine = Engine::new().with_ipv6_only(true);
+    let endpoint = free_v6("tcp", "::");
+    listen(&engine, &endpoint);
+
+    let port = endpoint.endpoint.rsplit_once(':').unwrap().1;
+    assert!(TcpStream::connect(format!("127.0.0.1:{}", port)).is_err());
+    assert!(TcpStream::connect(format!("[::1]:{}", port)).is_ok());
+}
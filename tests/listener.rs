@@ -0,0 +1,338 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream, UdpSocket},
+    sync::{mpsc, Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+fn is_accepted(e: &SocketEngineEvent) -> bool {
+    matches!(
+        e,
+        SocketEngineEvent::Connection(ConnectionEvent::Accepted { .. })
+    )
+}
+
+fn is_socket_error(e: &SocketEngineEvent) -> bool {
+    matches!(e, SocketEngineEvent::Error(ErrorEvent::SocketError { .. }))
+}
+
+// Starts a second listener on the endpoint of the first one once it runs
+struct Restarter(Arc<OnceLock<Arc<Engine>>>);
+
+impl EngineObserver for Restarter {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if let SocketEngineEvent::Connection(ConnectionEvent::ListenerStarted { endpoint }) = event
+        {
+            let handle = self.0.get().unwrap().start_listener_async(endpoint);
+            assert!(matches!(handle.status(), ListenerStatus::Failed(_)));
+        }
+    }
+}
+
+#[test]
+fn socket_failure_reported_to_observer_starting_listener() {
+    let cell = Arc::new(OnceLock::new());
+    let engine = Arc::new(Engine::new());
+    let _ = cell.set(engine.clone());
+    engine.add_observer(Arc::new(Mutex::new(Restarter(cell))));
+    let events = Events::attach(&engine);
+    listen(&engine, &free_endpoint("udp"));
+    assert!(events.wait_for(1, is_socket_error), "observer deadlocked");
+}
+
+#[test]
+fn many_listeners_do_not_delay_sends() {
+    let runtime = small_runtime(2);
+    let engine = Engine::new().with_runtime(runtime.handle().clone());
+    let events = Events::attach(&engine);
+    // Each one listens before the next port is picked, which could be the same
+    let endpoints: Vec<_> = (0..100)
+        .map(|_| {
+            let endpoint = free_endpoint("udp");
+            listen(&engine, &endpoint);
+            endpoint
+        })
+        .collect();
+
+    let started = Instant::now();
+    engine
+        .send_blocking(
+            endpoints[0].clone(),
+            b"hello".to_vec(),
+            SendOptions::default(),
+        )
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert!(events.wait_for(1, is_received));
+}
+
+#[test]
+fn idle_connections_do_not_delay_sends() {
+    let runtime = small_runtime(2);
+    let engine = Engine::new().with_runtime(runtime.handle().clone());
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("tcp");
+    listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+    let idle: Vec<_> = (0..8)
+        .map(|_| TcpStream::connect(address.trim_start_matches("tcp ")).unwrap())
+        .collect();
+    assert!(events.wait_for(idle.len(), is_accepted));
+
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let started = Instant::now();
+    engine
+        .send_blocking(tcp_target(&peer), b"hello".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+// Reports when each datagram is delivered
+#[cfg(not(feature = "with_delay"))]
+struct Arrivals(mpsc::Sender<Instant>);
+
+#[cfg(not(feature = "with_delay"))]
+impl EngineObserver for Arrivals {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if is_received(&event) {
+            let _ = self.0.send(Instant::now());
+        }
+    }
+}
+
+// Delivered as soon as the socket is readable, well within the poll interval
+#[cfg(not(feature = "with_delay"))]
+#[test]
+fn datagrams_are_received_within_a_millisecond() {
+    let engine = Engine::new();
+    let (arrivals, arrived) = mpsc::channel();
+    engine.add_observer(Arc::new(Mutex::new(Arrivals(arrivals))));
+    let endpoint = free_endpoint("udp");
+    listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+    let address = address.trim_start_matches("udp ");
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    let mut latencies = Vec::new();
+    for _ in 0..21 {
+        // The listener is back to waiting on the socket
+        std::thread::sleep(Duration::from_millis(5));
+        let sent = Instant::now();
+        sender.send_to(b"ping", address).unwrap();
+        let received = arrived.recv_timeout(Duration::from_secs(5)).unwrap();
+        latencies.push(received - sent);
+    }
+    latencies.sort();
+    let median = latencies[latencies.len() / 2];
+    assert!(median < Duration::from_millis(1), "{:?}", latencies);
+}
+
+// Holds the listener delivering to it until released
+struct Stuck {
+    entered: mpsc::Sender<()>,
+    release: Mutex<mpsc::Receiver<()>>,
+}
+
+impl EngineObserver for Stuck {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if is_received(&event) {
+            let _ = self.entered.send(());
+            let _ = self
+                .release
+                .lock()
+                .unwrap()
+                .recv_timeout(Duration::from_secs(10));
+        }
+    }
+}
+
+#[test]
+fn drop_waits_a_bounded_time_for_stuck_listeners() {
+    let engine =
+        Engine::with_config(EngineConfig::default().poll_interval(Duration::from_millis(50)));
+    let (entered, stuck) = mpsc::channel();
+    let (release, released) = mpsc::channel();
+    engine.add_observer(Arc::new(Mutex::new(Stuck {
+        entered,
+        release: Mutex::new(released),
+    })));
+    let endpoint = free_endpoint("udp");
+    let handle = listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .send_to(b"hello", address.trim_start_matches("udp "))
+        .unwrap();
+    stuck.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    let started = Instant::now();
+    drop(engine);
+    let waited = started.elapsed();
+    assert!(waited >= Duration::from_secs(1), "{:?}", waited);
+    assert!(waited < Duration::from_millis(1500), "{:?}", waited);
+    assert_eq!(handle.status(), ListenerStatus::Running);
+
+    release.send(()).unwrap();
+    wait_until(|| handle.status() == ListenerStatus::Stopped);
+}
+
+#[test]
+fn dropped_engine_frees_its_ports() {
+    let engine = Engine::new();
+    let udp = free_endpoint("udp");
+    let tcp = free_endpoint("tcp");
+    let handles = [listen(&engine, &udp), listen(&engine, &tcp)];
+
+    drop(engine);
+    for handle in &handles {
+        assert_eq!(handle.status(), ListenerStatus::Stopped);
+    }
+    let udp = udp.to_string();
+    UdpSocket::bind(udp.trim_start_matches("udp ")).unwrap();
+    let tcp = tcp.to_string();
+    TcpListener::bind(tcp.trim_start_matches("tcp ")).unwrap();
+}
+
+#[test]
+fn listener_errors_name_the_endpoint() {
+    let engine = Engine::new();
+    let endpoint = free_endpoint("udp");
+    assert!(matches!(
+        engine.stop_listener(endpoint.clone()),
+        Err(SocketEngineError::UnknownListener(e)) if e == endpoint
+    ));
+
+    listen(&engine, &endpoint);
+    let second = engine.start_listener_async(endpoint.clone());
+    assert!(matches!(
+        block_on(second.wait_ready()),
+        Err(SocketEngineError::ListenerFailed { endpoint: e, .. }) if e == endpoint
+    ));
+}
+
+#[test]
+fn listener_with_message_limit_stops_after_one() {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("udp");
+    let limits = ListenerLimits {
+        max_messages: Some(1),
+        max_duration: None,
+    };
+    engine.start_listener_with_limits(endpoint.clone(), limits);
+    let address = endpoint.endpoint.as_str();
+    wait_until(|| UdpSocket::bind(address).is_err());
+
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sender.send_to(b"first", address).unwrap();
+    assert!(events.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Connection(ConnectionEvent::ListenerStopped {
+            reason: ListenerStopReason::LimitReached,
+            messages: 1,
+            ..
+        })
+    )));
+    let _ = sender.send_to(b"second", address);
+
+    assert!(events.wait_for(1, is_received));
+    assert_eq!(events.received(), [b"first"]);
+    UdpSocket::bind(address).unwrap();
+}
+
+#[test]
+fn connections_beyond_the_limit_are_refused() {
+    let engine = Engine::new().with_max_connections(2);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("tcp");
+    listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+    let connect = || {
+        let stream = TcpStream::connect(address.trim_start_matches("tcp ")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    };
+    let is_too_many = |e: &SocketEngineEvent| {
+        matches!(
+            e,
+            SocketEngineEvent::Error(ErrorEvent::SocketError {
+                error: SocketEngineError::TooManyConnections { max: 2, .. },
+                ..
+            })
+        )
+    };
+
+    let mut first = connect();
+    let mut second = connect();
+    assert!(events.wait_for(2, is_accepted));
+    let mut third = connect();
+    assert!(events.wait_for(1, is_too_many));
+    // Closed by the listener, reset or at end of stream
+    assert!(matches!(third.read(&mut [0; 1]), Ok(0) | Err(_)));
+
+    first.write_all(b"one").unwrap();
+    second.write_all(b"two").unwrap();
+    assert!(events.wait_for(2, is_received));
+
+    // A slot frees up once a connection ends
+    let closed = events.count(is_closed);
+    drop(first);
+    assert!(events.wait_for(closed + 1, is_closed));
+    let mut fourth = connect();
+    fourth.write_all(b"four").unwrap();
+    assert!(events.wait_for(3, is_received));
+    assert_eq!(events.count(is_too_many), 1);
+}
+
+#[test]
+fn multicast_listeners_sharing_a_port_receive_the_group() {
+    let group = Ipv4Addr::new(239, 255, 42, 17);
+    let port = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let endpoint: Endpoint = format!("udp 0.0.0.0:{}", port).parse().unwrap();
+    let groups = vec![MulticastGroup::V4 {
+        group,
+        interface: Ipv4Addr::LOCALHOST,
+    }];
+    let engines = [Engine::new(), Engine::new()];
+    let events: Vec<Events> = engines.iter().map(Events::attach).collect();
+    let mut listeners: Vec<ListenerHandle> = engines
+        .iter()
+        .map(|engine| engine.start_multicast_listener(endpoint.clone(), groups.clone()))
+        .collect();
+    listeners.iter().for_each(wait_running);
+
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sender.set_multicast_loop_v4(true).unwrap();
+    sender.send_to(b"to the group", (group, port)).unwrap();
+    for events in &events {
+        assert!(events.wait_for(1, is_received));
+        assert_eq!(events.received(), [b"to the group"]);
+        let from = events.all().into_iter().find_map(|e| match e {
+            SocketEngineEvent::Data(DataEvent::Received { from, .. }) => Some(from),
+            _ => None,
+        });
+        assert_eq!(
+            from.unwrap().to_string(),
+            format!("udp {}", sender.local_addr().unwrap())
+        );
+    }
+
+    // Stopping one listener leaves the group for it alone
+    engines[0].stop_listener(endpoint.clone()).unwrap();
+    let stopped = listeners.remove(0);
+    wait_until(|| !stopped.is_running());
+    sender.send_to(b"again", (group, port)).unwrap();
+    assert!(events[1].wait_for(2, is_received));
+    assert_eq!(events[0].count(is_received), 1);
+}
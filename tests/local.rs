@@ -0,0 +1,67 @@
+mod common;
+
+use common::*;
+use socket_engine::prelude::*;
+
+// Data events without what tells the two paths apart: flag, wire bytes, order
+// of events emitted on different threads
+fn summary(events: &Events) -> (Vec<String>, Vec<bool>) {
+    let mut kinds = Vec::new();
+    let mut flags = Vec::new();
+    for event in events.all() {
+        let (kind, local) = match event {
+            SocketEngineEvent::Data(DataEvent::Sending {
+                to, bytes, local, ..
+            }) => (format!("sending {} to {}", bytes, to), local),
+            SocketEngineEvent::Data(DataEvent::Sent {
+                to,
+                bytes_sent,
+                local,
+                ..
+            }) => (format!("sent {} to {}", bytes_sent, to), local),
+            SocketEngineEvent::Data(DataEvent::Received {
+                data, from, local, ..
+            }) => (format!("received {:?} from {}", data, from), local),
+            _ => continue,
+        };
+        kinds.push(kind);
+        flags.push(local);
+    }
+    kinds.sort();
+    (kinds, flags)
+}
+
+fn deliver_to_self(shortcut: bool) -> (Vec<String>, Vec<bool>, Endpoint) {
+    let engine = Engine::new().with_local_shortcut(shortcut);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("udp");
+    listen(&engine, &endpoint);
+
+    engine
+        .send(
+            endpoint.clone(),
+            b"hello".to_vec(),
+            SendOptions::default()
+                .source(endpoint.clone())
+                .token("hello"),
+        )
+        .unwrap();
+    assert!(events.wait_for(1, is_received));
+    let (kinds, flags) = summary(&events);
+    (kinds, flags, endpoint)
+}
+
+#[test]
+fn shortcut_matches_socket_delivery() {
+    let (through_socket, flags, endpoint) = deliver_to_self(false);
+    assert_eq!(flags, [false; 3]);
+    let (shortcut, flags, shortcut_endpoint) = deliver_to_self(true);
+    assert_eq!(flags, [true; 3]);
+
+    // The two engines listen on different ports
+    let shortcut: Vec<_> = shortcut
+        .into_iter()
+        .map(|kind| kind.replace(&shortcut_endpoint.to_string(), &endpoint.to_string()))
+        .collect();
+    assert_eq!(shortcut, through_socket);
+}
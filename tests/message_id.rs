@@ -0,0 +1,17 @@
+use std::collections::HashSet;
+
+use socket_engine::prelude::*;
+
+#[test]
+fn v4_ids_are_well_formed_and_distinct() {
+    let ids: HashSet<String> = (0..10_000)
+        .map(|_| MessageId::new_v4().as_str().to_owned())
+        .collect();
+    assert_eq!(ids.len(), 10_000);
+    for id in &ids {
+        let groups: Vec<_> = id.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12], "{}", id);
+        assert_eq!(&id[14..15], "4", "{}", id);
+        assert!("89ab".contains(&id[19..20]), "{}", id);
+    }
+}
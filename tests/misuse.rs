@@ -0,0 +1,211 @@
+mod common;
+
+use std::{
+    net::TcpListener,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+fn is_misuse(kind: MisuseKind) -> impl Fn(&SocketEngineEvent) -> bool {
+    move |e| matches!(e, SocketEngineEvent::Error(ErrorEvent::Misuse { kind: k, .. }) if *k == kind)
+}
+
+fn is_warning(kind: MisuseKind) -> impl Fn(&SocketEngineEvent) -> bool {
+    move |e| matches!(e, SocketEngineEvent::Error(ErrorEvent::MisuseWarning { kind: k, .. }) if *k == kind)
+}
+
+fn is_sending(e: &SocketEngineEvent) -> bool {
+    matches!(e, SocketEngineEvent::Data(DataEvent::Sending { .. }))
+}
+
+struct Silent;
+
+impl EngineObserver for Silent {
+    fn on_engine_event(&mut self, _: SocketEngineEvent) {}
+}
+
+fn add_twice(engine: &Engine) {
+    let observer = Arc::new(Mutex::new(Silent));
+    engine.add_observer(observer.clone());
+    engine.add_observer(observer);
+}
+
+fn send_from_unknown_source(engine: &Engine, token: &str) -> Result<SendHandle, SocketEngineError> {
+    engine.send(
+        free_endpoint("udp"),
+        b"payload".to_vec(),
+        SendOptions::default()
+            .source(free_endpoint("udp"))
+            .token(token),
+    )
+}
+
+// Kept pending by a peer that never accepts, once the socket buffers are full,
+// until the peer is dropped
+fn send_pending(
+    engine: &Engine,
+    peer: &TcpListener,
+    token: &str,
+) -> Result<SendHandle, SocketEngineError> {
+    engine.send(
+        tcp_target(peer),
+        vec![0; 64 << 20],
+        SendOptions::default().token(token),
+    )
+}
+
+#[test]
+fn duplicate_observer_strict() {
+    let engine = Engine::new().with_strict(true);
+    let events = Events::attach(&engine);
+    add_twice(&engine);
+    assert!(events.wait_for(1, is_misuse(MisuseKind::DuplicateObserver)));
+    assert_eq!(engine.misuse_warnings(), 0);
+}
+
+#[test]
+fn duplicate_observer_lenient() {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    add_twice(&engine);
+    add_twice(&engine);
+    assert!(events.wait_for(1, is_warning(MisuseKind::DuplicateObserver)));
+    assert_eq!(engine.misuse_warnings(), 2);
+    assert_eq!(engine.total_stats().misuse_warnings, 2);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(events.count(is_warning(MisuseKind::DuplicateObserver)), 1);
+}
+
+#[test]
+fn unknown_source_strict() {
+    let engine = Engine::new().with_strict(true);
+    let events = Events::attach(&engine);
+    assert!(matches!(
+        send_from_unknown_source(&engine, "first"),
+        Err(SocketEngineError::Misuse(MisuseKind::UnknownSource))
+    ));
+    assert!(events.wait_for(1, is_misuse(MisuseKind::UnknownSource)));
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(events.count(is_sending), 0);
+}
+
+#[test]
+fn unknown_source_lenient() {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    send_from_unknown_source(&engine, "first").unwrap();
+    send_from_unknown_source(&engine, "second").unwrap();
+    assert!(events.wait_for(1, is_warning(MisuseKind::UnknownSource)));
+    assert!(events.wait_for(2, is_sending));
+    assert_eq!(engine.misuse_warnings(), 2);
+    assert_eq!(events.count(is_warning(MisuseKind::UnknownSource)), 1);
+}
+
+#[test]
+fn token_reused_strict() {
+    let engine = Engine::new().with_strict(true);
+    let events = Events::attach(&engine);
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    send_pending(&engine, &peer, "same").unwrap();
+    assert!(matches!(
+        send_pending(&engine, &peer, "same"),
+        Err(SocketEngineError::Misuse(MisuseKind::TokenReused))
+    ));
+    // Ends the pending send, which may hold the only runtime worker
+    drop(peer);
+    assert!(events.wait_for(1, is_misuse(MisuseKind::TokenReused)));
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(events.count(is_sending), 1);
+}
+
+#[test]
+fn token_reused_lenient() {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    send_pending(&engine, &peer, "same").unwrap();
+    send_pending(&engine, &peer, "same").unwrap();
+    // Ends the pending send, which may hold the only runtime worker
+    drop(peer);
+    assert!(events.wait_for(1, is_warning(MisuseKind::TokenReused)));
+    assert!(events.wait_for(2, is_sending));
+    assert_eq!(engine.misuse_warnings(), 1);
+}
+
+// Misuses from inside a notification, the warning then goes to the same observer
+struct Misbehaving(Arc<OnceLock<Arc<Engine>>>);
+
+impl EngineObserver for Misbehaving {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        if let SocketEngineEvent::Connection(ConnectionEvent::ListenerStarted { .. }) = event {
+            let _ = send_from_unknown_source(self.0.get().unwrap(), "nested");
+        }
+    }
+}
+
+#[test]
+fn misuse_reported_to_observer_misusing() {
+    for strict in [false, true] {
+        let cell = Arc::new(OnceLock::new());
+        let engine = Arc::new(Engine::new().with_strict(strict));
+        let _ = cell.set(engine.clone());
+        engine.add_observer(Arc::new(Mutex::new(Misbehaving(cell))));
+        let events = Events::attach(&engine);
+        listen(&engine, &free_endpoint("udp"));
+        let reported = |e: &SocketEngineEvent| {
+            is_misuse(MisuseKind::UnknownSource)(e) || is_warning(MisuseKind::UnknownSource)(e)
+        };
+        assert!(events.wait_for(1, reported), "observer deadlocked");
+    }
+}
+
+// A family no system knows, so that BP sockets cannot be created
+#[cfg(feature = "bp")]
+fn listen_on_unsupported_protocol(strict: bool) -> (Engine, Events, ListenerHandle) {
+    let engine = Engine::with_config(EngineConfig::default().bp(BpConfig {
+        family: 255,
+        protocol: 0,
+    }))
+    .with_strict(strict);
+    let events = Events::attach(&engine);
+    let handle = engine.start_listener_async("bp ipn:1.2".parse().unwrap());
+    (engine, events, handle)
+}
+
+#[cfg(feature = "bp")]
+#[test]
+fn unsupported_protocol_strict() {
+    let (engine, events, handle) = listen_on_unsupported_protocol(true);
+    assert!(matches!(
+        block_on(handle.wait_ready()),
+        Err(SocketEngineError::ListenerFailed { .. })
+    ));
+    assert!(events.wait_for(1, is_misuse(MisuseKind::UnsupportedProtocol)));
+    assert!(events.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Error(ErrorEvent::SocketError {
+            error: SocketEngineError::Misuse(MisuseKind::UnsupportedProtocol),
+            ..
+        })
+    )));
+    assert_eq!(engine.misuse_warnings(), 0);
+}
+
+#[cfg(feature = "bp")]
+#[test]
+fn unsupported_protocol_lenient() {
+    let (engine, events, handle) = listen_on_unsupported_protocol(false);
+    assert!(matches!(handle.status(), ListenerStatus::Failed(_)));
+    assert!(events.wait_for(1, is_warning(MisuseKind::UnsupportedProtocol)));
+    assert!(events.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Error(ErrorEvent::SocketError {
+            error: SocketEngineError::Socket(_),
+            ..
+        })
+    )));
+    assert_eq!(engine.misuse_warnings(), 1);
+}
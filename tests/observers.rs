@@ -0,0 +1,136 @@
+mod common;
+
+use std::{
+    net::UdpSocket,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+// Records the payloads it receives, each after a pause
+#[derive(Default)]
+struct SlowRecorder(Mutex<Vec<Vec<u8>>>);
+
+impl AsyncEngineObserver for SlowRecorder {
+    async fn on_engine_event(&self, event: SocketEngineEvent) {
+        if let SocketEngineEvent::Data(DataEvent::Received { data, .. }) = event {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.0.lock().unwrap().push(data);
+        }
+    }
+}
+
+// Sends each payload in a datagram to the UDP `endpoint`
+fn send_to(endpoint: &Endpoint, payloads: &[&[u8]]) {
+    let address = endpoint.to_string();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for payload in payloads {
+        socket
+            .send_to(payload, address.trim_start_matches("udp "))
+            .unwrap();
+    }
+}
+
+#[test]
+fn async_observers_get_events_in_order_without_delaying_others() {
+    let engine = Engine::new();
+    let slow = Arc::new(SlowRecorder::default());
+    let id = engine.add_async_observer(slow.clone());
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("udp");
+    listen(&engine, &endpoint);
+
+    send_to(&endpoint, &[b"one", b"two", b"three"]);
+    // The sync observer does not wait for the async one
+    assert!(events.wait_for(3, is_received));
+    assert!(slow.0.lock().unwrap().len() < 3);
+    wait_until(|| slow.0.lock().unwrap().len() == 3);
+    // In the order they were emitted, which `with_delay` may shuffle
+    assert_eq!(*slow.0.lock().unwrap(), events.received());
+
+    assert!(engine.remove_observer(id));
+    send_to(&endpoint, &[b"four"]);
+    assert!(events.wait_for(4, is_received));
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(slow.0.lock().unwrap().len(), 3);
+}
+
+// Categories of the events an observer received
+#[derive(Default)]
+struct Categories(Vec<EventMask>);
+
+impl EngineObserver for Categories {
+    fn on_engine_event(&mut self, event: SocketEngineEvent) {
+        self.0.push(match event {
+            SocketEngineEvent::Data(_) => EventMask::DATA,
+            SocketEngineEvent::Connection(_) => EventMask::CONNECTION,
+            SocketEngineEvent::Error(_) => EventMask::ERROR,
+        });
+    }
+}
+
+#[test]
+fn masks_select_the_categories_delivered() {
+    let engine = Engine::new();
+    let masks = [
+        EventMask::ALL,
+        EventMask::DATA,
+        EventMask::ERROR,
+        EventMask::DATA | EventMask::CONNECTION,
+    ];
+    let observers: Vec<_> = masks
+        .iter()
+        .map(|&mask| {
+            let observer = Arc::new(Mutex::new(Categories::default()));
+            engine.add_observer_filtered(observer.clone(), mask);
+            observer
+        })
+        .collect();
+    let endpoint = free_endpoint("tcp");
+    listen(&engine, &endpoint);
+
+    // Data and connection events, then an error for the closed port
+    engine
+        .send_blocking(endpoint.clone(), b"x".to_vec(), SendOptions::default())
+        .unwrap();
+    let closed = free_endpoint("tcp");
+    assert!(engine
+        .send_blocking(closed, b"x".to_vec(), SendOptions::default())
+        .is_err());
+
+    let all = observers[0].clone();
+    wait_until(|| all.lock().unwrap().0.contains(&EventMask::ERROR));
+    for (observer, mask) in observers.iter().zip(masks) {
+        let received = &observer.lock().unwrap().0;
+        for category in [EventMask::DATA, EventMask::CONNECTION, EventMask::ERROR] {
+            assert_eq!(
+                received.contains(&category),
+                mask.contains(category),
+                "{:?} {:?}",
+                mask,
+                category
+            );
+        }
+    }
+}
+
+#[test]
+fn filtered_out_events_never_lock_the_observer() {
+    let engine = Engine::new();
+    let errors = Arc::new(Mutex::new(Categories::default()));
+    engine.add_observer_filtered(errors.clone(), EventMask::ERROR);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("udp");
+    listen(&engine, &endpoint);
+
+    // Would block the listener and the send if they notified it
+    let held = errors.lock().unwrap();
+    engine
+        .send_blocking(endpoint, b"x".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(events.wait_for(1, is_received));
+    drop(held);
+    assert!(errors.lock().unwrap().0.is_empty());
+}
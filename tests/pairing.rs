@@ -0,0 +1,89 @@
+mod common;
+
+use std::{thread, time::Duration};
+
+use common::*;
+use socket_engine::prelude::*;
+
+// Codes of their own, so that concurrent tests do not see each other
+fn code(name: &str) -> String {
+    format!("{}-{}", name, std::process::id())
+}
+
+fn listening_engine() -> (Engine, Events, Endpoint) {
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("udp");
+    listen(&engine, &endpoint);
+    (engine, events, endpoint)
+}
+
+fn is_paired(e: &SocketEngineEvent) -> bool {
+    matches!(
+        e,
+        SocketEngineEvent::Connection(ConnectionEvent::Paired { .. })
+    )
+}
+
+#[test]
+fn two_engines_pair_with_each_other() {
+    let code = code("two");
+    let (first, first_events, first_endpoint) = listening_engine();
+    let (second, second_events, second_endpoint) = listening_engine();
+
+    let paired = thread::scope(|scope| {
+        let other = scope.spawn(|| second.pair_with_code(&code, Duration::from_secs(5)));
+        let paired = first.pair_with_code(&code, Duration::from_secs(5)).unwrap();
+        (paired, other.join().unwrap().unwrap())
+    });
+
+    assert_eq!(paired, (second_endpoint.clone(), first_endpoint.clone()));
+    assert_eq!(first.peer(PAIR_ALIAS), Some(second_endpoint));
+    assert_eq!(second.peer(PAIR_ALIAS), Some(first_endpoint));
+    for events in [first_events, second_events] {
+        assert_eq!(events.count(is_paired), 1);
+    }
+}
+
+#[test]
+fn third_participant_makes_pairing_ambiguous() {
+    let code = code("three");
+    let engines: Vec<_> = (0..3).map(|_| listening_engine()).collect();
+
+    let results: Vec<_> = thread::scope(|scope| {
+        let pairings: Vec<_> = engines
+            .iter()
+            .map(|(engine, ..)| {
+                scope.spawn(|| engine.pair_with_code(&code, Duration::from_secs(5)))
+            })
+            .collect();
+        pairings.into_iter().map(|p| p.join().unwrap()).collect()
+    });
+
+    for result in results {
+        assert!(
+            matches!(result, Err(PairingError::Ambiguous { participants: 3 })),
+            "{:?}",
+            result
+        );
+    }
+}
+
+#[test]
+fn lone_participant_times_out() {
+    let (engine, events, _) = listening_engine();
+    let result = engine.pair_with_code(&code("lone"), Duration::from_millis(300));
+    assert!(matches!(result, Err(PairingError::Timeout)), "{:?}", result);
+    assert_eq!(engine.peer(PAIR_ALIAS), None);
+    assert_eq!(events.count(is_paired), 0);
+}
+
+#[test]
+fn pairing_needs_a_listener() {
+    let result = Engine::new().pair_with_code(&code("none"), Duration::from_millis(300));
+    assert!(
+        matches!(result, Err(PairingError::NoListener)),
+        "{:?}",
+        result
+    );
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use std::{net::TcpListener, time::Duration};
+
+use common::*;
+use socket_engine::{peer_state::PeerStateChange, peer_state::PeerStateTracker, prelude::*};
+
+fn peer() -> Endpoint {
+    "udp 127.0.0.1:9".parse().unwrap()
+}
+
+fn sent() -> SocketEngineEvent {
+    SocketEngineEvent::Data(DataEvent::Sent {
+        token: "t".into(),
+        to: peer(),
+        bytes_sent: 1,
+        wire_bytes: 1,
+        from: None,
+        local: false,
+    })
+}
+
+fn failed() -> SocketEngineEvent {
+    SocketEngineEvent::Error(ErrorEvent::ConnectionFailed {
+        endpoint: peer(),
+        reason: ConnectionFailureReason::Refused,
+        token: "t".into(),
+    })
+}
+
+fn echo(rtt: Option<Duration>) -> SocketEngineEvent {
+    SocketEngineEvent::Data(DataEvent::EchoReply {
+        to: peer(),
+        seq: 0,
+        rtt,
+    })
+}
+
+// States after each event, and the transitions seen
+fn run(
+    thresholds: PeerStateThresholds,
+    events: &[SocketEngineEvent],
+) -> (Vec<PeerState>, Vec<PeerStateChange>) {
+    let mut tracker = PeerStateTracker::new(thresholds);
+    let mut states = Vec::new();
+    let mut changes = Vec::new();
+    for event in events {
+        changes.extend(tracker.apply(event));
+        states.push(tracker.state(&peer()));
+    }
+    (states, changes)
+}
+
+#[test]
+fn failures_degrade_then_one_success_restores() {
+    use PeerState::*;
+
+    let events = [
+        sent(),
+        failed(),
+        failed(),
+        failed(),
+        failed(),
+        echo(None),
+        sent(),
+    ];
+    let (states, changes) = run(PeerStateThresholds::default(), &events);
+    assert_eq!(
+        states,
+        [
+            Reachable,
+            Degraded,
+            Degraded,
+            Unreachable,
+            Unreachable,
+            Unreachable,
+            Reachable
+        ]
+    );
+    // Transitions only
+    let transitions: Vec<_> = changes
+        .iter()
+        .map(|change| (change.old, change.new, change.cause))
+        .collect();
+    assert_eq!(
+        transitions,
+        [
+            (Unknown, Reachable, PeerStateCause::Sent),
+            (Reachable, Degraded, PeerStateCause::ConnectionFailed),
+            (Degraded, Unreachable, PeerStateCause::ConnectionFailed),
+            (Unreachable, Reachable, PeerStateCause::Sent),
+        ]
+    );
+}
+
+#[test]
+fn thresholds_and_echoes_drive_transitions() {
+    use PeerState::*;
+
+    let thresholds = PeerStateThresholds {
+        degraded_after: 2,
+        unreachable_after: 2,
+    };
+    let events = [echo(Some(Duration::from_millis(1))), echo(None), echo(None)];
+    let (states, _) = run(thresholds, &events);
+    assert_eq!(states, [Reachable, Reachable, Unreachable]);
+
+    // Unrelated events leave the peer alone
+    let closed = SocketEngineEvent::Connection(ConnectionEvent::Closed {
+        remote: Some(peer()),
+    });
+    let (states, changes) = run(thresholds, &[closed]);
+    assert_eq!((states, changes), (vec![Unknown], vec![]));
+}
+
+#[test]
+fn same_events_same_transitions() {
+    let events = [failed(), sent(), failed(), failed(), failed(), sent()];
+    let first = run(PeerStateThresholds::default(), &events);
+    let second = run(PeerStateThresholds::default(), &events);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn stopped_listener_makes_peer_unreachable() {
+    let engine = Engine::new().with_peer_states(PeerStateThresholds::default());
+    let events = Events::attach(&engine);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&listener);
+
+    engine
+        .send(
+            target.clone(),
+            b"up".to_vec(),
+            SendOptions::default().token("up"),
+        )
+        .unwrap();
+    wait_until(|| engine.peer_states().get(&target) == Some(&PeerState::Reachable));
+
+    drop(listener);
+    for n in 0..3 {
+        engine
+            .send(
+                target.clone(),
+                b"down".to_vec(),
+                SendOptions::default().token(n.to_string()),
+            )
+            .unwrap();
+        assert!(events.wait_for(n + 1, is_error));
+    }
+    wait_until(|| engine.peer_states().get(&target) == Some(&PeerState::Unreachable));
+    let changes = events.count(|e| {
+        matches!(
+            e,
+            SocketEngineEvent::Connection(ConnectionEvent::PeerStateChanged { .. })
+        )
+    });
+    assert_eq!(changes, 3);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::{
+    net::UdpSocket,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+fn received(envelope: &EventEnvelope) -> Option<Vec<u8>> {
+    match &envelope.event {
+        SocketEngineEvent::Data(DataEvent::Received { data, .. }) => Some(data.clone()),
+        _ => None,
+    }
+}
+
+#[test]
+#[cfg_attr(
+    feature = "with_delay",
+    ignore = "delayed Received events may be reordered"
+)]
+fn events_are_polled_in_order_alongside_observers() {
+    let engine = Engine::new().with_poll_queue(1024);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("udp");
+    listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+
+    let sender = thread::spawn(move || {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for n in 0..50u8 {
+            socket
+                .send_to(&[n], address.trim_start_matches("udp "))
+                .unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+    let mut seqs = Vec::new();
+    let mut payloads = Vec::new();
+    while payloads.len() < 50 {
+        let envelope = engine
+            .poll_event(Duration::from_secs(5))
+            .expect("no event in time");
+        seqs.push(envelope.seq);
+        payloads.extend(received(&envelope));
+    }
+    sender.join().unwrap();
+
+    assert!(seqs.windows(2).all(|pair| pair[1] == pair[0] + 1));
+    assert_eq!(payloads, (0..50u8).map(|n| vec![n]).collect::<Vec<_>>());
+    // The observer may be notified after the poll queue
+    assert!(events.wait_for(50, is_received));
+    assert_eq!(events.received(), payloads);
+    assert_eq!(engine.poll_dropped(), 0);
+}
+
+#[test]
+fn polling_an_idle_engine_times_out() {
+    let engine = Engine::new().with_poll_queue(8);
+    let started = Instant::now();
+    assert!(engine.poll_event(Duration::from_millis(100)).is_none());
+    assert!(started.elapsed() >= Duration::from_millis(100));
+
+    // Without a queue there is nothing to wait for
+    let started = Instant::now();
+    assert!(Engine::new().poll_event(Duration::from_secs(5)).is_none());
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+#[cfg_attr(
+    feature = "with_delay",
+    ignore = "delayed Received events may be reordered"
+)]
+fn overflow_drops_the_oldest_events() {
+    let engine = Engine::new().with_poll_queue(2);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("udp");
+    listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for n in 0..5u8 {
+        socket
+            .send_to(&[n], address.trim_start_matches("udp "))
+            .unwrap();
+    }
+    assert!(events.wait_for(5, is_received));
+
+    // `ListenerStarted` and three payloads were pushed out
+    assert_eq!(engine.poll_dropped(), 4);
+    let last: Vec<_> = std::iter::from_fn(|| engine.poll_event(Duration::ZERO)).collect();
+    assert_eq!(last.len(), 2);
+    assert_eq!(last[1].seq, last[0].seq + 1);
+    assert_eq!(
+        last.iter().filter_map(received).collect::<Vec<_>>(),
+        [[3], [4]]
+    );
+}
@@ -0,0 +1,233 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use socket2::SockRef;
+
+use common::*;
+use socket_engine::prelude::*;
+
+#[test]
+fn two_sends_one_accept() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&listener);
+    let engine = Engine::new().with_close_after_send(false);
+    let events = Events::attach(&engine);
+
+    for payload in [&b"first"[..], b"second"] {
+        engine
+            .send_blocking(target.clone(), payload.to_vec(), SendOptions::default())
+            .unwrap();
+    }
+
+    let (mut stream, _) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut received = [0; 11];
+    stream.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"firstsecond");
+
+    listener.set_nonblocking(true).unwrap();
+    assert!(listener.accept().is_err(), "a second connection was opened");
+    assert_eq!(events.count(is_established), 1);
+    assert_eq!(events.count(is_closed), 0);
+}
+
+#[test]
+fn idle_connection_is_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&listener);
+    let engine = Engine::new()
+        .with_close_after_send(false)
+        .with_idle_timeout(Duration::from_millis(100));
+    let events = Events::attach(&engine);
+
+    engine
+        .send_blocking(target, b"hello".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(events.wait_for(1, is_closed));
+    assert_eq!(engine.socket_count(), 0);
+}
+
+#[test]
+fn connection_closed_after_send_by_default() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&listener);
+    let engine = Engine::new();
+
+    for payload in [&b"first"[..], b"second"] {
+        engine
+            .send_blocking(target.clone(), payload.to_vec(), SendOptions::default())
+            .unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        assert_eq!(received, payload);
+    }
+    assert_eq!(engine.socket_count(), 0);
+}
+
+#[test]
+fn close_after_send_decides_the_connection_events() {
+    for close in [true, false] {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let engine = Engine::new().with_close_after_send(close);
+        let events = Events::attach(&engine);
+
+        engine
+            .send(
+                tcp_target(&listener),
+                b"hello".to_vec(),
+                SendOptions::default().token("hello"),
+            )
+            .unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let mut received = [0; 5];
+        stream.read_exact(&mut received).unwrap();
+        // End of stream when closed, still open otherwise
+        let mut more = [0; 1];
+        let closed = matches!(stream.read(&mut more), Ok(0));
+        assert_eq!(closed, close);
+
+        assert!(events.wait_for(1, is_established));
+        if close {
+            assert!(events.wait_for(1, is_closed));
+        } else {
+            assert_eq!(events.count(is_closed), 0);
+            assert_eq!(engine.socket_count(), 2);
+        }
+    }
+}
+
+#[test]
+fn reconnects_once_the_pooled_peer_is_gone() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&listener);
+    let engine = Engine::new()
+        .with_close_after_send(false)
+        .with_reconnect(true);
+    let events = Events::attach(&engine);
+
+    engine
+        .send_blocking(target.clone(), b"first".to_vec(), SendOptions::default())
+        .unwrap();
+    let (mut stream, _) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut received = [0; 5];
+    stream.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"first");
+    // Reset rather than closed, as a peer that died
+    SockRef::from(&stream)
+        .set_linger(Some(Duration::ZERO))
+        .unwrap();
+    drop(stream);
+
+    engine
+        .send_blocking(target, b"second".to_vec(), SendOptions::default())
+        .unwrap();
+    let (mut stream, _) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let mut received = Vec::new();
+    let _ = stream.read_to_end(&mut received);
+    assert_eq!(received, b"second");
+
+    listener.set_nonblocking(true).unwrap();
+    assert!(listener.accept().is_err(), "a third connection was opened");
+    assert_eq!(events.count(is_sent), 2);
+    assert_eq!(events.count(is_error), 0);
+    assert_eq!(events.count(is_established), 2);
+    assert_eq!(events.count(is_closed), 1);
+}
+
+#[test]
+fn engine_peers_answer_heartbeats() {
+    let peer = Engine::new();
+    let peer_events = Events::attach(&peer);
+    let endpoint = free_endpoint("tcp");
+    listen(&peer, &endpoint);
+    let engine = Engine::with_config(
+        EngineConfig::default()
+            .heartbeat_interval(Duration::from_millis(50))
+            .heartbeat_timeout(Duration::from_millis(100))
+            .heartbeat_max_missed(2),
+    )
+    .with_close_after_send(false);
+    let events = Events::attach(&engine);
+
+    engine
+        .send_blocking(endpoint, b"hello".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(peer_events.wait_for(1, is_received));
+    std::thread::sleep(Duration::from_millis(600));
+    assert_eq!(events.count(is_closed), 0);
+    assert_eq!(engine.socket_count(), 2);
+    // Probes are answered, not delivered
+    assert_eq!(peer_events.received(), [b"hello".to_vec()]);
+}
+
+#[test]
+fn connection_is_dropped_once_heartbeats_go_unanswered() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&listener);
+    let engine = Engine::with_config(
+        EngineConfig::default()
+            .heartbeat_interval(Duration::from_millis(50))
+            .heartbeat_timeout(Duration::from_millis(100))
+            .heartbeat_max_missed(2),
+    )
+    .with_close_after_send(false);
+    let events = Events::attach(&engine);
+
+    engine
+        .send_blocking(target.clone(), b"hello".to_vec(), SendOptions::default())
+        .unwrap();
+    // A peer answering probes as an engine listener does, until it stops
+    let answering = Arc::new(AtomicBool::new(true));
+    let (mut stream, _) = listener.accept().unwrap();
+    let peer = {
+        let answering = answering.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 64];
+            while let Ok(size @ 1..) = stream.read(&mut buf) {
+                if answering.load(Ordering::Relaxed) && buf[..size].ends_with(b"\xE0SE-PING") {
+                    stream.write_all(b"\xE0SE-PONG").unwrap();
+                }
+            }
+        })
+    };
+    std::thread::sleep(Duration::from_millis(400));
+    assert_eq!(events.count(is_closed), 0);
+    assert_eq!(engine.socket_count(), 2);
+
+    answering.store(false, Ordering::Relaxed);
+    assert!(events.wait_for(1, is_closed));
+    assert_eq!(engine.socket_count(), 0);
+    // The connection was shut down, ending the peer
+    peer.join().unwrap();
+
+    // The next send connects again
+    engine
+        .send_blocking(target, b"again".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(listener.accept().is_ok());
+    assert_eq!(events.count(is_established), 2);
+}
@@ -0,0 +1,105 @@
+//! Snapshot of the supported API: everything used here must stay reachable
+//! through the prelude, or the `runtime` module for process-wide settings, with
+//! these signatures. A change that breaks this file breaks downstream code the
+//! same way.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use socket_engine::{
+    prelude::*,
+    runtime::{set_thread_budget, thread_budget, ThreadBudget},
+};
+
+#[allow(dead_code)]
+struct Types(
+    Engine,
+    EngineConfig,
+    Endpoint,
+    EndpointProto,
+    EndpointParseError,
+    SendOptions,
+    SendHandle,
+    SendOutcome,
+    SendState,
+    SocketEngineError,
+    SocketEngineEvent,
+    DataEvent,
+    ConnectionEvent,
+    ErrorEvent,
+    ConnectionFailureReason,
+    ConnectionId,
+    ListenerStopReason,
+    MessageId,
+    MisuseKind,
+    ObserverId,
+    EventMask,
+    EventEnvelope,
+    ChannelObserver,
+    Framing,
+    FrameError,
+    PairingError,
+    PeerState,
+    PeerStateCause,
+    PeerStateThresholds,
+    PingReport,
+    RateLimit,
+    RetryPolicy,
+    AdoptedSocket,
+    ListenerHandle,
+    ListenerLimits,
+    ListenerStatus,
+    MulticastGroup,
+    EndpointStats,
+    ThreadBudget,
+);
+
+fn object_safe(_: &dyn EngineObserver, _: &dyn MessageAssembler) {}
+
+fn implemented<T: AsyncEngineObserver, M: ResponseMatcher>() {}
+
+#[test]
+fn prelude_covers_the_supported_api() {
+    let _ = object_safe;
+    let _ = implemented::<NoopObserver, fn(&MessageId, &Endpoint, &[u8]) -> bool>;
+
+    let _: fn() -> Engine = Engine::new;
+    let _: fn(EngineConfig) -> Engine = Engine::with_config;
+    let _: fn(&Engine, Endpoint) -> ListenerHandle = Engine::start_listener_async;
+    let _: fn(&Engine, Endpoint, ListenerLimits) -> ListenerHandle =
+        Engine::start_listener_with_limits;
+    let _: fn(&Engine, Endpoint) -> Result<(), SocketEngineError> = Engine::stop_listener;
+    let _: fn(&Engine, Endpoint, Vec<u8>, SendOptions) -> Result<SendHandle, SocketEngineError> =
+        Engine::send;
+    let _: fn(&Engine, Endpoint, Vec<u8>, SendOptions) -> Result<usize, SocketEngineError> =
+        Engine::send_blocking;
+    let _: fn(&Engine, &MessageId) -> bool = Engine::cancel_send;
+    let _: fn(&Engine, Arc<Mutex<dyn EngineObserver + Send + Sync>>) -> ObserverId =
+        Engine::add_observer;
+    let _: fn(&Engine, ObserverId) -> bool = Engine::remove_observer;
+    let _: fn(&Engine, &Endpoint) -> EndpointStats = Engine::stats;
+    let _: fn(&Engine) -> EndpointStats = Engine::total_stats;
+    let _: fn(&Engine) -> usize = Engine::socket_count;
+    let _: fn(&Engine) -> HashMap<Endpoint, PeerState> = Engine::peer_states;
+    let _: fn(&Engine, Duration) -> Option<EventEnvelope> = Engine::poll_event;
+    let _: fn(&Engine, ConnectionId, Vec<u8>, MessageId) -> Result<(), SocketEngineError> =
+        Engine::reply;
+    let _: fn(&Engine, &str, Duration) -> Result<Endpoint, PairingError> = Engine::pair_with_code;
+    let _: fn(&Engine, AdoptedSocket) -> ListenerHandle = Engine::adopt_listener;
+    let _: fn(&Engine, AdoptedSocket) -> Result<(), SocketEngineError> = Engine::adopt_send_socket;
+    let _: fn() -> MessageId = MessageId::new_v4;
+    let _: fn() -> ThreadBudget = thread_budget;
+    let _: fn(ThreadBudget) -> Result<(), SocketEngineError> = set_thread_budget;
+    let _ = PAIR_ALIAS;
+    #[cfg(feature = "bp")]
+    let _: fn(EngineConfig, BpConfig) -> EngineConfig = EngineConfig::bp;
+}
+
+struct NoopObserver;
+
+impl AsyncEngineObserver for NoopObserver {
+    async fn on_engine_event(&self, _: SocketEngineEvent) {}
+}
@@ -0,0 +1,72 @@
+//! The library reports failures through results and events, never by printing.
+//! The test harness captures what tests print, so the checks run in a child
+//! process with capture disabled.
+
+mod common;
+
+use std::{
+    env,
+    io::Write,
+    net::TcpStream,
+    process::{Command, Stdio},
+};
+
+use common::*;
+use socket_engine::{
+    framing::{FrameDecoder, DEFAULT_MAX_FRAME_SIZE},
+    prelude::*,
+};
+
+const CHILD: &str = "SOCKET_ENGINE_QUIET_CHILD";
+const BEGIN: &str = "--- begin ---";
+const END: &str = "--- end ---";
+
+// Decodes bad frames, directly and on a framed listener
+fn decode_bad_frames() {
+    let bad = (DEFAULT_MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+    let mut decoder = FrameDecoder::new(DEFAULT_MAX_FRAME_SIZE);
+    assert!(decoder.push(&bad).is_err());
+    let mut decoder = FrameDecoder::new(DEFAULT_MAX_FRAME_SIZE);
+    decoder.push(&[0, 0, 0, 5, b'h']).unwrap();
+    assert!(decoder.finish().is_err());
+
+    let engine = Engine::new().with_length_prefix_framing(true);
+    let events = Events::attach(&engine);
+    let endpoint = free_endpoint("tcp");
+    listen(&engine, &endpoint);
+    let address = endpoint.to_string();
+    let mut stream = TcpStream::connect(address.trim_start_matches("tcp ")).unwrap();
+    stream.write_all(&bad).unwrap();
+    assert!(events.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Error(ErrorEvent::ReceiveFailed { .. })
+    )));
+}
+
+#[test]
+fn bad_frames_print_nothing() {
+    if env::var_os(CHILD).is_some() {
+        println!("{}", BEGIN);
+        eprintln!("{}", BEGIN);
+        decode_bad_frames();
+        println!("{}", END);
+        eprintln!("{}", END);
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["bad_frames_print_nothing", "--exact", "--nocapture"])
+        .env(CHILD, "1")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    for stream in [output.stdout, output.stderr] {
+        let stream = String::from_utf8(stream).unwrap();
+        let printed = stream
+            .split_once(BEGIN)
+            .and_then(|(_, rest)| rest.split_once(END))
+            .map(|(printed, _)| printed.trim().to_string());
+        assert_eq!(printed.as_deref(), Some(""), "{}", stream);
+    }
+}
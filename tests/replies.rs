@@ -0,0 +1,127 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    time::Duration,
+};
+
+use common::*;
+use socket_engine::prelude::*;
+
+#[test]
+fn replies_are_read_once_the_write_half_is_shut_down() {
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&peer);
+    let engine = Engine::new().with_read_replies(true);
+    let events = Events::attach(&engine);
+
+    engine
+        .send_blocking(target.clone(), b"question".to_vec(), SendOptions::default())
+        .unwrap();
+    let (mut stream, _) = peer.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    // Ends once the engine shut its write half down
+    let mut question = Vec::new();
+    stream.read_to_end(&mut question).unwrap();
+    assert_eq!(question, b"question");
+    assert_eq!(events.count(is_closed), 0);
+
+    stream.write_all(b"answer").unwrap();
+    drop(stream);
+    assert!(events.wait_for(1, is_closed));
+    assert!(events.wait_for(1, is_received));
+    let replies: Vec<_> = events
+        .all()
+        .into_iter()
+        .filter_map(|e| match e {
+            SocketEngineEvent::Data(DataEvent::Received {
+                data,
+                from,
+                connection,
+                ..
+            }) => Some((data, from, connection)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(replies, [(b"answer".to_vec(), target, None)]);
+}
+
+// Peer, payload and connection of the `Received` events
+fn received(events: &Events) -> Vec<(Endpoint, Endpoint, Vec<u8>, Option<ConnectionId>)> {
+    events
+        .all()
+        .into_iter()
+        .filter_map(|e| match e {
+            SocketEngineEvent::Data(DataEvent::Received {
+                from,
+                listener,
+                data,
+                connection,
+                ..
+            }) => Some((from, listener, data, connection)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn listeners_reply_on_the_accepted_connection() {
+    let listener = Engine::new();
+    let incoming = Events::attach(&listener);
+    let endpoint = free_endpoint("tcp");
+    listen(&listener, &endpoint);
+    let sender = Engine::new()
+        .with_close_after_send(false)
+        .with_read_replies(true);
+    let replies = Events::attach(&sender);
+
+    sender
+        .send_blocking(
+            endpoint.clone(),
+            b"question".to_vec(),
+            SendOptions::default(),
+        )
+        .unwrap();
+    assert!(incoming.wait_for(1, is_received));
+    let (peer, _, question, connection) = received(&incoming).remove(0);
+    assert_eq!(question, b"question");
+    let connection = connection.unwrap();
+
+    listener
+        .reply(connection, b"answer".to_vec(), "answer")
+        .unwrap();
+    assert!(incoming.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Data(DataEvent::Sent { token, to, .. })
+            if token == &MessageId::from("answer") && to == &peer
+    )));
+    assert!(replies.wait_for(1, is_received));
+    // Read by the sender on the local end of the connection the question took
+    assert_eq!(
+        received(&replies),
+        [(endpoint.clone(), peer.clone(), b"answer".to_vec(), None)]
+    );
+
+    // The pooled connection carries the next message, under the same id
+    sender
+        .send_blocking(endpoint.clone(), b"again".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(incoming.wait_for(2, is_received));
+    assert_eq!(
+        received(&incoming)[1],
+        (peer, endpoint, b"again".to_vec(), Some(connection))
+    );
+
+    // Replies fail once the connection is gone
+    drop(sender);
+    assert!(incoming.wait_for(1, is_closed));
+    let late = || listener.reply(connection, b"late".to_vec(), "late");
+    wait_until(|| late().is_err());
+    assert!(matches!(
+        late(),
+        Err(SocketEngineError::Send(e)) if e.kind() == std::io::ErrorKind::NotFound
+    ));
+}
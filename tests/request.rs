@@ -0,0 +1,34 @@
+mod common;
+
+use std::{net::UdpSocket, sync::Arc, time::Duration};
+
+use common::*;
+use socket_engine::prelude::*;
+
+#[test]
+fn dropped_request_detaches_its_observer() {
+    let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let target: Endpoint = format!("udp {}", peer.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let engine = Engine::new();
+    let captured = Arc::new(());
+    let held = captured.clone();
+    let matcher = move |_: &MessageId, _: &Endpoint, _: &[u8]| {
+        let _ = &held;
+        false
+    };
+
+    // Given up on by the caller long before its own timeout
+    let request = engine.request(
+        target,
+        b"ping".to_vec(),
+        SendOptions::default(),
+        matcher,
+        Duration::from_secs(60),
+    );
+    let given_up =
+        block_on(async { tokio::time::timeout(Duration::from_millis(50), request).await });
+    assert!(given_up.is_err());
+    wait_until(|| Arc::strong_count(&captured) == 1);
+}
@@ -0,0 +1,346 @@
+mod common;
+
+use std::{
+    io::Read,
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use common::*;
+use socket2::{Domain, Socket, Type};
+use socket_engine::{prelude::*, socket::GenericSocket};
+
+// Checks that a UDP listener of `engine` still receives promptly
+fn assert_listener_receives(engine: &Engine) {
+    let events = Events::attach(engine);
+    let endpoint = free_endpoint("udp");
+    listen(engine, &endpoint);
+    let address = endpoint.to_string();
+    let address = address.trim_start_matches("udp ");
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .send_to(b"ping", address)
+        .unwrap();
+    assert!(events.wait_for(1, is_received), "the listener was stalled");
+}
+
+#[test]
+fn peer_not_reading_does_not_stall_listeners() {
+    // A single worker, which a send blocking it would stall
+    let runtime = small_runtime(1);
+    let engine = Engine::new().with_runtime(runtime.handle().clone());
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    // Far more than the socket buffers hold, so the write waits on the peer
+    let _handle = engine
+        .send(
+            tcp_target(&peer),
+            vec![0; 64 * 1024 * 1024],
+            SendOptions::default(),
+        )
+        .unwrap();
+    let _accepted = peer.accept().unwrap();
+    assert_listener_receives(&engine);
+}
+
+#[test]
+fn pending_connect_does_not_stall_listeners() {
+    // A single worker, which a send blocking it would stall
+    let runtime = small_runtime(1);
+    let engine =
+        Engine::with_config(EngineConfig::default().connect_timeout(Duration::from_secs(30)))
+            .with_runtime(runtime.handle().clone());
+
+    // The connect of the send stays pending
+    let (_peer, _queued, target) = blackhole();
+    let _handle = engine
+        .send(target, b"hello".to_vec(), SendOptions::default())
+        .unwrap();
+    assert_listener_receives(&engine);
+}
+
+// A peer whose backlog is full: further connection requests are dropped, so
+// connects to it hang like those to an unreachable peer
+fn blackhole() -> (Socket, Vec<TcpStream>, Endpoint) {
+    let peer = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+    peer.bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into())
+        .unwrap();
+    peer.listen(0).unwrap();
+    let address = peer.local_addr().unwrap().as_socket().unwrap();
+    let mut queued = Vec::new();
+    for _ in 0..4 {
+        if let Ok(stream) = TcpStream::connect_timeout(&address, Duration::from_millis(200)) {
+            queued.push(stream);
+        }
+    }
+    let target = format!("tcp {}", address).parse().unwrap();
+    (peer, queued, target)
+}
+
+fn timed_out_token(e: &SocketEngineEvent) -> Option<MessageId> {
+    match e {
+        SocketEngineEvent::Error(ErrorEvent::ConnectionFailed {
+            reason: ConnectionFailureReason::Timeout,
+            token,
+            ..
+        }) => Some(token.clone()),
+        _ => None,
+    }
+}
+
+#[test]
+fn connect_to_unreachable_peer_times_out() {
+    let (_listener, _queued, target) = blackhole();
+    let engine =
+        Engine::with_config(EngineConfig::default().connect_timeout(Duration::from_millis(300)));
+    let events = Events::attach(&engine);
+
+    for (token, options, timeout) in [
+        ("engine", SendOptions::default(), 300),
+        (
+            "send",
+            SendOptions::default().connect_timeout(Duration::from_millis(100)),
+            100,
+        ),
+    ] {
+        let started = Instant::now();
+        let result = engine.send_blocking(target.clone(), b"x".to_vec(), options.token(token));
+        let elapsed = started.elapsed();
+        assert!(result.is_err());
+        assert!(elapsed >= Duration::from_millis(timeout), "{:?}", elapsed);
+        assert!(
+            elapsed < Duration::from_millis(timeout + 500),
+            "{:?}",
+            elapsed
+        );
+        assert!(events.wait_for(1, |e| timed_out_token(e) == Some(token.into())));
+    }
+}
+
+#[test]
+fn full_send_queue_fails_sends_and_blocks_blocking_ones() {
+    let engine = Arc::new(Engine::with_config(
+        EngineConfig::default()
+            .send_workers(1)
+            .send_queue_capacity(1)
+            .connect_timeout(Duration::from_secs(30)),
+    ));
+    let events = Events::attach(&engine);
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let target: Endpoint = format!("udp {}", receiver.local_addr().unwrap())
+        .parse()
+        .unwrap();
+
+    // The only worker waits on a connect, the next send fills the queue
+    let (_peer, _queued, stuck) = blackhole();
+    let stuck = engine
+        .send(stuck, b"stuck".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(events.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Data(DataEvent::Sending { .. })
+    )));
+    engine
+        .send(target.clone(), b"queued".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(matches!(
+        engine.send(target.clone(), b"refused".to_vec(), SendOptions::default()),
+        Err(SocketEngineError::QueueFull)
+    ));
+
+    let (done, finished) = mpsc::channel();
+    let blocking = {
+        let engine = engine.clone();
+        std::thread::spawn(move || {
+            let sent = engine.send_blocking(target, b"blocking".to_vec(), SendOptions::default());
+            done.send(()).unwrap();
+            sent
+        })
+    };
+    assert!(finished.recv_timeout(Duration::from_millis(300)).is_err());
+
+    // Freeing the worker drains the queue, making room for the blocked send
+    assert!(stuck.abort());
+    assert_eq!(blocking.join().unwrap().unwrap(), 8);
+    let mut buf = [0; 16];
+    for payload in [&b"queued"[..], b"blocking"] {
+        let (len, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], payload);
+    }
+}
+
+#[test]
+fn broadcast_sends_once_to_each_target() {
+    let listener = Engine::new();
+    let received = Events::attach(&listener);
+    let targets: Vec<Endpoint> = (0..3).map(|_| free_endpoint("udp")).collect();
+    for target in &targets {
+        listen(&listener, target);
+    }
+    let sender = Engine::new();
+    let sent = Events::attach(&sender);
+
+    let handles = sender.broadcast(
+        &targets,
+        b"to all".to_vec(),
+        SendOptions::default().token("broadcast"),
+    );
+    assert_eq!(handles.len(), targets.len());
+    for (handle, target) in handles.into_iter().zip(&targets) {
+        let handle = handle.unwrap();
+        assert_eq!(handle.to(), target);
+        assert_eq!(handle.token(), &MessageId::from("broadcast"));
+    }
+
+    assert!(sent.wait_for(targets.len(), is_sent));
+    assert!(received.wait_for(targets.len(), is_received));
+    for target in &targets {
+        let sent_to = sent.count(|e| {
+            matches!(e, SocketEngineEvent::Data(DataEvent::Sent { to, token, .. })
+                if to == target && token == &MessageId::from("broadcast"))
+        });
+        assert_eq!(sent_to, 1, "{}", target);
+        let delivered = received.count(|e| {
+            matches!(e, SocketEngineEvent::Data(DataEvent::Received { listener, data, .. })
+                if listener == target && data == b"to all")
+        });
+        assert_eq!(delivered, 1, "{}", target);
+    }
+}
+
+#[test]
+fn nonblocking_socket_still_connects_and_delivers() {
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = tcp_target(&peer);
+
+    // Sockets of the listener map are nonblocking, so are their clones, and
+    // their connect reports EINPROGRESS
+    let listening = GenericSocket::new(target.clone(), &EngineConfig::default()).unwrap();
+    listening.socket.set_nonblocking(true).unwrap();
+    let mut sock = listening.try_clone().unwrap();
+    let addr = peer.local_addr().unwrap().into();
+    block_on(async {
+        sock.connect_any(&[addr], Duration::from_secs(5))
+            .await
+            .unwrap();
+        sock.write_all(b"hello").await.unwrap();
+    });
+    drop((listening, sock));
+
+    let (mut stream, _) = peer.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"hello");
+}
+
+#[test]
+fn tcp_send_delivers_while_listening() {
+    let engine = Engine::new();
+    let endpoint = free_endpoint("tcp");
+    listen(&engine, &endpoint);
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    engine
+        .send_blocking(
+            tcp_target(&peer),
+            b"hello".to_vec(),
+            SendOptions::default().source(endpoint),
+        )
+        .unwrap();
+    let (mut stream, _) = peer.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"hello");
+}
+
+#[test]
+fn rate_limited_sends_wait_for_the_bucket_to_refill() {
+    let engine = Engine::new();
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let target: Endpoint = format!("udp {}", receiver.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    engine.set_rate_limit(
+        target.clone(),
+        RateLimit {
+            bytes_per_sec: 1000,
+            burst: 100,
+        },
+    );
+    let arrivals = |count: usize| {
+        let mut buf = [0; 256];
+        let started = Instant::now();
+        for _ in 0..count {
+            engine
+                .send(target.clone(), vec![0; 100], SendOptions::default())
+                .unwrap();
+        }
+        (0..count)
+            .map(|_| {
+                receiver.recv(&mut buf).unwrap();
+                started.elapsed()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The burst leaves at once, each further 100 bytes a tenth of a second later
+    let burst = arrivals(3);
+    assert!(burst[0] < Duration::from_millis(50), "{:?}", burst);
+    assert!(burst[2] >= Duration::from_millis(190), "{:?}", burst);
+    assert!(burst[2] < Duration::from_millis(500), "{:?}", burst);
+
+    // Once idle, the bucket is full again, but no fuller than the burst
+    std::thread::sleep(Duration::from_millis(400));
+    let refilled = arrivals(2);
+    assert!(refilled[0] < Duration::from_millis(50), "{:?}", refilled);
+    assert!(refilled[1] >= Duration::from_millis(90), "{:?}", refilled);
+
+    // Sends already waiting still wait, later ones no longer do
+    std::thread::sleep(Duration::from_millis(200));
+    engine.remove_rate_limit(&target);
+    let unlimited = arrivals(3);
+    assert!(unlimited[2] < Duration::from_millis(50), "{:?}", unlimited);
+}
+
+#[test]
+fn sends_are_listed_as_pending_until_their_outcome() {
+    let (_peer, _queued, target) = blackhole();
+    let engine =
+        Engine::with_config(EngineConfig::default().connect_timeout(Duration::from_millis(300)));
+    let token = MessageId::from("outstanding");
+    let before = Instant::now();
+    let handle = engine
+        .send(
+            target.clone(),
+            b"payload".to_vec(),
+            SendOptions::default().token(token.clone()),
+        )
+        .unwrap();
+
+    let pending = engine.pending_sends();
+    assert_eq!(pending.len(), 1);
+    let state = &pending[0];
+    assert_eq!(state.token, token);
+    assert_eq!(state.to, target);
+    assert_eq!(state.bytes, 7);
+    assert_eq!(state.attempts, 1);
+    assert!(state.started_at >= before && state.started_at <= Instant::now());
+    assert_eq!(engine.send_state(&token).unwrap().to, target);
+
+    assert!(matches!(
+        block_on(handle.outcome()),
+        SendOutcome::Failed { .. }
+    ));
+    wait_until(|| engine.send_state(&token).is_none());
+    assert!(engine.pending_sends().is_empty());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::*;
+use socket_engine::prelude::*;
+
+fn failed_token(e: &SocketEngineEvent) -> Option<MessageId> {
+    match e {
+        SocketEngineEvent::Error(ErrorEvent::SendFailed { token, .. }) => Some(token.clone()),
+        _ => None,
+    }
+}
+
+#[test]
+fn malformed_targets_fail_the_send_only() {
+    let mut targets = vec![
+        ("tcp", "300.1.1.1:99"),
+        ("udp", "127.0.0.1:99999"),
+        ("udp", "127.0.0.1"),
+    ];
+    if cfg!(feature = "bp") {
+        targets.extend([("bp", "ipn:1"), ("bp", "ipn:x.2"), ("bp", "ipn:1.2.3.4")]);
+    }
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+
+    for (n, (proto, address)) in targets.into_iter().enumerate() {
+        // Built as is, the parser would have refused most of them
+        let valid = if proto == "bp" {
+            "ipn:1.2"
+        } else {
+            "127.0.0.1:1"
+        };
+        let target = Endpoint {
+            endpoint: address.to_string(),
+            ..format!("{} {}", proto, valid).parse().unwrap()
+        };
+        let token = MessageId::from(format!("bad-{}", n));
+        let result = engine.send(
+            target,
+            b"x".to_vec(),
+            SendOptions::default().token(token.clone()),
+        );
+        assert!(result.is_err(), "{}", address);
+        assert!(
+            events.wait_for(1, |e| failed_token(e).as_ref() == Some(&token)),
+            "no SendFailed for {}",
+            address
+        );
+    }
+}
@@ -0,0 +1,207 @@
+#![cfg(feature = "serde")]
+
+use std::{io, sync::Arc, time::Duration};
+
+use socket_engine::prelude::*;
+
+fn endpoint() -> Endpoint {
+    "udp 127.0.0.1:8888".parse().unwrap()
+}
+
+// Serializing what was deserialized gives the same JSON and the same value
+fn assert_round_trip(event: SocketEngineEvent) {
+    let json = serde_json::to_value(&event).unwrap();
+    let back: SocketEngineEvent = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&back).unwrap(), json);
+    assert_eq!(format!("{:?}", back), format!("{:?}", event));
+}
+
+fn errors() -> Vec<SocketEngineError> {
+    vec![
+        SocketEngineError::Endpoint(EndpointParseError::InvalidAddress {
+            address: "x".into(),
+            reason: "y".into(),
+        }),
+        SocketEngineError::AddrParse("bad".into()),
+        SocketEngineError::UnsupportedScheme("sctp".into()),
+        SocketEngineError::Socket(Arc::new(io::Error::from_raw_os_error(libc::EMFILE))),
+        SocketEngineError::SocketMismatch("type".into()),
+        SocketEngineError::Bind(Arc::new(io::Error::from_raw_os_error(libc::EADDRINUSE))),
+        SocketEngineError::AlreadyInUse(endpoint()),
+        SocketEngineError::NoBpIdentity,
+        SocketEngineError::Misuse(MisuseKind::TokenReused),
+        SocketEngineError::Connect(ConnectionFailureReason::Timeout),
+        SocketEngineError::QueueFull,
+        SocketEngineError::TooManyConnections {
+            remote: endpoint(),
+            max: 3,
+        },
+        SocketEngineError::Cancelled,
+        SocketEngineError::NoResponse,
+        SocketEngineError::Send(Arc::new(io::Error::other("no route"))),
+        SocketEngineError::Receive(Arc::new(io::Error::from_raw_os_error(libc::ECONNRESET))),
+        SocketEngineError::Frame {
+            peer: endpoint(),
+            error: FrameError::PayloadExceedsMax { len: 9, max: 8 },
+        },
+        SocketEngineError::Shutdown(Arc::new(io::Error::from_raw_os_error(libc::ENOTCONN))),
+    ]
+}
+
+#[test]
+fn data_events_round_trip() {
+    let token = MessageId::from("token");
+    let connection: ConnectionId = serde_json::from_str("7").unwrap();
+    for event in [
+        DataEvent::Received {
+            data: vec![0, 1, 2, 255],
+            from: endpoint(),
+            listener: endpoint(),
+            connection: Some(connection),
+            wire_bytes: 8,
+            local: false,
+        },
+        DataEvent::Sending {
+            token: token.clone(),
+            to: endpoint(),
+            bytes: 4,
+            local: true,
+        },
+        DataEvent::Sent {
+            token: token.clone(),
+            to: endpoint(),
+            bytes_sent: 4,
+            wire_bytes: 8,
+            from: Some(endpoint()),
+            local: false,
+        },
+        DataEvent::Cancelled {
+            token: token.clone(),
+            to: endpoint(),
+        },
+        DataEvent::Retrying {
+            token,
+            to: endpoint(),
+            attempt: 2,
+            next_in: Duration::from_millis(150),
+        },
+        DataEvent::EchoReply {
+            to: endpoint(),
+            seq: 3,
+            rtt: Some(Duration::from_micros(420)),
+        },
+        DataEvent::EchoReply {
+            to: endpoint(),
+            seq: 4,
+            rtt: None,
+        },
+        DataEvent::Echoed {
+            from: endpoint(),
+            listener: endpoint(),
+            bytes: 64,
+        },
+    ] {
+        assert_round_trip(SocketEngineEvent::Data(event));
+    }
+}
+
+#[test]
+fn connection_events_round_trip() {
+    for event in [
+        ConnectionEvent::ListenerStarted {
+            endpoint: endpoint(),
+        },
+        ConnectionEvent::ListenerStopped {
+            endpoint: endpoint(),
+            reason: ListenerStopReason::LimitReached,
+            messages: 10,
+        },
+        ConnectionEvent::Established { remote: endpoint() },
+        ConnectionEvent::Accepted {
+            remote: endpoint(),
+            local: endpoint(),
+        },
+        ConnectionEvent::Closed { remote: None },
+        ConnectionEvent::PeerDiscovered {
+            endpoint: endpoint(),
+        },
+        ConnectionEvent::Paired {
+            alias: PAIR_ALIAS.into(),
+            endpoint: endpoint(),
+        },
+        ConnectionEvent::PeerStateChanged {
+            endpoint: endpoint(),
+            old: PeerState::Degraded,
+            new: PeerState::Unreachable,
+            cause: PeerStateCause::EchoLost,
+        },
+    ] {
+        assert_round_trip(SocketEngineEvent::Connection(event));
+    }
+}
+
+#[test]
+fn error_events_round_trip() {
+    for error in errors() {
+        for event in [
+            ErrorEvent::SendFailed {
+                endpoint: endpoint(),
+                token: "t".into(),
+                error: error.clone(),
+            },
+            ErrorEvent::ReceiveFailed {
+                endpoint: endpoint(),
+                error: error.clone(),
+            },
+            ErrorEvent::SocketError {
+                endpoint: endpoint(),
+                error,
+            },
+        ] {
+            assert_round_trip(SocketEngineEvent::Error(event));
+        }
+    }
+    for event in [
+        ErrorEvent::ConnectionFailed {
+            endpoint: endpoint(),
+            reason: ConnectionFailureReason::Refused,
+            token: "t".into(),
+        },
+        ErrorEvent::Misuse {
+            kind: MisuseKind::UnknownSource,
+            detail: "d".into(),
+        },
+        ErrorEvent::MisuseWarning {
+            kind: MisuseKind::DuplicateObserver,
+            detail: "d".into(),
+        },
+    ] {
+        assert_round_trip(SocketEngineEvent::Error(event));
+    }
+}
+
+#[test]
+fn payloads_are_base64_strings() {
+    for (data, encoded) in [
+        (&b""[..], ""),
+        (b"f", "Zg=="),
+        (b"fo", "Zm8="),
+        (b"foo", "Zm9v"),
+        (b"\xff\x00\xfe", "/wD+"),
+    ] {
+        let event = SocketEngineEvent::Data(DataEvent::Received {
+            data: data.to_vec(),
+            from: endpoint(),
+            listener: endpoint(),
+            connection: None,
+            wire_bytes: 0,
+            local: false,
+        });
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["Data"]["Received"]["data"], encoded);
+        assert_round_trip(event);
+    }
+    let bad = r#"{"Data":{"Received":{"data":"@@","from":{"proto":"Udp","endpoint":"a:1"},
+        "listener":{"proto":"Udp","endpoint":"a:1"},"connection":null,"wire_bytes":0,"local":false}}}"#;
+    assert!(serde_json::from_str::<SocketEngineEvent>(bad).is_err());
+}
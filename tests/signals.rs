@@ -0,0 +1,98 @@
+mod common;
+
+use std::{
+    io,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use common::*;
+use socket_engine::{prelude::*, socket::retry_on_eintr};
+
+extern "C" fn ignore(_: libc::c_int) {}
+
+// Sends SIGUSR1 to every thread of the process until `stop` is set. The no-op
+// handler is installed without `SA_RESTART`, so blocking calls fail with EINTR
+fn bombard(stop: Arc<AtomicBool>) -> thread::JoinHandle<u64> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert_eq!(
+            libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()),
+            0
+        );
+    }
+    thread::spawn(move || {
+        let pid = unsafe { libc::getpid() };
+        let mut sent = 0;
+        while !stop.load(Ordering::Relaxed) {
+            for task in std::fs::read_dir("/proc/self/task").unwrap().flatten() {
+                let tid: libc::c_int = task.file_name().to_str().unwrap().parse().unwrap();
+                if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, libc::SIGUSR1) } == 0 {
+                    sent += 1;
+                }
+            }
+            thread::sleep(Duration::from_micros(200));
+        }
+        sent
+    })
+}
+
+#[test]
+fn interrupted_calls_are_retried() {
+    let mut interruptions = 2;
+    let result = retry_on_eintr(|| {
+        if interruptions == 0 {
+            return Ok(7);
+        }
+        interruptions -= 1;
+        Err(io::Error::from(io::ErrorKind::Interrupted))
+    });
+    assert_eq!(result.unwrap(), 7);
+    let failed: io::Result<()> = retry_on_eintr(|| Err(io::ErrorKind::WouldBlock.into()));
+    assert_eq!(failed.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+}
+
+#[test]
+fn signals_do_not_disturb_transfers() {
+    const MESSAGES: usize = 200;
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let tcp = free_endpoint("tcp");
+    listen(&engine, &tcp);
+    let udp = free_endpoint("udp");
+    listen(&engine, &udp);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let bombardment = bombard(stop.clone());
+    let address = udp.to_string();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for n in 0..MESSAGES {
+        let payload = n.to_be_bytes().to_vec();
+        engine
+            .send(
+                tcp.clone(),
+                payload.clone(),
+                SendOptions::default().token(n.to_string()),
+            )
+            .unwrap();
+        sender
+            .send_to(&payload, address.trim_start_matches("udp "))
+            .unwrap();
+        // Leaves the UDP receive buffer room, and the signals time to land
+        thread::sleep(Duration::from_micros(500));
+    }
+    let delivered = events.wait_for(2 * MESSAGES, is_received);
+    stop.store(true, Ordering::Relaxed);
+    assert!(bombardment.join().unwrap() > 0);
+
+    assert!(delivered, "{} received", events.count(is_received));
+    let spurious: Vec<_> = events.all().into_iter().filter(is_error).collect();
+    assert!(spurious.is_empty(), "{:?}", spurious);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::*;
+use socket_engine::{framing::FRAME_HEADER_LEN, prelude::*, stats::MAX_TRACKED_ENDPOINTS};
+
+#[test]
+fn wire_bytes_include_frame_headers() {
+    let receiver = Engine::new().with_length_prefix_framing(true);
+    let events = Events::attach(&receiver);
+    let target = free_endpoint("tcp");
+    listen(&receiver, &target);
+    let sender = Engine::new().with_length_prefix_framing(true);
+
+    for size in [10, 20, 30] {
+        sender
+            .send_blocking(target.clone(), vec![7; size], SendOptions::default())
+            .unwrap();
+    }
+    assert!(events.wait_for(3, is_received));
+
+    let wire = 60 + 3 * FRAME_HEADER_LEN as u64;
+    let sent = sender.stats(&target);
+    assert_eq!((sent.messages_sent, sent.bytes_sent), (3, 60));
+    assert_eq!(sent.wire_bytes_sent, wire);
+    assert_eq!(sent.send_overhead(), 20.0);
+    assert_eq!(sender.total_stats().wire_bytes_sent, wire);
+
+    let received = receiver.total_stats();
+    assert_eq!(
+        (received.messages_received, received.bytes_received),
+        (3, 60)
+    );
+    assert_eq!(received.wire_bytes_received, wire);
+    assert_eq!(received.receive_overhead(), 20.0);
+}
+
+#[test]
+fn datagrams_have_no_overhead() {
+    let receiver = Engine::new();
+    let events = Events::attach(&receiver);
+    let target = free_endpoint("udp");
+    listen(&receiver, &target);
+    let sender = Engine::new();
+
+    sender
+        .send_blocking(target.clone(), vec![7; 100], SendOptions::default())
+        .unwrap();
+    assert!(events.wait_for(1, is_received));
+
+    let sent = sender.stats(&target);
+    assert_eq!((sent.bytes_sent, sent.wire_bytes_sent), (100, 100));
+    assert_eq!(sent.send_overhead(), 0.0);
+    let received = receiver.total_stats();
+    assert_eq!(
+        (received.bytes_received, received.wire_bytes_received),
+        (100, 100)
+    );
+}
+
+#[test]
+fn endpoints_with_the_oldest_traffic_are_forgotten() {
+    let engine = Engine::new();
+    let target =
+        |port: usize| -> Endpoint { format!("udp 127.0.0.1:{}", 20_000 + port).parse().unwrap() };
+    let send = |endpoint: Endpoint| {
+        let _ = engine.send_blocking(endpoint, b"x".to_vec(), SendOptions::default());
+    };
+    send(target(0));
+    send(target(1));
+    // Still in use, unlike the second one
+    send(target(0));
+    for port in 2..=MAX_TRACKED_ENDPOINTS {
+        send(target(port));
+    }
+    let sends = MAX_TRACKED_ENDPOINTS as u64 + 2;
+    wait_until(|| {
+        let total = engine.total_stats();
+        total.messages_sent + total.send_failures == sends
+    });
+
+    let kept = engine.stats(&target(0));
+    assert_eq!(kept.messages_sent + kept.send_failures, 2);
+    assert_eq!(engine.stats(&target(1)), EndpointStats::default());
+    let last = engine.stats(&target(MAX_TRACKED_ENDPOINTS));
+    assert_eq!(last.messages_sent + last.send_failures, 1);
+}
@@ -0,0 +1,60 @@
+//! The thread budget applies to the whole process, so this binary holds a
+//! single test.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::net::TcpListener;
+
+use common::*;
+use socket_engine::{
+    prelude::*,
+    runtime::{set_thread_budget, thread_budget, ThreadBudget, LISTENER_THREAD_NAME},
+};
+
+#[test]
+fn loopback_exchange_stays_within_budget() {
+    let budget = ThreadBudget {
+        workers: 2,
+        blocking: 1,
+        listeners_share_workers: true,
+    };
+    set_thread_budget(budget).unwrap();
+    assert_eq!(thread_budget(), budget);
+    assert!(matches!(
+        set_thread_budget(budget),
+        Err(SocketEngineError::AlreadyConfigured(_))
+    ));
+    let no_workers = ThreadBudget {
+        workers: 0,
+        ..budget
+    };
+    assert!(matches!(
+        set_thread_budget(no_workers),
+        Err(SocketEngineError::InvalidConfig(_))
+    ));
+    let baseline = threads().len();
+
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let udp = free_endpoint("udp");
+    listen(&engine, &udp);
+    let tcp = free_endpoint("tcp");
+    listen(&engine, &tcp);
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    for target in [udp, tcp, tcp_target(&peer)] {
+        engine
+            .send(
+                target,
+                b"hello".to_vec(),
+                SendOptions::default().token("hello"),
+            )
+            .unwrap();
+    }
+    assert!(events.wait_for(2, is_received));
+    assert!(events.wait_for(3, is_sent));
+
+    let threads = threads();
+    assert!(threads.len() <= baseline + 3, "{:?}", threads);
+    assert!(!threads.iter().any(|name| name == LISTENER_THREAD_NAME));
+}
@@ -0,0 +1,40 @@
+//! The thread budget applies to the whole process, so this binary holds a
+//! single test.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::*;
+use socket_engine::{
+    prelude::*,
+    runtime::{set_thread_budget, thread_budget, ThreadBudget, LISTENER_THREAD_NAME},
+};
+
+#[test]
+fn listeners_run_on_their_own_thread() {
+    let budget = ThreadBudget {
+        workers: 1,
+        blocking: 1,
+        listeners_share_workers: false,
+    };
+    set_thread_budget(budget).unwrap();
+    assert_eq!(thread_budget(), budget);
+    let baseline = threads().len();
+
+    let engine = Engine::new();
+    let events = Events::attach(&engine);
+    let udp = free_endpoint("udp");
+    listen(&engine, &udp);
+    let tcp = free_endpoint("tcp");
+    listen(&engine, &tcp);
+    for target in [udp, tcp] {
+        engine
+            .send_blocking(target, b"hello".to_vec(), SendOptions::default())
+            .unwrap();
+    }
+    assert!(events.wait_for(2, is_received));
+
+    let threads = threads();
+    assert!(threads.len() <= baseline + 3, "{:?}", threads);
+    assert!(threads.iter().any(|name| name == LISTENER_THREAD_NAME));
+}
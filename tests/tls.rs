@@ -0,0 +1,190 @@
+#![cfg(feature = "tls")]
+
+mod common;
+
+use common::*;
+use rcgen::CertifiedKey;
+use socket_engine::prelude::*;
+
+// A self-signed certificate for the loopback address, trusted as its own root
+fn certificate() -> CertifiedKey {
+    rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap()
+}
+
+fn identity(cert: &CertifiedKey) -> TlsConfig {
+    TlsConfig::default()
+        .identity(
+            cert.cert.pem().as_bytes(),
+            cert.key_pair.serialize_pem().as_bytes(),
+        )
+        .unwrap()
+}
+
+fn trusting(cert: &CertifiedKey) -> TlsConfig {
+    TlsConfig::default()
+        .roots(cert.cert.pem().as_bytes())
+        .unwrap()
+}
+
+fn tls_engine(config: TlsConfig) -> Engine {
+    Engine::with_config(EngineConfig::default().tls(config))
+}
+
+fn handshake_failed(e: &SocketEngineEvent) -> Option<MessageId> {
+    match e {
+        SocketEngineEvent::Error(ErrorEvent::ConnectionFailed {
+            reason: ConnectionFailureReason::Handshake,
+            token,
+            ..
+        }) => Some(token.clone()),
+        _ => None,
+    }
+}
+
+#[test]
+fn messages_cross_a_tls_connection() {
+    let cert = certificate();
+    let listener = tls_engine(identity(&cert));
+    let received = Events::attach(&listener);
+    let endpoint = free_endpoint("tcp");
+    listen(&listener, &endpoint);
+
+    let sender = tls_engine(trusting(&cert));
+    let sent = Events::attach(&sender);
+    let handle = sender
+        .send(endpoint, b"secret".to_vec(), SendOptions::default())
+        .unwrap();
+
+    assert!(matches!(
+        block_on(handle.outcome()),
+        SendOutcome::Sent { bytes: 6 }
+    ));
+    assert!(received.wait_for(1, is_received));
+    assert_eq!(received.received(), vec![b"secret".to_vec()]);
+    assert!(sent.wait_for(1, is_established));
+    assert!(sent.wait_for(1, is_closed));
+}
+
+#[test]
+fn untrusted_listeners_fail_the_handshake_with_the_token() {
+    let listener = tls_engine(identity(&certificate()));
+    let endpoint = free_endpoint("tcp");
+    listen(&listener, &endpoint);
+
+    let sender = tls_engine(trusting(&certificate()));
+    let events = Events::attach(&sender);
+    let token = MessageId::from("untrusted");
+    let handle = sender
+        .send(
+            endpoint,
+            b"secret".to_vec(),
+            SendOptions::default().token(token.clone()),
+        )
+        .unwrap();
+
+    assert!(matches!(
+        block_on(handle.outcome()),
+        SendOutcome::Failed {
+            error: SocketEngineError::Connect(ConnectionFailureReason::Handshake)
+        }
+    ));
+    assert!(events.wait_for(1, |e| handshake_failed(e).as_ref() == Some(&token)));
+    assert_eq!(events.count(is_established), 0);
+    assert_eq!(events.count(is_sent), 0);
+}
+
+#[test]
+fn plaintext_peers_of_tls_listeners_are_dropped() {
+    let listener = tls_engine(identity(&certificate()));
+    let events = Events::attach(&listener);
+    let endpoint = free_endpoint("tcp");
+    listen(&listener, &endpoint);
+
+    let sender = Engine::new();
+    sender
+        .send(endpoint, b"in the clear".to_vec(), SendOptions::default())
+        .unwrap();
+
+    assert!(events.wait_for(1, |e| matches!(
+        e,
+        SocketEngineEvent::Error(ErrorEvent::ReceiveFailed { .. })
+    )));
+    assert!(events.wait_for(1, is_closed));
+    assert_eq!(events.count(is_received), 0);
+}
+
+#[test]
+fn reloading_swaps_the_identity_of_running_listeners() {
+    let old = certificate();
+    let new = certificate();
+    let listener = tls_engine(identity(&old));
+    let received = Events::attach(&listener);
+    let endpoint = free_endpoint("tcp");
+    listen(&listener, &endpoint);
+    let sender = tls_engine(trusting(&new));
+
+    let before = sender
+        .send(endpoint.clone(), b"before".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(matches!(
+        block_on(before.outcome()),
+        SendOutcome::Failed { .. }
+    ));
+
+    listener.reload_tls(identity(&new));
+    let after = sender
+        .send(endpoint, b"after".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(matches!(
+        block_on(after.outcome()),
+        SendOutcome::Sent { .. }
+    ));
+    assert!(received.wait_for(1, is_received));
+    assert_eq!(received.received(), vec![b"after".to_vec()]);
+}
+
+#[test]
+fn replies_come_back_over_the_tls_connection() {
+    let cert = certificate();
+    let listener = tls_engine(identity(&cert));
+    let received = Events::attach(&listener);
+    let endpoint = free_endpoint("tcp");
+    listen(&listener, &endpoint);
+    let sender = tls_engine(trusting(&cert)).with_read_replies(true);
+    let replies = Events::attach(&sender);
+
+    sender
+        .send(endpoint.clone(), b"ping".to_vec(), SendOptions::default())
+        .unwrap();
+    assert!(received.wait_for(1, is_received));
+    let connection = received
+        .all()
+        .into_iter()
+        .find_map(|e| match e {
+            SocketEngineEvent::Data(DataEvent::Received { connection, .. }) => connection,
+            _ => None,
+        })
+        .unwrap();
+    listener
+        .reply(connection, b"pong".to_vec(), "pong")
+        .unwrap();
+
+    assert!(replies.wait_for(1, is_received));
+    let from = replies.all().into_iter().find_map(|e| match e {
+        SocketEngineEvent::Data(DataEvent::Received { data, from, .. }) => Some((data, from)),
+        _ => None,
+    });
+    assert_eq!(from, Some((b"pong".to_vec(), endpoint)));
+}
+
+#[test]
+fn unreadable_certificates_are_invalid_config() {
+    assert!(matches!(
+        TlsConfig::default().identity(b"not a certificate", b"not a key"),
+        Err(SocketEngineError::InvalidConfig(_))
+    ));
+    assert!(matches!(
+        TlsConfig::default().roots_file("/nonexistent/ca.pem"),
+        Err(SocketEngineError::InvalidConfig(_))
+    ));
+}
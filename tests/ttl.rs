@@ -0,0 +1,100 @@
+mod common;
+
+use std::net::UdpSocket;
+
+use common::*;
+use socket_engine::{prelude::*, socket::GenericSocket};
+
+#[test]
+fn set_ttl_applies_to_the_socket_family() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sock = GenericSocket::new(free_endpoint("udp"), &EngineConfig::default()).unwrap();
+    sock.set_ttl(1).unwrap();
+    assert_eq!(sock.socket.ttl().unwrap(), 1);
+
+    // Loopback traffic is delivered whatever its TTL
+    sock.socket
+        .send_to(b"hello", &receiver.local_addr().unwrap().into())
+        .unwrap();
+    let mut buf = [0; 5];
+    receiver.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+
+    if UdpSocket::bind("[::1]:0").is_err() {
+        return;
+    }
+    let sock =
+        GenericSocket::new("udp [::1]:9".parse().unwrap(), &EngineConfig::default()).unwrap();
+    sock.set_ttl(1).unwrap();
+    assert_eq!(sock.socket.unicast_hops_v6().unwrap(), 1);
+}
+
+// TTL of the next datagram on `socket`, read from its IP_TTL control message
+#[cfg(target_os = "linux")]
+fn recv_ttl(socket: &UdpSocket) -> i32 {
+    use std::os::fd::AsRawFd;
+
+    let mut data = [0u8; 64];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+    let mut control = [0u8; 64];
+    // SAFETY: the message header points to buffers living until the end of the
+    // function, and control messages are only read within `msg_controllen`
+    unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control.len() as _;
+        assert!(libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) >= 0);
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_TTL {
+                return std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    panic!("no TTL control message");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn engine_sends_datagrams_with_its_ttl() {
+    use std::os::fd::AsRawFd;
+
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    let on: libc::c_int = 1;
+    // SAFETY: `on` outlives the call and its size is passed along
+    let set = unsafe {
+        libc::setsockopt(
+            receiver.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_RECVTTL,
+            (&on as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    assert_eq!(set, 0);
+    let target: Endpoint = format!("udp {}", receiver.local_addr().unwrap())
+        .parse()
+        .unwrap();
+
+    // Unset, the system default applies
+    let default_ttl = std::fs::read_to_string("/proc/sys/net/ipv4/ip_default_ttl")
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    for (engine, ttl) in [(Engine::new().with_ttl(1), 1), (Engine::new(), default_ttl)] {
+        engine
+            .send_blocking(target.clone(), b"hello".to_vec(), SendOptions::default())
+            .unwrap();
+        assert_eq!(recv_ttl(&receiver), ttl);
+    }
+}